@@ -7,7 +7,8 @@ fn main() {
         .is_ok();
     let in_ci = var_os("CI").is_some();
     // Windows on github has docker but only runs windows images
-    let allowed_in_ci = !in_ci || cfg!(linux);
+    let allowed_in_ci = !in_ci || cfg!(target_os = "linux");
+    println!("cargo:rustc-check-cfg=cfg(docker)");
     if has_docker && allowed_in_ci {
         println!("cargo:rustc-cfg=docker");
     }
@@ -0,0 +1,56 @@
+//! GitHub Actions workflow commands, which show errors and warnings on the run's summary
+
+use std::env;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+/// Whether we run inside a GitHub Actions workflow
+pub fn github_actions() -> bool {
+    env::var("GITHUB_ACTIONS").as_deref() == Ok("true")
+}
+
+/// The runner reads workflow commands from stderr too, which keeps stdout free for patches
+pub fn annotate(level: Level, title: Option<&str>, message: &str) {
+    eprintln!("{}", annotation(level, title, message));
+}
+
+fn annotation(level: Level, title: Option<&str>, message: &str) -> String {
+    let command = match level {
+        Level::Error => "error",
+        Level::Warning => "warning",
+    };
+    let parameters = title
+        .map(|title| format!(" title={}", escape_property(title)))
+        .unwrap_or_default();
+    format!("::{}{}::{}", command, parameters, escape_data(message))
+}
+
+fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(property: &str) -> String {
+    escape_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{annotation, Level};
+
+    #[test]
+    fn test_annotation() {
+        assert_eq!(
+            annotation(Level::Error, Some("repo: a, b"), "failed\n100%"),
+            "::error title=repo%3A a%2C b::failed%0A100%25"
+        );
+        assert_eq!(annotation(Level::Warning, None, "slow"), "::warning::slow");
+    }
+}
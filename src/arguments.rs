@@ -1,10 +1,338 @@
-use camino::Utf8PathBuf;
+use std::{str::FromStr, time::Duration};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::{eyre, Report};
 use structopt::StructOpt;
 
+use crate::annotations;
+
 #[derive(Debug, StructOpt)]
 pub struct Arguments {
-    #[structopt(long, short("f"))]
-    pub plan_file: Utf8PathBuf,
-    #[structopt(long, short)]
+    #[structopt(subcommand)]
+    pub command: Command,
+    /// Fetch the repositories from the provider instead of using the cached list
+    #[structopt(long, short, global = true)]
     pub skip_repository_cache: bool,
+    /// Global config with defaults for all plans, defaults to the there-i-fixed-it.toml on the
+    /// user's config folder
+    #[structopt(long, global = true)]
+    pub config: Option<Utf8PathBuf>,
+    /// Template variable like `VERSION=2`, can be repeated, overriding the ones of the plan's env
+    #[structopt(long = "plan-var", global = true, number_of_values = 1)]
+    pub plan_vars: Vec<PlanVariable>,
+    /// Where the files a plan read from stdin refers to are, defaults to the current folder
+    #[structopt(long, global = true)]
+    pub plan_dir: Option<Utf8PathBuf>,
+    /// Show more logs, can be repeated (-vv). RUST_LOG takes precedence when set
+    #[structopt(long, short, global = true, parse(from_occurrences))]
+    pub verbose: u8,
+    /// Only show warnings and errors
+    #[structopt(long, short, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Report failures as GitHub Actions annotations, enabled by default inside GitHub Actions
+    #[structopt(long, global = true)]
+    pub github_annotations: bool,
+}
+
+// Parsed once, so the size of run's arguments does not matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Apply a plan to all the repositories it targets
+    Run(RunArguments),
+    /// Print the repositories a plan targets, without cloning or changing them
+    List(ListArguments),
+    /// Take the plan's draft pull requests out of draft once their checks pass
+    MarkReady(MarkReadyArguments),
+    /// Print the branch, commit message and pull request a plan would create on a repository
+    RenderPr(RenderPrArguments),
+    /// Check that git, SSH, the provider's token and the repositories folder are set up
+    Doctor(DoctorArguments),
+    /// Delete the branches of the plan's prefix whose pull requests were merged or closed
+    PruneBranches(PruneBranchesArguments),
+}
+
+#[derive(Debug, StructOpt)]
+pub struct RunArguments {
+    #[structopt(flatten)]
+    pub plan_file: PlanFile,
+    /// Process repositories even if the plan and their default branch did not change since the
+    /// last successful run
+    #[structopt(long)]
+    pub force: bool,
+    /// Show the changes of each repository and ask before committing them, one repository at a
+    /// time
+    #[structopt(long, short)]
+    pub interactive: bool,
+    /// Save a JSON report of the run on this path
+    #[structopt(long)]
+    pub report: Option<Utf8PathBuf>,
+    /// How many repositories are processed at the same time, overrides the plan's concurrency
+    #[structopt(long, short)]
+    pub jobs: Option<usize>,
+    /// How many git commands run at the same time across all repositories, unbounded by default
+    #[structopt(long)]
+    pub git_jobs: Option<usize>,
+    /// Wait for other runs processing the same repositories instead of skipping them
+    #[structopt(long)]
+    pub wait_for_locks: bool,
+    /// File with a repository per line, processed instead of listing all repositories, overrides
+    /// the plan's repository_names
+    #[structopt(long)]
+    pub repos_file: Option<Utf8PathBuf>,
+    /// Only process the repositories that failed on this report of a previous run of the plan
+    #[structopt(long, conflicts_with = "repos-file")]
+    pub retry_from: Option<Utf8PathBuf>,
+    /// Where repositories are cloned, overrides the plan's repositories_folder
+    #[structopt(long)]
+    pub repositories_folder: Option<Utf8PathBuf>,
+    /// Show the changes without committing, pushing or opening pull requests
+    #[structopt(long)]
+    pub dry_run: bool,
+    /// How dry run changes are shown, text (per repository, the default) or patch (a single patch
+    /// for all repositories)
+    #[structopt(long, requires = "dry-run")]
+    pub format: Option<DiffFormat>,
+    /// Unchanged lines shown around each change of dry run diffs, defaults to 3
+    #[structopt(long)]
+    pub context: Option<usize>,
+    /// Save the original of each changed file as `<file>.orig` on the clone, without committing it
+    #[structopt(long)]
+    pub keep_backups: bool,
+    /// Bytes kept of the output of each git or plan command, from its beginning and its end,
+    /// defaults to 1 MiB
+    #[structopt(long)]
+    pub max_output: Option<usize>,
+    /// Save the dry run changes on this file instead of showing them
+    #[structopt(long, requires = "dry-run")]
+    pub output: Option<Utf8PathBuf>,
+    /// SSH command used by git, like "ssh -i ~/.ssh/deploy-key -o IdentitiesOnly=yes", overrides the
+    /// plan's ssh_command
+    #[structopt(long)]
+    pub ssh_command: Option<String>,
+    /// Stop starting repositories after this long, like 30m or 1h30m, finishing the ones in
+    /// progress and exiting with code 75 unless some failed
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    pub max_runtime: Option<Duration>,
+    /// Add the current branch, git status and last commits of the repositories that fail to the
+    /// report
+    #[structopt(long)]
+    pub diagnose_failures: bool,
+    /// Push the branches without opening pull requests, reporting where they were pushed
+    #[structopt(long)]
+    pub no_pr: bool,
+    /// Remove the untracked and ignored files of reused clones, like the ones a failed run left,
+    /// before processing them
+    #[structopt(long)]
+    pub clean: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ListArguments {
+    #[structopt(flatten)]
+    pub plan_file: PlanFile,
+    /// One of text (name and clone url) or json
+    #[structopt(long, default_value = "text")]
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct MarkReadyArguments {
+    #[structopt(flatten)]
+    pub plan_file: PlanFile,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct RenderPrArguments {
+    #[structopt(flatten)]
+    pub plan_file: PlanFile,
+    /// Name of the repository whose values are used on the templates
+    #[structopt(long)]
+    pub repo: String,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct DoctorArguments {
+    /// Plan whose providers and repositories folder are checked, only git and SSH are checked
+    /// without it
+    pub plan_file: Option<Utf8PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct PruneBranchesArguments {
+    #[structopt(flatten)]
+    pub plan_file: PlanFile,
+    /// Delete the branches, otherwise they are only listed
+    #[structopt(long)]
+    pub yes: bool,
+}
+
+/// The plan of a command, also taken by the `-f/--plan-file` of older versions
+#[derive(Debug, StructOpt)]
+pub struct PlanFile {
+    /// `-` reads the plan from stdin
+    #[structopt(name = "plan-file", required_unless = "plan-file-option")]
+    positional: Option<Utf8PathBuf>,
+    #[structopt(
+        name = "plan-file-option",
+        short = "f",
+        long = "plan-file",
+        hidden = true,
+        conflicts_with = "plan-file"
+    )]
+    option: Option<Utf8PathBuf>,
+}
+
+impl PlanFile {
+    pub fn path(&self) -> &Utf8Path {
+        // One of them is required
+        self.positional
+            .as_deref()
+            .or(self.option.as_deref())
+            .unwrap_or_else(|| Utf8Path::new("-"))
+    }
+}
+
+/// A `NAME=VALUE` pair
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanVariable {
+    pub name: String,
+    pub value: String,
+}
+
+impl FromStr for PlanVariable {
+    type Err = Report;
+
+    fn from_str(variable: &str) -> Result<Self, Self::Err> {
+        match variable.split_once('=') {
+            Some((name, value)) if !name.is_empty() => Ok(Self {
+                name: name.to_owned(),
+                value: value.to_owned(),
+            }),
+            _ => Err(eyre!("invalid variable {:?}, use NAME=VALUE", variable)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Report;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(eyre!("unknown format {:?}, use text or json", format)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffFormat {
+    Text,
+    Patch,
+}
+
+impl FromStr for DiffFormat {
+    type Err = Report;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(Self::Text),
+            "patch" => Ok(Self::Patch),
+            _ => Err(eyre!("unknown format {:?}, use text or patch", format)),
+        }
+    }
+}
+
+/// Numbers followed by `h`, `m` or `s`, like `1h30m`, or only seconds
+fn parse_duration(duration: &str) -> Result<Duration, Report> {
+    if let Ok(seconds) = duration.parse() {
+        return Ok(Duration::from_secs(seconds));
+    }
+    let invalid = || {
+        eyre!(
+            "invalid duration {:?}, use something like 30m or 1h30m",
+            duration
+        )
+    };
+    let mut total = 0;
+    let mut number = String::new();
+    for character in duration.chars() {
+        let unit = match character {
+            '0'..='9' => {
+                number.push(character);
+                continue;
+            }
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid()),
+        };
+        let value: u64 = number.parse().map_err(|_| invalid())?;
+        total += value * unit;
+        number.clear();
+    }
+    if duration.is_empty() || !number.is_empty() {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(total))
+}
+
+impl Arguments {
+    pub fn github_annotations(&self) -> bool {
+        self.github_annotations || annotations::github_actions()
+    }
+
+    pub fn log_level(&self) -> &'static str {
+        match (self.quiet, self.verbose) {
+            (true, _) => "warn",
+            (false, 0) => "info",
+            (false, 1) => "debug",
+            (false, _) => "trace",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use structopt::StructOpt;
+
+    use super::{parse_duration, Arguments, Command};
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(1800));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("2m10s").unwrap(), Duration::from_secs(130));
+        for invalid in ["", "m", "30x", "1h30"] {
+            assert!(parse_duration(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_plan_file_option() {
+        for arguments in [
+            &["fix", "list", "plan.toml"][..],
+            &["fix", "list", "-f", "plan.toml"],
+            &["fix", "list", "--plan-file", "plan.toml"],
+        ] {
+            let arguments = Arguments::from_iter_safe(arguments).unwrap();
+            let list = match arguments.command {
+                Command::List(list) => list,
+                command => panic!("unexpected command {:?}", command),
+            };
+            assert_eq!(list.plan_file.path(), "plan.toml");
+        }
+        assert!(Arguments::from_iter_safe(["fix", "list"]).is_err());
+        assert!(Arguments::from_iter_safe(["fix", "list", "a.toml", "-f", "b.toml"]).is_err());
+    }
 }
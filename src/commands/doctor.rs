@@ -0,0 +1,250 @@
+use std::{
+    fmt::{self, Display},
+    process::Stdio,
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use directories::BaseDirs;
+use tokio::{fs, process::Command};
+
+use crate::{
+    arguments::{Arguments, DoctorArguments},
+    constants::CACHE_DIR,
+};
+
+use super::parse_plan;
+
+/// One line of the checklist, with how to fix it when it fails
+struct Check {
+    name: &'static str,
+    result: Result<String>,
+    hint: &'static str,
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.result {
+            Ok(details) => write!(f, "[ok]   {}: {}", self.name, details),
+            Err(err) => write!(
+                f,
+                "[fail] {}: {:#}\n       hint: {}",
+                self.name, err, self.hint
+            ),
+        }
+    }
+}
+
+pub async fn doctor(arguments: &Arguments, doctor_arguments: &DoctorArguments) -> Result<()> {
+    let mut checks = vec![
+        Check {
+            name: "git",
+            result: git_version().await,
+            hint: "install git and make sure it is on the PATH",
+        },
+        Check {
+            name: "ssh",
+            result: ssh_keys().await,
+            hint: "create a key with ssh-keygen, add it to ssh-agent and to the provider",
+        },
+    ];
+    if let Some(plan_file) = &doctor_arguments.plan_file {
+        check_plan(plan_file, arguments, &mut checks).await;
+    } else {
+        checks.push(repositories_folder_check(&CACHE_DIR).await);
+    }
+
+    for check in &checks {
+        println!("{}", check);
+    }
+    match checks.iter().filter(|check| check.result.is_err()).count() {
+        0 => Ok(()),
+        failures => Err(eyre!("{} checks failed", failures)),
+    }
+}
+
+/// The plan's checks only go as far as the previous ones passed
+async fn check_plan(plan_file: &Utf8Path, arguments: &Arguments, checks: &mut Vec<Check>) {
+    let mut plan = match parse_plan(plan_file, arguments).await {
+        Ok(plan) => plan,
+        Err(err) => {
+            checks.push(Check {
+                name: "plan",
+                result: Err(err),
+                hint: "fix the plan, the README has an example of all its keys",
+            });
+            return;
+        }
+    };
+    checks.push(Check {
+        name: "plan",
+        result: Ok(plan_file.to_string()),
+        hint: "",
+    });
+    checks.push(
+        repositories_folder_check(plan.repositories_folder.as_ref().unwrap_or(&CACHE_DIR)).await,
+    );
+
+    let token = plan.load_token().await;
+    let token_loaded = token.is_ok();
+    checks.push(Check {
+        name: "token",
+        result: token.map(|_| "loaded".to_owned()),
+        hint: "set the provider's token, token_env, token_file, token_command or token_keyring",
+    });
+    if !token_loaded {
+        return;
+    }
+    for provider in plan.providers() {
+        checks.push(Check {
+            name: "provider",
+            result: provider
+                .authenticated_user()
+                .await
+                .map(|user| format!("authenticated as {}", user)),
+            hint: "check that the token is still valid and the provider is reachable",
+        });
+    }
+}
+
+async fn repositories_folder_check(folder: &Utf8Path) -> Check {
+    Check {
+        name: "repositories folder",
+        result: writable(folder).await.map(|_| folder.to_string()),
+        hint: "point the plan's repositories_folder to a folder you can write to",
+    }
+}
+
+async fn git_version() -> Result<String> {
+    let output = Command::new("git")
+        .arg("--version")
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .wrap_err("failed to run git")?;
+    if !output.status.success() {
+        return Err(eyre!("git --version exited with {}", output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// Keys loaded on ssh-agent, or the key files on `~/.ssh` when there is no agent
+async fn ssh_keys() -> Result<String> {
+    if std::env::var_os("SSH_AUTH_SOCK").is_some() {
+        let output = Command::new("ssh-add")
+            .arg("-l")
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .wrap_err("failed to run ssh-add")?;
+        if output.status.success() {
+            let keys = String::from_utf8_lossy(&output.stdout).lines().count();
+            return Ok(format!("{} keys on ssh-agent", keys));
+        }
+    }
+    let ssh_folder = BaseDirs::new()
+        .ok_or_else(|| eyre!("failed to find the home folder"))?
+        .home_dir()
+        .join(".ssh");
+    let ssh_folder = Utf8PathBuf::from_path_buf(ssh_folder)
+        .map_err(|path| eyre!("{:?} is not valid UTF-8", path))?;
+    let keys = private_keys(&ssh_folder).await?;
+    if keys.is_empty() {
+        return Err(eyre!("no keys on ssh-agent or {}", ssh_folder));
+    }
+    Ok(format!("{} on {}", keys.join(", "), ssh_folder))
+}
+
+/// Names of the `id_*` files of `folder` that have a public key next to them
+async fn private_keys(folder: &Utf8Path) -> Result<Vec<String>> {
+    let mut keys = vec![];
+    let mut entries = match fs::read_dir(folder).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(keys),
+        Err(err) => return Err(eyre!(err).wrap_err(format!("failed to read {}", folder))),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with("id_")
+            && !name.ends_with(".pub")
+            && folder.join(format!("{}.pub", name)).exists()
+        {
+            keys.push(name);
+        }
+    }
+    keys.sort();
+    Ok(keys)
+}
+
+/// Creates `folder` when missing and writes a file on it
+async fn writable(folder: &Utf8Path) -> Result<()> {
+    fs::create_dir_all(folder)
+        .await
+        .wrap_err_with(|| format!("failed to create {}", folder))?;
+    let file = folder.join(".doctor");
+    fs::write(&file, "")
+        .await
+        .wrap_err_with(|| format!("failed to write to {}", folder))?;
+    fs::remove_file(&file).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use camino::Utf8Path;
+    use color_eyre::eyre::eyre;
+    use tempdir::TempDir;
+
+    use super::{private_keys, writable, Check};
+
+    #[test]
+    fn test_check_display() {
+        let passed = Check {
+            name: "git",
+            result: Ok("git version 2.40.0".to_owned()),
+            hint: "install git",
+        };
+        assert_eq!(passed.to_string(), "[ok]   git: git version 2.40.0");
+        let failed = Check {
+            name: "git",
+            result: Err(eyre!("not found")),
+            hint: "install git",
+        };
+        assert_eq!(
+            failed.to_string(),
+            "[fail] git: not found\n       hint: install git"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_private_keys() {
+        let temp = TempDir::new("doctor").unwrap();
+        let folder = Utf8Path::from_path(temp.path()).unwrap();
+        for name in ["id_rsa", "id_rsa.pub", "id_ed25519", "known_hosts"] {
+            fs::write(folder.join(name), "").unwrap();
+        }
+        assert_eq!(private_keys(folder).await.unwrap(), vec!["id_rsa"]);
+        assert!(private_keys(&folder.join("missing"))
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_writable() {
+        let temp = TempDir::new("doctor").unwrap();
+        let folder = Utf8Path::from_path(temp.path()).unwrap();
+        writable(&folder.join("repositories")).await.unwrap();
+        assert!(folder.join("repositories").is_dir());
+
+        fs::write(folder.join("file"), "").unwrap();
+        assert!(writable(&folder.join("file").join("repositories"))
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,20 @@
+use color_eyre::Result;
+
+use crate::arguments::{Arguments, ListArguments, OutputFormat};
+
+use super::{load_plan, target_repositories};
+
+pub async fn list(arguments: &Arguments, list_arguments: &ListArguments) -> Result<()> {
+    let plan = load_plan(list_arguments.plan_file.path(), arguments).await?;
+    let repositories = target_repositories(&plan, !arguments.skip_repository_cache).await?;
+
+    match list_arguments.format {
+        OutputFormat::Text => {
+            for repository in &repositories {
+                println!("{}\t{}", repository.name, repository.ssh_url);
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&repositories)?),
+    }
+    Ok(())
+}
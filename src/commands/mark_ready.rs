@@ -0,0 +1,57 @@
+use color_eyre::{eyre::eyre, Result};
+use tracing::{error, info};
+
+use crate::{
+    arguments::{Arguments, MarkReadyArguments},
+    providers::{ChecksState, Provider},
+};
+
+use super::{load_plan, target_repositories};
+
+pub async fn mark_ready(
+    arguments: &Arguments,
+    mark_ready_arguments: &MarkReadyArguments,
+) -> Result<()> {
+    let plan = load_plan(mark_ready_arguments.plan_file.path(), arguments).await?;
+    let repositories = target_repositories(&plan, !arguments.skip_repository_cache).await?;
+
+    let mut failures = 0;
+    for repository in &repositories {
+        let head = plan.pull_request_head(repository, &plan.branch_name(repository));
+        match mark_repository_ready(plan.provider(repository), &repository.name, &head).await {
+            Ok(status) => info!("{}: {}", repository.name, status),
+            Err(err) => {
+                error!("{}: {:?}", repository.name, err);
+                failures += 1;
+            }
+        }
+    }
+
+    match failures {
+        0 => Ok(()),
+        failures => Err(eyre!("{} repositories failed", failures)),
+    }
+}
+
+/// What happened to the repository's draft pull request
+async fn mark_repository_ready(
+    provider: &dyn Provider,
+    repository_name: &str,
+    branch_name: &str,
+) -> Result<&'static str> {
+    let pull_request = match provider.draft_pr(repository_name, branch_name).await? {
+        Some(pull_request) => pull_request,
+        None => return Ok("no draft pull request"),
+    };
+    let state = provider
+        .checks_state(repository_name, &pull_request.head_commit)
+        .await?;
+    Ok(match state {
+        ChecksState::Pending => "checks pending",
+        ChecksState::Failure => "checks failed",
+        ChecksState::Success => {
+            provider.mark_ready(repository_name, &pull_request).await?;
+            "marked ready"
+        }
+    })
+}
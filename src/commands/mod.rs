@@ -0,0 +1,148 @@
+mod doctor;
+mod list;
+mod mark_ready;
+mod prune_branches;
+mod render_pr;
+mod run;
+
+use camino::Utf8Path;
+use color_eyre::{eyre::Context, Result};
+use tokio::{fs, io};
+use tracing::info;
+
+use crate::{
+    arguments::Arguments,
+    config::GlobalConfig,
+    plan::{plan_from_file, plan_from_reader, Plan},
+    report::SkipReason,
+    Repository,
+};
+
+pub use self::doctor::doctor;
+pub use self::list::list;
+pub use self::mark_ready::mark_ready;
+pub use self::prune_branches::prune_branches;
+pub use self::render_pr::render_pr;
+pub use self::run::{run, MaxRuntimeExceeded, MAX_RUNTIME_EXIT_CODE};
+
+/// Parses the plan, or stdin when it is `-`, with the global config and loads its provider's
+/// credentials
+async fn load_plan(plan_file: &Utf8Path, arguments: &Arguments) -> Result<Plan> {
+    let mut plan = parse_plan(plan_file, arguments).await?;
+    plan.load_token().await?;
+    Ok(plan)
+}
+
+async fn parse_plan(plan_file: &Utf8Path, arguments: &Arguments) -> Result<Plan> {
+    info!("parsing plan");
+    let mut config = GlobalConfig::load(arguments.config.as_deref()).await?;
+    let variables = arguments
+        .plan_vars
+        .iter()
+        .map(|variable| (variable.name.clone(), variable.value.clone()))
+        .collect::<Vec<_>>();
+    config.override_variables(&variables);
+    if plan_file == "-" {
+        let directory = arguments
+            .plan_dir
+            .as_deref()
+            .unwrap_or_else(|| Utf8Path::new(""));
+        plan_from_reader(io::stdin(), directory, &config).await
+    } else {
+        plan_from_file(plan_file, &config).await
+    }
+}
+
+/// The provider's repositories that pass all of the plan's filters
+async fn target_repositories(plan: &Plan, use_cache: bool) -> Result<Vec<Repository>> {
+    Ok(filter_repositories(plan, use_cache).await?.0)
+}
+
+/// The provider's repositories that pass all of the plan's filters, and the names of the others
+/// with the filter that left them out
+async fn filter_repositories(
+    plan: &Plan,
+    use_cache: bool,
+) -> Result<(Vec<Repository>, Vec<(String, SkipReason)>)> {
+    let all_repositories = plan.list_repositories(use_cache).await?;
+    let mut repositories = vec![];
+    let mut skipped = vec![];
+    // Languages are checked last, as matching any of them requests the provider
+    for repository in all_repositories {
+        let reason = if plan.repository_excluded(&repository.name) {
+            Some(SkipReason::Excluded)
+        } else if !plan.repository_allowed(&repository.name) {
+            Some(SkipReason::NotAllowed)
+        } else if !plan.language_allowed(&repository).await? {
+            Some(SkipReason::Language)
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => skipped.push((repository.name, reason)),
+            None => repositories.push(repository),
+        }
+    }
+    Ok((repositories, skipped))
+}
+
+/// One repository per line, ignoring empty lines and `#` comments
+async fn read_repository_names(path: &Utf8Path) -> Result<Vec<String>> {
+    let contents = fs::read_to_string(path)
+        .await
+        .wrap_err_with(|| format!("failed to read repositories from {}", path))?;
+    Ok(parse_repository_names(&contents))
+}
+
+fn parse_repository_names(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config::GlobalConfig, plan::plan_from_str, report::SkipReason};
+
+    use super::{filter_repositories, parse_repository_names};
+
+    #[tokio::test]
+    async fn test_filter_repositories() {
+        let plan = r#"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            repositories = ["*"]
+            deny_repositories = ["*-rs"]
+            repository_exclude = ["legacy-thing"]
+            repository_names = ["working-repo", "legacy-thing", "parser-rs"]
+
+            [provider]
+            name = "test"
+
+            [[files]]
+            processors = []
+        "#;
+        let plan = plan_from_str(plan, &GlobalConfig::default()).unwrap();
+        let (repositories, skipped) = filter_repositories(&plan, false).await.unwrap();
+        assert_eq!(repositories.len(), 1);
+        assert_eq!(repositories[0].name, "working-repo");
+        assert_eq!(
+            skipped,
+            vec![
+                ("legacy-thing".to_owned(), SkipReason::Excluded),
+                ("parser-rs".to_owned(), SkipReason::NotAllowed),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_repository_names() {
+        assert_eq!(
+            parse_repository_names("# targeted fix\napi\n\n  my-org/web  \n"),
+            vec!["api", "my-org/web"]
+        );
+    }
+}
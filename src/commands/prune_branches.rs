@@ -0,0 +1,149 @@
+use color_eyre::{eyre::eyre, Result};
+use tracing::{error, info};
+
+use crate::{
+    arguments::{Arguments, PruneBranchesArguments},
+    providers::{PrState, Provider},
+    Repository,
+};
+
+use super::{load_plan, target_repositories};
+
+pub async fn prune_branches(
+    arguments: &Arguments,
+    prune_branches_arguments: &PruneBranchesArguments,
+) -> Result<()> {
+    let plan = load_plan(prune_branches_arguments.plan_file.path(), arguments).await?;
+    let repositories = target_repositories(&plan, !arguments.skip_repository_cache).await?;
+
+    let mut failures = 0;
+    let mut found = 0;
+    for repository in &repositories {
+        let branch_name = plan.branch_name(repository);
+        // Without a prefix only the plan's own branch is automated
+        let filter = match plan.branch_prefix() {
+            Some(prefix) if !prefix.is_empty() => BranchFilter::Prefix(prefix),
+            _ => BranchFilter::Exact(&branch_name),
+        };
+        let provider = plan.provider(repository);
+        let head = |branch: &str| plan.pull_request_head(repository, branch);
+        let branches = match stale_branches(provider, repository, filter, head).await {
+            Ok(branches) => branches,
+            Err(err) => {
+                error!("{}: {:?}", repository.name, err);
+                failures += 1;
+                continue;
+            }
+        };
+        for branch in branches {
+            found += 1;
+            if !prune_branches_arguments.yes {
+                info!("{}: would delete {}", repository.name, branch);
+                continue;
+            }
+            match provider.delete_branch(&repository.name, &branch).await {
+                Ok(()) => info!("{}: deleted {}", repository.name, branch),
+                Err(err) => {
+                    error!("{}: {:?}", repository.name, err);
+                    failures += 1;
+                }
+            }
+        }
+    }
+    if found > 0 && !prune_branches_arguments.yes {
+        info!(
+            "{} stale branches found, run again with --yes to delete them",
+            found
+        );
+    }
+
+    match failures {
+        0 => Ok(()),
+        failures => Err(eyre!("{} branches or repositories failed", failures)),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BranchFilter<'a> {
+    Prefix(&'a str),
+    Exact(&'a str),
+}
+
+/// Automated branches of the repository whose latest pull request was merged or closed. Branches
+/// without pull requests could still be in use and the default branch is never stale. `head`
+/// gives the head of the pull requests of a branch
+async fn stale_branches(
+    provider: &dyn Provider,
+    repository: &Repository,
+    filter: BranchFilter<'_>,
+    head: impl Fn(&str) -> String,
+) -> Result<Vec<String>> {
+    let prefix = match filter {
+        BranchFilter::Prefix(prefix) | BranchFilter::Exact(prefix) => prefix,
+    };
+    let mut output = vec![];
+    for branch in provider.branches(&repository.name, prefix).await? {
+        if branch == repository.default_branch() {
+            continue;
+        }
+        if matches!(filter, BranchFilter::Exact(name) if name != branch) {
+            continue;
+        }
+        match provider.pr_state(&repository.name, &head(&branch)).await? {
+            Some(PrState::Merged) | Some(PrState::Closed) => output.push(branch),
+            Some(PrState::Open) | None => {}
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::providers::{tests::TestProvider, Provider};
+
+    use super::{stale_branches, BranchFilter};
+
+    #[tokio::test]
+    async fn test_stale_branches() {
+        let provider = TestProvider;
+        let repository = provider.list_repositories(false).await.unwrap().remove(0);
+
+        let branches = stale_branches(
+            &provider,
+            &repository,
+            BranchFilter::Exact("merged-branch"),
+            str::to_owned,
+        )
+        .await
+        .unwrap();
+        assert_eq!(branches, vec!["merged-branch"]);
+
+        // Only merged-branch has a merged pull request
+        let branches = stale_branches(
+            &provider,
+            &repository,
+            BranchFilter::Prefix(""),
+            str::to_owned,
+        )
+        .await
+        .unwrap();
+        assert_eq!(branches, vec!["merged-branch"]);
+
+        let branches = stale_branches(
+            &provider,
+            &repository,
+            BranchFilter::Prefix("open"),
+            str::to_owned,
+        )
+        .await
+        .unwrap();
+        assert!(branches.is_empty());
+
+        // The pull requests of the branches are looked up by their head
+        let head = |branch: &str| branch.replace("open", "merged");
+        let branches = stale_branches(&provider, &repository, BranchFilter::Prefix("open"), head)
+            .await
+            .unwrap();
+        assert_eq!(branches, vec!["open-branch"]);
+    }
+}
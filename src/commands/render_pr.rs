@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use color_eyre::{eyre::eyre, Help, Result};
+
+use crate::{
+    arguments::{Arguments, RenderPrArguments},
+    constants::CACHE_DIR,
+    plan::{executor::RenderedChanges, Action, PlanExecutor},
+};
+
+use super::load_plan;
+
+pub async fn render_pr(
+    arguments: &Arguments,
+    render_pr_arguments: &RenderPrArguments,
+) -> Result<()> {
+    let plan = load_plan(render_pr_arguments.plan_file.path(), arguments).await?;
+    let repository = plan
+        .list_repositories(!arguments.skip_repository_cache)
+        .await?
+        .into_iter()
+        .find(|repository| repository.name == render_pr_arguments.repo)
+        .ok_or_else(|| {
+            eyre!("repository {} not found", render_pr_arguments.repo)
+                .suggestion("use --skip-repository-cache if it was created recently")
+        })?;
+    let plan = Arc::new(plan);
+    let executor = PlanExecutor::new(
+        plan.clone(),
+        Default::default(),
+        repository,
+        plan.repositories_folder.as_deref().unwrap_or(&CACHE_DIR),
+    );
+
+    let changes = executor.render_changes();
+    if plan.action == Action::Issue {
+        print_issue(&changes);
+        return Ok(());
+    }
+    println!("Branch: {}", changes.branch_name);
+    if changes.pull_request_head != changes.branch_name {
+        println!("Pull request head: {}", changes.pull_request_head);
+    }
+    println!("Commit message:\n{}\n", changes.commit_message.trim_end());
+    println!("Pull request title: {}", changes.pull_request_title);
+    if let Some(body) = &changes.pull_request_body {
+        println!("Pull request body:\n{}", body);
+    }
+    if plan.action == Action::PullRequestAndIssue {
+        print_issue(&changes);
+    }
+    Ok(())
+}
+
+fn print_issue(changes: &RenderedChanges) {
+    println!("Issue title: {}", changes.issue_title);
+    if let Some(body) = &changes.issue_body {
+        println!("Issue body:\n{}", body);
+    }
+}
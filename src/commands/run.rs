@@ -0,0 +1,377 @@
+use std::{
+    fmt::{self, Display},
+    io::{self, IsTerminal},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use color_eyre::{
+    eyre::{eyre, Context},
+    Help, Result,
+};
+use tokio::{fs, signal, sync::Semaphore, task};
+use tracing::{error, info, warn};
+
+use crate::{
+    annotations::{self, Level},
+    arguments::{Arguments, DiffFormat, RunArguments},
+    constants::CACHE_DIR,
+    plan::{interactive::Confirmation, process, ExecutorError, ExecutorOptions, PlanExecutor},
+    report::{OperationDiff, Outcome, Report, SavedReport, SkipReason},
+};
+
+use super::{filter_repositories, load_plan, read_repository_names};
+
+const DEFAULT_CONCURRENCY: usize = 5;
+/// Exit code when `--max-runtime` stops the run early, which schedulers can tell from failures.
+/// It is `EX_TEMPFAIL`, as running again continues with the repositories left
+pub const MAX_RUNTIME_EXIT_CODE: i32 = 75;
+
+/// The run stopped starting repositories because `--max-runtime` was exceeded
+#[derive(Debug)]
+pub struct MaxRuntimeExceeded {
+    max_runtime: Duration,
+    not_processed: usize,
+}
+
+impl Display for MaxRuntimeExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "max runtime of {:?} exceeded, {} repositories were not processed",
+            self.max_runtime, self.not_processed
+        )
+    }
+}
+
+impl std::error::Error for MaxRuntimeExceeded {}
+
+pub async fn run(arguments: &Arguments, run_arguments: &RunArguments) -> Result<()> {
+    let started = Instant::now();
+    if run_arguments.interactive && run_arguments.plan_file.path() == "-" {
+        return Err(eyre!("--interactive needs stdin for the prompts")
+            .suggestion("save the plan to a file instead of reading it from stdin"));
+    }
+    let mut plan = load_plan(run_arguments.plan_file.path(), arguments).await?;
+    if let Some(path) = &run_arguments.repos_file {
+        plan.repository_names = read_repository_names(path).await?;
+    }
+    if let Some(path) = &run_arguments.retry_from {
+        let failed = SavedReport::load(path)
+            .await?
+            .failed_repositories(&plan.identity())?;
+        if failed.is_empty() {
+            info!("no repositories failed on {}", path);
+            return Ok(());
+        }
+        info!("retrying {} repositories", failed.len());
+        plan.repository_names = failed;
+    }
+    plan.check_ssh_command(run_arguments.ssh_command.as_deref())?;
+    let plan = Arc::new(plan);
+    let (repositories, skipped) =
+        filter_repositories(&plan, !arguments.skip_repository_cache).await?;
+
+    if run_arguments.git_jobs == Some(0) {
+        return Err(eyre!("--git-jobs must be at least 1"));
+    }
+    let options = Arc::new(ExecutorOptions {
+        force: run_arguments.force,
+        confirmation: run_arguments.interactive.then(Confirmation::default),
+        dry_run: run_arguments.dry_run,
+        ssh_command: run_arguments.ssh_command.clone(),
+        git_jobs: run_arguments
+            .git_jobs
+            .map(|jobs| Arc::new(Semaphore::new(jobs))),
+        wait_for_locks: run_arguments.wait_for_locks,
+        context_lines: run_arguments.context,
+        keep_backups: run_arguments.keep_backups,
+        max_output: run_arguments.max_output,
+        no_pr: run_arguments.no_pr,
+        clean: run_arguments.clean,
+    });
+    let repositories_folder = run_arguments
+        .repositories_folder
+        .as_ref()
+        .or_else(|| plan.repositories_folder.as_ref())
+        .unwrap_or(&CACHE_DIR);
+    let executors = repositories
+        .into_iter()
+        .map(|repository| {
+            PlanExecutor::new(
+                plan.clone(),
+                options.clone(),
+                repository,
+                repositories_folder,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut futures = vec![];
+
+    // Prompts are easier to follow without other repositories logging in between
+    let concurrency = if run_arguments.interactive {
+        1
+    } else {
+        run_arguments
+            .jobs
+            .or(plan.concurrency)
+            .unwrap_or(DEFAULT_CONCURRENCY)
+    };
+    let interrupted = Arc::new(AtomicBool::new(false));
+    task::spawn(handle_interrupts(interrupted.clone()));
+    let total = executors.len();
+    let s = Arc::new(Semaphore::new(concurrency));
+    let mut executors = executors.into_iter();
+    let mut not_started = vec![];
+    while let Some(executor) = executors.next() {
+        let permit = s.clone().acquire_owned().await?;
+        if interrupted.load(Ordering::SeqCst) {
+            break;
+        }
+        let out_of_time = run_arguments
+            .max_runtime
+            .is_some_and(|max_runtime| started.elapsed() >= max_runtime);
+        if out_of_time {
+            warn!("max runtime exceeded, finishing the repositories in progress");
+            not_started.push(executor.to_string());
+            not_started.extend(executors.map(|executor| executor.to_string()));
+            break;
+        }
+        let diagnose_failures = run_arguments.diagnose_failures;
+        futures.push(task::spawn(async move {
+            let _ = permit;
+            let result = executor
+                .process()
+                .await
+                .map_err(ExecutorError::into_report)
+                .wrap_err(format!("failed to process repository {}", executor));
+            let diagnostics = match &result {
+                Err(_) if diagnose_failures => executor.diagnostics().await,
+                _ => None,
+            };
+            (
+                executor.to_string(),
+                result,
+                executor.timings(),
+                diagnostics,
+            )
+        }))
+    }
+
+    let annotate = arguments.github_annotations();
+    let format = run_arguments.format.unwrap_or(DiffFormat::Text);
+    let show_diffs = format == DiffFormat::Text && run_arguments.output.is_none();
+    let colors = use_colors();
+    let grouped = plan.operations_count() > 1;
+    let mut report = Report::for_plan(plan.identity());
+    for (name, reason) in &skipped {
+        report.skip(name, *reason);
+    }
+    for name in &not_started {
+        report.skip(name, SkipReason::MaxRuntime);
+    }
+    let mut patch = String::new();
+    for future in futures {
+        let (name, result, timings, diagnostics) = future.await?;
+        match &result {
+            Err(err) => {
+                error!("{:?}", err);
+                if annotate {
+                    annotations::annotate(
+                        Level::Error,
+                        Some(&format!("Failed to process {}", name)),
+                        &format!("{:#}", err),
+                    );
+                }
+            }
+            Ok(Outcome {
+                diff: Some(diff),
+                operation_diffs,
+                commit,
+                ..
+            }) => {
+                if show_diffs {
+                    println!("{}:\n{}", name, commit.as_deref().unwrap_or_default());
+                    print!("{}", group_diffs(diff, operation_diffs, grouped, colors));
+                }
+                patch.push_str(diff);
+            }
+            Ok(_) => {}
+        }
+        report.add(&name, &result, timings);
+        if let Some(diagnostics) = diagnostics {
+            report.add_diagnostics(&name, diagnostics);
+        }
+    }
+
+    info!("process done");
+    if let Some(path) = &run_arguments.output {
+        fs::write(path, &patch)
+            .await
+            .wrap_err_with(|| format!("failed to save changes to {}", path))?;
+    }
+    if format == DiffFormat::Patch && run_arguments.output.is_none() {
+        print!("{}", patch);
+        // The report goes to stderr to keep stdout a valid patch
+        eprintln!("{}", report);
+    } else {
+        println!("{}", report);
+    }
+    if let Some(path) = &run_arguments.report {
+        report.save(path).await?;
+    }
+
+    if interrupted.load(Ordering::SeqCst) {
+        if annotate {
+            annotations::annotate(Level::Warning, None, "the run was interrupted");
+        }
+        return Err(eyre!(
+            "interrupted, {} repositories were not processed",
+            total - report.len()
+        ));
+    }
+    if let (Some(max_runtime), false) = (run_arguments.max_runtime, not_started.is_empty()) {
+        if annotate {
+            annotations::annotate(Level::Warning, None, "the run exceeded its max runtime");
+        }
+        let exceeded = MaxRuntimeExceeded {
+            max_runtime,
+            not_processed: not_started.len(),
+        };
+        // Failures keep their exit code, so schedulers do not take them for a run to continue
+        return match report.failures() {
+            0 => Err(eyre!(exceeded)),
+            failures => Err(eyre!("{} repositories failed, {}", failures, exceeded)),
+        };
+    }
+    match report.failures() {
+        0 => Ok(()),
+        failures => Err(eyre!("{} repositories failed", failures)),
+    }
+}
+
+/// The changes under a header for each operation, or all of them when the plan has only one
+fn group_diffs(
+    diff: &str,
+    operation_diffs: &[OperationDiff],
+    grouped: bool,
+    colors: bool,
+) -> String {
+    if !grouped || operation_diffs.is_empty() {
+        return format!("{}\n", colorize(diff, colors));
+    }
+    let mut output = String::new();
+    for operation_diff in operation_diffs {
+        let header = format!("=== {} ===", operation_diff.operation);
+        if colors {
+            output.push_str(&format!("\x1b[1;33m{}\x1b[0m\n", header));
+        } else {
+            output.push_str(&format!("{}\n", header));
+        }
+        output.push_str(&colorize(&operation_diff.diff, colors));
+        output.push('\n');
+    }
+    output
+}
+
+/// Colors for terminals, unless `NO_COLOR` is set, see https://no-color.org
+fn use_colors() -> bool {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    !no_color && io::stdout().is_terminal()
+}
+
+/// Green added lines, red removed lines and cyan hunk headers
+fn colorize(diff: &str, colors: bool) -> String {
+    if !colors {
+        return diff.to_owned();
+    }
+    diff.split_inclusive('\n')
+        .map(|line| {
+            let (content, ending) = match line.strip_suffix('\n') {
+                Some(content) => (content, "\n"),
+                None => (line, ""),
+            };
+            let color = if content.starts_with("+++") || content.starts_with("---") {
+                "1"
+            } else if content.starts_with('+') {
+                "32"
+            } else if content.starts_with('-') {
+                "31"
+            } else if content.starts_with("@@") {
+                "36"
+            } else if content.starts_with("diff ") {
+                "1"
+            } else {
+                return line.to_owned();
+            };
+            format!("\x1b[{}m{}\x1b[0m{}", color, content, ending)
+        })
+        .collect()
+}
+
+/// The first Ctrl-C lets the repositories in progress finish, the second one exits right away
+async fn handle_interrupts(interrupted: Arc<AtomicBool>) {
+    if signal::ctrl_c().await.is_err() {
+        return;
+    }
+    warn!("interrupted, finishing the repositories in progress, press Ctrl-C again to exit now");
+    interrupted.store(true, Ordering::SeqCst);
+    if signal::ctrl_c().await.is_ok() {
+        // Git and the commands have their own process groups, which do not get the Ctrl-C
+        process::kill_running_groups();
+        std::process::exit(130);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::report::OperationDiff;
+
+    use super::{colorize, group_diffs};
+
+    const DIFF: &str =
+        "diff --git a/file b/file\n--- a/file\n+++ b/file\n@@ -1 +1 @@\n-old\n+new\n";
+
+    #[test]
+    fn test_colorize() {
+        assert_eq!(colorize(DIFF, false), DIFF);
+        assert_eq!(
+            colorize(DIFF, true),
+            "\x1b[1mdiff --git a/file b/file\x1b[0m\n\x1b[1m--- a/file\x1b[0m\n\x1b[1m+++ b/file\x1b[0m\n\
+             \x1b[36m@@ -1 +1 @@\x1b[0m\n\x1b[31m-old\x1b[0m\n\x1b[32m+new\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn test_group_diffs() {
+        let operation_diffs = vec![
+            OperationDiff {
+                operation: "operation 1: *.py".to_owned(),
+                diff: DIFF.to_owned(),
+            },
+            OperationDiff {
+                operation: "commands".to_owned(),
+                diff: DIFF.to_owned(),
+            },
+        ];
+        assert_eq!(
+            group_diffs(DIFF, &operation_diffs, true, false),
+            format!(
+                "=== operation 1: *.py ===\n{}\n=== commands ===\n{}\n",
+                DIFF, DIFF
+            )
+        );
+        assert_eq!(
+            group_diffs(DIFF, &operation_diffs[..1], true, false),
+            format!("=== operation 1: *.py ===\n{}\n", DIFF)
+        );
+        assert_eq!(
+            group_diffs(DIFF, &operation_diffs, false, false),
+            format!("{}\n", DIFF)
+        );
+    }
+}
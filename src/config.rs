@@ -0,0 +1,197 @@
+use camino::Utf8Path;
+use color_eyre::{eyre::Context, Result};
+use tokio::fs;
+use toml::{value::Table, Value};
+use tracing::debug;
+
+use crate::constants::CONFIG_FILE;
+
+/// Defaults shared by all plans, with the same keys as a plan.
+///
+/// The precedence order is: command line > plan > global config > built-in default.
+#[derive(Debug, Default)]
+pub struct GlobalConfig {
+    defaults: Table,
+    /// Variables of the command line, which win over the plan's `[env]`
+    variables: Table,
+}
+
+impl GlobalConfig {
+    /// Loads `path` or, when missing, the default config file if it exists
+    pub async fn load(path: Option<&Utf8Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path,
+            None => match CONFIG_FILE.as_ref() {
+                Some(path) if path.exists() => path.as_path(),
+                _ => return Ok(Self::default()),
+            },
+        };
+        debug!("loading global config from {}", path);
+        let contents = fs::read_to_string(path)
+            .await
+            .wrap_err_with(|| format!("failed to read global config from {:?}", path))?;
+        Self::parse(&contents).wrap_err_with(|| format!("failed to parse {:?}", path))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        Ok(Self {
+            defaults: toml::from_str(contents)?,
+            variables: Table::new(),
+        })
+    }
+
+    /// Template variables that replace the ones on the plan's `[env]`
+    pub fn override_variables(&mut self, variables: &[(String, String)]) {
+        for (name, value) in variables {
+            self.variables
+                .insert(name.clone(), Value::String(value.clone()));
+        }
+    }
+
+    /// Fills whatever `plan` does not define with the values of this config
+    pub fn apply_to(&self, plan: Table) -> Table {
+        let mut plan = merge(self.defaults.clone(), plan);
+        if !self.variables.is_empty() {
+            let env = plan
+                .entry("env")
+                .or_insert_with(|| Value::Table(Table::new()));
+            if let Value::Table(env) = env {
+                env.extend(self.variables.clone());
+            }
+        }
+        plan
+    }
+}
+
+fn merge(mut defaults: Table, values: Table) -> Table {
+    for (key, value) in values {
+        let merged = match (defaults.remove(&key), value) {
+            (Some(Value::Table(default)), Value::Table(value)) if compatible(&default, &value) => {
+                Value::Table(merge(default, value))
+            }
+            // Like `[[provider]]`, whose entries each take the defaults of the same name
+            (Some(Value::Table(default)), Value::Array(values)) => Value::Array(
+                values
+                    .into_iter()
+                    .map(|value| match value {
+                        Value::Table(value) if compatible(&default, &value) => {
+                            Value::Table(merge(default.clone(), value))
+                        }
+                        value => value,
+                    })
+                    .collect(),
+            ),
+            (_, value) => value,
+        };
+        defaults.insert(key, merged);
+    }
+    defaults
+}
+
+/// Tables tagged with different names (e.g. two distinct providers) are not merged
+fn compatible(default: &Table, value: &Table) -> bool {
+    match (default.get("name"), value.get("name")) {
+        (Some(default), Some(value)) => default == value,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::value::Table;
+
+    use super::GlobalConfig;
+
+    #[test]
+    fn test_apply_to() {
+        let config = GlobalConfig::parse(
+            r#"
+            concurrency = 10
+            repositories_folder = "/tmp/repos"
+
+            [provider]
+            name = "github"
+            api_url = "https://github.example.com/api/v3"
+            token_env = "GITHUB_TOKEN"
+            "#,
+        )
+        .unwrap();
+
+        let plan: Table = toml::from_str(
+            r#"
+            concurrency = 2
+
+            [provider]
+            name = "github"
+            token_env = "OTHER_TOKEN"
+            "#,
+        )
+        .unwrap();
+        let merged = config.apply_to(plan);
+        assert_eq!(merged["concurrency"].as_integer(), Some(2));
+        assert_eq!(merged["repositories_folder"].as_str(), Some("/tmp/repos"));
+        assert_eq!(
+            merged["provider"]["api_url"].as_str(),
+            Some("https://github.example.com/api/v3")
+        );
+        assert_eq!(
+            merged["provider"]["token_env"].as_str(),
+            Some("OTHER_TOKEN")
+        );
+
+        let plan: Table = toml::from_str(
+            r#"
+            [provider]
+            name = "test"
+            "#,
+        )
+        .unwrap();
+        let merged = config.apply_to(plan);
+        assert_eq!(merged["provider"].as_table().unwrap().len(), 1);
+
+        let plan: Table = toml::from_str(
+            r#"
+            [[provider]]
+            name = "github"
+            organization = "fix-it"
+
+            [[provider]]
+            name = "test"
+            "#,
+        )
+        .unwrap();
+        let merged = config.apply_to(plan);
+        let providers = merged["provider"].as_array().unwrap();
+        assert_eq!(
+            providers[0]["api_url"].as_str(),
+            Some("https://github.example.com/api/v3")
+        );
+        assert_eq!(providers[0]["organization"].as_str(), Some("fix-it"));
+        assert_eq!(providers[1].as_table().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_override_variables() {
+        let mut config = GlobalConfig::parse(
+            r#"
+            [env]
+            TEAM = "platform"
+            "#,
+        )
+        .unwrap();
+        config.override_variables(&[("VERSION".to_owned(), "2".to_owned())]);
+
+        let plan: Table = toml::from_str(
+            r#"
+            [env]
+            VERSION = "1"
+            OWNER = "me"
+            "#,
+        )
+        .unwrap();
+        let merged = config.apply_to(plan);
+        assert_eq!(merged["env"]["VERSION"].as_str(), Some("2"));
+        assert_eq!(merged["env"]["OWNER"].as_str(), Some("me"));
+        assert_eq!(merged["env"]["TEAM"].as_str(), Some("platform"));
+    }
+}
@@ -1,5 +1,5 @@
 use camino::Utf8PathBuf;
-use directories::ProjectDirs;
+use directories::{BaseDirs, ProjectDirs};
 use lazy_static::lazy_static;
 
 lazy_static! {
@@ -9,4 +9,7 @@ lazy_static! {
     pub static ref CACHE_DIR: Utf8PathBuf =
         Utf8PathBuf::from_path_buf(PROJECT_DIR.cache_dir().to_owned())
             .expect("failed to get a cache directory");
+    pub static ref CONFIG_FILE: Option<Utf8PathBuf> = BaseDirs::new()
+        .and_then(|dirs| Utf8PathBuf::from_path_buf(dirs.config_dir().to_owned()).ok())
+        .map(|dir| dir.join("there-i-fixed-it.toml"));
 }
@@ -1,21 +1,21 @@
-use std::sync::Arc;
+use std::sync::OnceLock;
 
-use color_eyre::{eyre::Context, Result};
+use color_eyre::Result;
 use serde::{Deserialize, Serialize};
 use structopt::StructOpt;
-use tokio::{sync::Semaphore, task};
-use tracing::info;
 use tracing_error::ErrorLayer;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
-use crate::arguments::Arguments;
-use crate::constants::CACHE_DIR;
-use crate::plan::{plan_from_file, PlanExecutor};
+use crate::arguments::{Arguments, Command};
 
+mod annotations;
 mod arguments;
+mod commands;
+mod config;
 mod constants;
 mod plan;
 mod providers;
+mod report;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Repository {
@@ -23,15 +23,42 @@ pub struct Repository {
     private: bool,
     fork: bool,
     ssh_url: String,
+    /// Empty when the provider does not tell it, see `default_branch()`
+    #[serde(default)]
     default_branch: String,
+    /// Primary language, as detected by the provider
+    #[serde(default)]
+    language: Option<String>,
+    /// Index of the plan's provider that listed the repository
+    #[serde(skip)]
+    provider: usize,
+    /// Detected from the clone when the provider did not tell the default branch
+    #[serde(skip)]
+    detected_default_branch: OnceLock<String>,
 }
 
-pub(crate) fn setup_error_handlers() -> Result<()> {
+impl Repository {
+    /// The default branch from the provider, or the one detected from the clone, empty until
+    /// then
+    pub fn default_branch(&self) -> &str {
+        if !self.default_branch.is_empty() {
+            return &self.default_branch;
+        }
+        self.detected_default_branch
+            .get()
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+}
+
+/// `default_level` is used when `RUST_LOG` is not set
+pub(crate) fn setup_error_handlers(default_level: &str) -> Result<()> {
     if tracing::dispatcher::has_been_set() {
         return Ok(());
     }
     let error_layer = ErrorLayer::default();
-    let filter_layer = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info"))?;
+    let filter_layer =
+        EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new(default_level))?;
     let fmt_layer = fmt::layer().with_target(false);
 
     tracing_subscriber::Registry::default()
@@ -46,41 +73,31 @@ pub(crate) fn setup_error_handlers() -> Result<()> {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    setup_error_handlers()?;
-
     let arguments = Arguments::from_args();
-    info!("parsing plan");
-    let plan = Arc::new(plan_from_file(&arguments.plan_file).await?);
-    let provider = plan.get_provider();
-    let all_repositories = provider
-        .list_repositories(!arguments.skip_repository_cache)
-        .await?;
-
-    let executors = all_repositories
-        .into_iter()
-        .filter(|repository| plan.repository_allowed(&repository.name))
-        .map(|repository| PlanExecutor::new(plan.clone(), repository, &CACHE_DIR))
-        .collect::<Vec<_>>();
-
-    let mut futures = vec![];
+    setup_error_handlers(arguments.log_level())?;
 
-    let s = Arc::new(Semaphore::new(5));
-    for executor in executors {
-        let permit = s.clone().acquire_owned().await?;
-        futures.push(task::spawn(async move {
-            let _ = permit;
-            executor
-                .process()
-                .await
-                .wrap_err(format!("failed to process repository {}", executor))
-        }))
+    let result = match &arguments.command {
+        Command::Run(run_arguments) => commands::run(&arguments, run_arguments).await,
+        Command::List(list_arguments) => commands::list(&arguments, list_arguments).await,
+        Command::MarkReady(mark_ready_arguments) => {
+            commands::mark_ready(&arguments, mark_ready_arguments).await
+        }
+        Command::RenderPr(render_pr_arguments) => {
+            commands::render_pr(&arguments, render_pr_arguments).await
+        }
+        Command::Doctor(doctor_arguments) => commands::doctor(&arguments, doctor_arguments).await,
+        Command::PruneBranches(prune_branches_arguments) => {
+            commands::prune_branches(&arguments, prune_branches_arguments).await
+        }
+    };
+    if let Err(err) = &result {
+        if arguments.github_annotations() {
+            annotations::annotate(annotations::Level::Error, None, &format!("{:#}", err));
+        }
+        if err.downcast_ref::<commands::MaxRuntimeExceeded>().is_some() {
+            eprintln!("Error: {:?}", err);
+            std::process::exit(commands::MAX_RUNTIME_EXIT_CODE);
+        }
     }
-
-    for future in futures {
-        let _response = future.await??;
-    }
-
-    info!("process done");
-
-    Ok(())
+    result
 }
@@ -0,0 +1,168 @@
+use camino::Utf8Path;
+use color_eyre::{eyre::Context, Result};
+use glob::{MatchOptions, Pattern};
+use tokio::fs;
+
+/// Where GitHub looks for the file, in order
+const LOCATIONS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// The owners of each path of a repository, from its `CODEOWNERS` file
+#[derive(Debug, Default)]
+pub struct CodeOwners {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    pattern: Pattern,
+    /// Patterns with a `/` other than a trailing one only match from the root
+    anchored: bool,
+    owners: Vec<String>,
+}
+
+impl CodeOwners {
+    /// `None` when the repository has no `CODEOWNERS`
+    pub async fn from_repository(directory: &Utf8Path) -> Result<Option<Self>> {
+        for location in LOCATIONS.iter() {
+            let path = directory.join(location);
+            if !path.exists() {
+                continue;
+            }
+            let contents = fs::read_to_string(&path)
+                .await
+                .wrap_err_with(|| format!("failed to read {}", path))?;
+            return Ok(Some(Self::parse(&contents)));
+        }
+        Ok(None)
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let mut rules = vec![];
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let pattern = match parts.next() {
+                Some(pattern) => pattern,
+                None => continue,
+            };
+            let owners = parts
+                .take_while(|part| !part.starts_with('#'))
+                .map(str::to_owned)
+                .collect();
+            let anchored = pattern.trim_end_matches('/').contains('/');
+            let pattern = pattern.trim_start_matches('/');
+            let pattern = if pattern.ends_with('/') {
+                format!("{}**", pattern)
+            } else {
+                pattern.to_owned()
+            };
+            if let Ok(pattern) = Pattern::new(&pattern) {
+                rules.push(Rule {
+                    pattern,
+                    anchored,
+                    owners,
+                });
+            }
+        }
+        Self { rules }
+    }
+
+    /// Owners of any of `relative_paths` without the leading `@`, skipping e-mails. Paths are
+    /// relative to the root of the repository and use `/` as separator
+    pub fn owners<'a>(&self, relative_paths: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+        let mut owners: Vec<String> = vec![];
+        for path in relative_paths {
+            // The last matching line wins, even when it has no owners
+            let rule = match self.rules.iter().rev().find(|rule| rule.matches(path)) {
+                Some(rule) => rule,
+                None => continue,
+            };
+            for owner in &rule.owners {
+                let owner = match owner.strip_prefix('@') {
+                    Some(owner) => owner,
+                    None => continue,
+                };
+                if !owners.iter().any(|known| known == owner) {
+                    owners.push(owner.to_owned());
+                }
+            }
+        }
+        owners
+    }
+}
+
+impl Rule {
+    fn matches(&self, path: &str) -> bool {
+        if self.anchored {
+            return self.matches_from(path);
+        }
+        // Patterns without a `/` match at any depth
+        let mut start = 0;
+        loop {
+            if self.matches_from(&path[start..]) {
+                return true;
+            }
+            match path[start..].find('/') {
+                Some(separator) => start += separator + 1,
+                None => return false,
+            }
+        }
+    }
+
+    /// Whether `path` or one of its parent folders matches
+    fn matches_from(&self, path: &str) -> bool {
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        let mut end = path.len();
+        loop {
+            if self.pattern.matches_with(&path[..end], options) {
+                return true;
+            }
+            match path[..end].rfind('/') {
+                Some(separator) => end = separator,
+                None => return false,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CodeOwners;
+
+    #[test]
+    fn test_owners() {
+        let owners = CodeOwners::parse(
+            r#"
+# comments are ignored
+*       @org/everyone
+*.py    @org/python @alice # trailing comments too
+/docs/  @org/docs docs@example.com
+build/logs/ @bob
+config
+vendor/generated.go
+"#,
+        );
+
+        assert_eq!(owners.owners(["main.rs"]), vec!["org/everyone"]);
+        assert_eq!(
+            owners.owners(["main.rs", "app/main.py", "app/other.py"]),
+            vec!["org/everyone", "org/python", "alice"]
+        );
+        assert_eq!(owners.owners(["docs/a/index.md"]), vec!["org/docs"]);
+        assert_eq!(owners.owners(["app/docs/index.md"]), vec!["org/everyone"]);
+        assert_eq!(owners.owners(["build/logs/today.log"]), vec!["bob"]);
+        assert_eq!(
+            owners.owners(["app/build/logs/today.log"]),
+            vec!["org/everyone"]
+        );
+        // The last matching rule has no owners
+        assert!(owners.owners(["app/config/settings.toml"]).is_empty());
+        assert!(owners.owners(["vendor/generated.go"]).is_empty());
+    }
+}
@@ -0,0 +1,113 @@
+use color_eyre::{eyre::eyre, Result};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use serde::{de::Visitor, Deserialize, Deserializer};
+
+/// Text encoding of the files of an operation, defaults to UTF-8
+#[derive(Debug, Clone, Copy)]
+pub struct FileEncoding(&'static Encoding);
+
+impl Default for FileEncoding {
+    fn default() -> Self {
+        Self(UTF_8)
+    }
+}
+
+impl FileEncoding {
+    pub fn name(&self) -> &'static str {
+        self.0.name()
+    }
+
+    /// Whether ASCII text, like line endings, is encoded as in ASCII
+    pub fn is_ascii_compatible(&self) -> bool {
+        self.0.is_ascii_compatible()
+    }
+
+    /// Returns `None` for binary files or files that are not valid on this encoding.
+    /// A byte order mark is kept as the first character of the text.
+    pub fn decode(&self, bytes: &[u8]) -> Option<String> {
+        let is_utf16 = self.0 == UTF_16LE || self.0 == UTF_16BE;
+        if !is_utf16 && bytes.contains(&0) {
+            return None;
+        }
+        self.0
+            .decode_without_bom_handling_and_without_replacement(bytes)
+            .map(|text| text.into_owned())
+    }
+
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>> {
+        // encoding_rs only encodes to UTF-16 as UTF-8, so it has to be done by hand
+        if self.0 == UTF_16LE {
+            return Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect());
+        }
+        if self.0 == UTF_16BE {
+            return Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect());
+        }
+        let (bytes, _, had_unmappable) = self.0.encode(text);
+        if had_unmappable {
+            return Err(eyre!("text cannot be represented as {}", self.name()));
+        }
+        Ok(bytes.into_owned())
+    }
+}
+
+struct FileEncodingVisitor;
+impl<'de> Visitor<'de> for FileEncodingVisitor {
+    type Value = FileEncoding;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("an encoding label like utf-8, latin1 or utf-16le")
+    }
+
+    fn visit_str<E>(self, v: &str) -> std::result::Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        match Encoding::for_label(v.as_bytes()) {
+            Some(encoding) => Ok(FileEncoding(encoding)),
+            None => Err(E::custom(format!("unknown encoding {:?}", v))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FileEncoding {
+    fn deserialize<D>(deserializer: D) -> Result<FileEncoding, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(FileEncodingVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::{UTF_16LE, WINDOWS_1252};
+
+    use super::FileEncoding;
+
+    #[test]
+    fn test_utf8() {
+        let encoding = FileEncoding::default();
+        assert_eq!(
+            encoding.decode(b"enabled = True").unwrap(),
+            "enabled = True"
+        );
+        assert_eq!(encoding.decode(b"\xef\xbb\xbfbom").unwrap(), "\u{feff}bom");
+        assert_eq!(encoding.decode(b"binary\x00file"), None);
+        assert_eq!(encoding.decode(b"latin1 \xe9"), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let latin1 = FileEncoding(WINDOWS_1252);
+        let text = latin1.decode(b"caf\xe9").unwrap();
+        assert_eq!(text, "café");
+        assert_eq!(latin1.encode(&text).unwrap(), b"caf\xe9");
+        assert!(latin1.encode("日本").is_err());
+
+        let utf16 = FileEncoding(UTF_16LE);
+        let bytes = b"\xff\xfea\x00b\x00";
+        let text = utf16.decode(bytes).unwrap();
+        assert_eq!(text, "\u{feff}ab");
+        assert_eq!(utf16.encode(&text).unwrap(), bytes);
+    }
+}
@@ -0,0 +1,103 @@
+use std::{error::Error, fmt};
+
+use color_eyre::Report;
+
+/// What failed while processing a repository, wrapping the report of the cause so the commands
+/// can react to the kind of failure and still show its details. The crate only builds the CLI, so
+/// this is not an API for other crates
+#[derive(Debug)]
+pub enum ExecutorError {
+    /// Checking what decides whether the repository is processed, like the plan's
+    /// `require_merged_branch`, the lock of the repository or its saved state
+    Precondition(Report),
+    /// Cloning or updating the repository
+    Clone(Report),
+    /// Cleaning the clone, checking out the base or creating the branch
+    Branch(Report),
+    /// Running the operations on the files
+    Processor(Report),
+    /// Committing the changes
+    Commit(Report),
+    /// Pushing the branch or the tag
+    Push(Report),
+    /// Opening the pull request or the issue
+    Provider(Report),
+    /// Saving the state of the repository after it was processed, so the next run skips it
+    State(Report),
+}
+
+impl ExecutorError {
+    pub fn report(&self) -> &Report {
+        match self {
+            Self::Precondition(report)
+            | Self::Clone(report)
+            | Self::Branch(report)
+            | Self::Processor(report)
+            | Self::Commit(report)
+            | Self::Push(report)
+            | Self::Provider(report)
+            | Self::State(report) => report,
+        }
+    }
+
+    /// The report of the cause with what failed on top, keeping its sections and suggestions,
+    /// which wrapping the error on a new report would lose
+    pub fn into_report(self) -> Report {
+        let kind = self.to_string();
+        let report = match self {
+            Self::Precondition(report)
+            | Self::Clone(report)
+            | Self::Branch(report)
+            | Self::Processor(report)
+            | Self::Commit(report)
+            | Self::Push(report)
+            | Self::Provider(report)
+            | Self::State(report) => report,
+        };
+        report.wrap_err(kind)
+    }
+}
+
+impl fmt::Display for ExecutorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            Self::Precondition(_) => "precondition not met",
+            Self::Clone(_) => "clone failed",
+            Self::Branch(_) => "branch failed",
+            Self::Processor(_) => "processor failed",
+            Self::Commit(_) => "commit failed",
+            Self::Push(_) => "push failed",
+            Self::Provider(_) => "provider failed",
+            Self::State(_) => "saving state failed",
+        };
+        f.write_str(kind)
+    }
+}
+
+impl Error for ExecutorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&**self.report())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use color_eyre::eyre::eyre;
+
+    use super::ExecutorError;
+
+    #[test]
+    fn test_executor_error() {
+        let error = ExecutorError::Push(eyre!("remote rejected").wrap_err("failed to push"));
+        assert_eq!(error.to_string(), "push failed");
+        assert_eq!(
+            std::error::Error::source(&error).unwrap().to_string(),
+            "failed to push"
+        );
+
+        let report =
+            ExecutorError::Clone(eyre!("not found").wrap_err("failed to clone")).into_report();
+        let chain = report.chain().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(chain, vec!["clone failed", "failed to clone", "not found"]);
+    }
+}
@@ -1,7 +1,11 @@
 use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
     fmt::Display,
+    future::Future,
+    hash::{Hash, Hasher},
     process::{Output, Stdio},
-    sync::Arc,
+    sync::{Arc, Mutex},
+    time::Instant,
 };
 
 use camino::{Utf8Path, Utf8PathBuf};
@@ -9,84 +13,491 @@ use color_eyre::{
     eyre::{eyre, Context},
     Help, Result, SectionExt,
 };
-use tokio::{fs, process::Command};
-use tracing::{debug, info, instrument, trace};
+use regex::bytes::Regex;
+use tokio::{
+    fs,
+    io::{self, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
+    process::Command,
+    sync::{Semaphore, SemaphorePermit},
+};
+use tracing::{debug, info, instrument, trace, warn};
+
+use crate::{
+    providers::{Issue, PrComment, PrState, PullRequest},
+    report::{
+        Diagnostics, DiffStat, OperationDiff, Outcome, Phase, PhaseTiming, PushedBranch,
+        SkipReason, SkippedFile, Status,
+    },
+    Repository,
+};
+
+use super::{
+    codeowners::CodeOwners,
+    encoding::FileEncoding,
+    error::ExecutorError,
+    gitattributes::GeneratedFiles,
+    interactive::Confirmation,
+    lock::RepositoryLock,
+    output::{wait_with_limited_output, DEFAULT_OUTPUT_LIMIT},
+    process::grouped_command,
+    processors::FileProcessor,
+    state::RepositoryState,
+    submodules::Submodules,
+    template, Action, CommitStrategy, FileOperation, OnError, Plan,
+};
+#[cfg(feature = "libgit2")]
+use super::{libgit2, GitBackend};
+
+/// Only the beginning of the files is scanned when selecting them by content
+const MAX_SCANNED_BYTES: u64 = 1024 * 1024;
+
+/// Larger files are streamed when all of their processors only need one line at a time
+const STREAMING_THRESHOLD: u64 = 8 * 1024 * 1024;
+
+/// Appended to the path of a changed file for its backup, with `keep_backups`
+const BACKUP_SUFFIX: &str = ".orig";
 
-use crate::Repository;
+/// Shown on dry runs when git has no identity to commit with
+const UNKNOWN_AUTHOR: &str = "Unknown <unknown>";
 
-use super::{glob_pattern::GlobPattern, FileOperation, Plan};
+/// Files or folders that mean the repository has CI configured
+const CI_CONFIGURATIONS: [&str; 2] = [".github/workflows", ".gitlab-ci.yml"];
+
+/// Settings that come from the command line instead of the plan
+#[derive(Debug, Default)]
+pub struct ExecutorOptions {
+    /// Process repositories even if nothing changed since their last successful run
+    pub force: bool,
+    /// Ask before committing the changes of each repository
+    pub confirmation: Option<Confirmation>,
+    /// Only compute the changes, without committing, pushing or opening pull requests
+    pub dry_run: bool,
+    /// Overrides the plan's `ssh_command`
+    pub ssh_command: Option<String>,
+    /// Bounds the git subprocesses running at the same time across all repositories
+    pub git_jobs: Option<Arc<Semaphore>>,
+    /// Wait for other runs processing the same repository instead of skipping it
+    pub wait_for_locks: bool,
+    /// Unchanged lines shown around each change of dry run diffs, git's default when missing
+    pub context_lines: Option<usize>,
+    /// Save the original of each changed file next to it, ignored by git so it is not committed
+    pub keep_backups: bool,
+    /// Bytes kept of the output of git and of the plan's commands, 1 MiB when missing
+    pub max_output: Option<usize>,
+    /// Push the branches without opening pull requests, like the plan's `open_pr = false`
+    pub no_pr: bool,
+    /// Remove the untracked and ignored files of reused clones before processing them
+    pub clean: bool,
+}
+
+/// What a run commits and opens on a repository
+#[derive(Debug)]
+pub struct RenderedChanges {
+    pub branch_name: String,
+    pub pull_request_head: String,
+    pub commit_message: String,
+    pub pull_request_title: String,
+    pub pull_request_body: Option<String>,
+    pub issue_title: String,
+    pub issue_body: Option<String>,
+}
 
 pub struct PlanExecutor {
     plan: Arc<Plan>,
+    options: Arc<ExecutorOptions>,
     repository: Repository,
     directory: Utf8PathBuf,
+    state_file: Utf8PathBuf,
+    lock_file: Utf8PathBuf,
+    timings: Mutex<Vec<PhaseTiming>>,
+    skipped_files: Mutex<Vec<SkippedFile>>,
+    /// Files of the current run with a backup, which keeps the content before the first operation
+    backed_up_files: Mutex<HashSet<Utf8PathBuf>>,
+    /// Changes of each operation of a dry run so far
+    operation_diffs: Mutex<Vec<OperationDiff>>,
+    /// Files changed by each operation that ran so far
+    operation_files: Mutex<Vec<OperationFiles>>,
+}
+
+/// Hidden on the pull request comment, to find it on later runs
+const DETAILS_MARKER: &str = "<!-- there-i-fixed-it:details -->";
+
+#[derive(Debug)]
+struct OperationFiles {
+    operation: String,
+    /// Relative to the repository, with `/` as separator
+    files: Vec<String>,
 }
 
 impl PlanExecutor {
-    pub fn new(plan: Arc<Plan>, repository: Repository, repositories_folder: &Utf8Path) -> Self {
-        let directory = repositories_folder.join("repos").join(&repository.name);
+    pub fn new(
+        plan: Arc<Plan>,
+        options: Arc<ExecutorOptions>,
+        repository: Repository,
+        repositories_folder: &Utf8Path,
+    ) -> Self {
+        // Repositories with the same name on other providers must not share a clone
+        let name = match repository.provider {
+            0 => repository.name.clone(),
+            index => format!("{}.{}", repository.name, index),
+        };
+        let directory = repositories_folder.join("repos").join(&name);
+        let state_file = repositories_folder
+            .join("state")
+            .join(format!("{}.json", name));
+        let lock_file = repositories_folder
+            .join("locks")
+            .join(format!("{}.lock", name));
 
         Self {
             plan,
+            options,
             repository,
             directory,
+            state_file,
+            lock_file,
+            timings: Default::default(),
+            skipped_files: Default::default(),
+            backed_up_files: Default::default(),
+            operation_diffs: Default::default(),
+            operation_files: Default::default(),
         }
     }
+
+    /// How long each phase of the last `process` took
+    pub fn timings(&self) -> Vec<PhaseTiming> {
+        self.timings.lock().unwrap().clone()
+    }
+
+    async fn timed<T>(&self, phase: Phase, future: impl Future<Output = Result<T>>) -> Result<T> {
+        let start = Instant::now();
+        let result = future.await;
+        let elapsed = start.elapsed();
+        debug!("{} took {:.1?}", phase, elapsed);
+        self.timings
+            .lock()
+            .unwrap()
+            .push(PhaseTiming::new(phase, elapsed));
+        result
+    }
     #[instrument(skip(self), fields(repository_name = self.repository.name.as_str()))]
-    pub async fn process(&self) -> Result<()> {
+    pub async fn process(&self) -> Result<Outcome, ExecutorError> {
         debug!("started");
+        self.timings.lock().unwrap().clear();
+        self.skipped_files.lock().unwrap().clear();
+        self.backed_up_files.lock().unwrap().clear();
+        self.operation_diffs.lock().unwrap().clear();
+        self.operation_files.lock().unwrap().clear();
 
-        self.clone_repository().await?;
-        self.ensure_branch().await?;
+        if !self
+            .required_branch_merged()
+            .await
+            .map_err(ExecutorError::Precondition)?
+        {
+            return Ok(Outcome::skipped(SkipReason::BranchNotMerged));
+        }
+        if self.plan.action == Action::Issue {
+            return self
+                .timed(Phase::Issue, self.open_issue())
+                .await
+                .map_err(ExecutorError::Provider);
+        }
+        let _lock = match self.lock().await.map_err(ExecutorError::Precondition)? {
+            Some(lock) => lock,
+            None => {
+                info!("repository is being processed by another run");
+                return Ok(Outcome::skipped(SkipReason::Locked));
+            }
+        };
 
-        if !self.process_operations().await? {
-            return Ok(());
+        self.timed(Phase::Clone, self.clone_repository())
+            .await
+            .map_err(ExecutorError::Clone)?;
+        if self.is_empty().await.map_err(ExecutorError::Clone)? {
+            info!("repository is empty");
+            return Ok(Outcome::empty());
+        }
+        if self.repository.default_branch().is_empty() {
+            self.detect_default_branch()
+                .await
+                .map_err(ExecutorError::Clone)?;
+        }
+        let state = self
+            .current_state()
+            .await
+            .map_err(ExecutorError::Precondition)?;
+        let saved_state = RepositoryState::load(&self.state_file)
+            .await
+            .map_err(ExecutorError::Precondition)?;
+        if !self.options.force && saved_state == state {
+            info!("nothing changed since the last run");
+            return Ok(Outcome::skipped(SkipReason::UpToDate));
+        }
+
+        let mut outcome = self.apply().await?;
+        outcome.skipped_files = self.skipped_files.lock().unwrap().clone();
+        let applied = outcome.status != Status::Skipped && !self.options.dry_run;
+        if let Some(state) = state.filter(|_| applied) {
+            state
+                .save(&self.state_file)
+                .await
+                .map_err(ExecutorError::State)?;
+        }
+        Ok(outcome)
+    }
+
+    async fn apply(&self) -> Result<Outcome, ExecutorError> {
+        if self.options.clean {
+            self.clean().await.map_err(ExecutorError::Branch)?;
+        }
+        if !self
+            .timed(Phase::Branch, self.checkout_base())
+            .await
+            .map_err(ExecutorError::Branch)?
+        {
+            warn!(
+                "base_ref {} does not exist on the repository",
+                self.plan.base_ref.as_deref().unwrap_or_default()
+            );
+            return Ok(Outcome::skipped(SkipReason::MissingBaseRef));
+        }
+        if self.options.keep_backups {
+            self.ignore_backups().await.map_err(ExecutorError::Branch)?;
+        }
+        if self.plan.require_ci && !self.has_ci() {
+            info!("no CI configuration found");
+            return Ok(Outcome::skipped(SkipReason::NoCi));
+        }
+
+        if !self
+            .timed(Phase::Process, self.process_operations())
+            .await
+            .map_err(ExecutorError::Processor)?
+        {
+            return Ok(Outcome::unchanged());
+        }
+
+        if self.options.dry_run {
+            return self
+                .discard_changes()
+                .await
+                .map_err(ExecutorError::Processor);
+        }
+
+        if !self.confirm().await.map_err(ExecutorError::Processor)? {
+            info!("changes declined, discarding them");
+            self.git_output(&["reset", "--hard"])
+                .await
+                .wrap_err("failed to discard changes")
+                .map_err(ExecutorError::Processor)?;
+            return Ok(Outcome::skipped(SkipReason::Declined));
+        }
+
+        // Only now, so runs without changes do not leave branches behind
+        self.create_branch().await.map_err(ExecutorError::Branch)?;
+        if !self
+            .timed(Phase::Commit, self.commit())
+            .await
+            .map_err(ExecutorError::Commit)?
+        {
+            info!("processors changed files but git found nothing to commit");
+            return Ok(Outcome::unchanged());
+        }
+        let diff_stat = self.diff_stat().await.map_err(ExecutorError::Commit)?;
+        self.timed(Phase::Push, async {
+            self.push().await?;
+            self.push_tag().await
+        })
+        .await
+        .map_err(ExecutorError::Push)?;
+        let mut outcome = Outcome::changed(diff_stat.clone());
+        // The same branch and head as the pull request paths, so they cannot drift apart
+        let changes = self.render_changes();
+        if self.plan.open_pr && !self.options.no_pr {
+            self.timed(Phase::PullRequest, self.open_pr(&changes, &diff_stat))
+                .await
+                .map_err(ExecutorError::Provider)?;
+        } else {
+            let name = changes.branch_name;
+            let url = self
+                .plan
+                .provider(&self.repository)
+                .branch_url(&self.repository.name, &name);
+            info!("pushed {} without opening a pull request: {}", name, url);
+            outcome.pushed_branch = Some(PushedBranch { name, url });
+        }
+        if self.plan.action == Action::PullRequestAndIssue {
+            self.timed(Phase::Issue, self.open_issue())
+                .await
+                .map_err(ExecutorError::Provider)?;
+        }
+        Ok(outcome)
+    }
+
+    async fn lock(&self) -> Result<Option<RepositoryLock>> {
+        if self.options.wait_for_locks {
+            return RepositoryLock::acquire(&self.lock_file).await.map(Some);
+        }
+        RepositoryLock::try_acquire(&self.lock_file).await
+    }
+
+    #[instrument(skip(self))]
+    async fn required_branch_merged(&self) -> Result<bool> {
+        let branch_name = match &self.plan.require_merged_branch {
+            Some(branch_name) => branch_name,
+            None => return Ok(true),
+        };
+        let state = self
+            .plan
+            .provider(&self.repository)
+            .pr_state(
+                &self.repository.name,
+                &self.plan.pull_request_head(&self.repository, branch_name),
+            )
+            .await
+            .wrap_err("failed to check the required branch")?;
+        if state != Some(PrState::Merged) {
+            info!("pull request of {} is not merged: {:?}", branch_name, state);
+            return Ok(false);
         }
+        Ok(true)
+    }
+
+    fn has_ci(&self) -> bool {
+        CI_CONFIGURATIONS.iter().any(|path| {
+            let path = self.directory.join(path);
+            match path.read_dir() {
+                Ok(mut entries) => entries.next().is_some(),
+                Err(_) => path.is_file(),
+            }
+        })
+    }
+
+    /// Empty repositories have no branches, not even the default one
+    #[instrument(skip(self))]
+    async fn is_empty(&self) -> Result<bool> {
+        let output = self
+            .git_output(&["ls-remote", "--heads", "origin"])
+            .await
+            .wrap_err("failed to list remote branches")?;
+        Ok(output.trim().is_empty())
+    }
 
-        self.commit().await?;
-        self.push().await?;
-        self.open_pr().await?;
+    /// For repositories whose provider did not tell their default branch, from the remote's
+    /// `HEAD`, asking the remote when the clone does not know it
+    #[instrument(skip(self))]
+    async fn detect_default_branch(&self) -> Result<()> {
+        let output = self
+            .run_git(
+                &["symbolic-ref", "--short", "refs/remotes/origin/HEAD"],
+                None,
+            )
+            .await?;
+        let default_branch = if output.status.success() {
+            let reference = String::from_utf8_lossy(&output.stdout);
+            let reference = reference.trim();
+            reference
+                .strip_prefix("origin/")
+                .unwrap_or(reference)
+                .to_owned()
+        } else {
+            let output = self
+                .git_output(&["ls-remote", "--symref", "origin", "HEAD"])
+                .await
+                .wrap_err("failed to ask the remote for its default branch")?;
+            remote_head_branch(&output)
+                .ok_or_else(|| eyre!("failed to detect the default branch"))
+                .suggestion("check that the remote has a HEAD branch")?
+                .to_owned()
+        };
+        debug!("detected {}", default_branch);
+        self.repository
+            .detected_default_branch
+            .set(default_branch)
+            .ok();
         Ok(())
     }
 
+    /// The plan and the remote default branch that a run would be based on
+    #[instrument(skip(self))]
+    async fn current_state(&self) -> Result<Option<RepositoryState>> {
+        let reference = format!("refs/heads/{}", self.repository.default_branch());
+        let output = self
+            .git_output(&["ls-remote", "origin", &reference])
+            .await
+            .wrap_err("failed to get the default branch commit")?;
+        Ok(output
+            .split_whitespace()
+            .next()
+            .map(|base_commit| RepositoryState {
+                plan_checksum: self.plan.checksum.clone(),
+                base_commit: base_commit.to_owned(),
+            }))
+    }
+
     #[instrument(skip(self))]
     async fn clone_repository(&self) -> Result<()> {
         if self.directory.exists() {
             debug!("Skipping");
             return Ok(());
         }
+        let _permit = self.git_permit().await?;
+        #[cfg(feature = "libgit2")]
+        if self.plan.git_backend == GitBackend::Libgit2 {
+            libgit2::clone(
+                &self.repository.ssh_url,
+                &self.directory,
+                self.plan.process_submodules,
+            )
+            .await?;
+            info!("done");
+            return Ok(());
+        }
 
-        let output = Command::new("git")
-            .args(&["clone", &self.repository.ssh_url.as_str()])
+        let mut command = self.git();
+        command.args(["clone", self.repository.ssh_url.as_str()]);
+        if self.plan.process_submodules {
+            command.arg("--recurse-submodules");
+        }
+        let child = command
             .arg(&self.directory)
             .stdin(Stdio::null())
             .stderr(Stdio::piped())
             .stdout(Stdio::piped())
-            .spawn()?
-            .wait_with_output()
-            .await?;
+            .spawn()?;
+        let output =
+            wait_with_limited_output(child, self.output_limit(), self.output_limit()).await?;
         check_process(&output).wrap_err("failed to clone repository")?;
         info!("done");
         Ok(())
     }
 
+    /// Checks out what the changes start from: the branch when it is already checked out and
+    /// should not be recreated, otherwise the plan's `base_ref` or the latest default branch, on
+    /// which the branch is only created once there are changes. `false` when the plan's
+    /// `base_ref` does not exist on the repository
     #[instrument(skip(self), fields(directory = self.directory.as_str()))]
-    async fn ensure_branch(&self) -> Result<()> {
-        // git branch --show-current is only on git 2.22+
-        let current_branch = self
-            .git_output(&["rev-parse", "--abbrev-ref", "HEAD"])
-            .await
-            .wrap_err("failed to list branch")?;
-        let current_branch = current_branch.trim();
-        if current_branch == self.plan.branch_name {
+    async fn checkout_base(&self) -> Result<bool> {
+        #[cfg(feature = "libgit2")]
+        if self.plan.git_backend == GitBackend::Libgit2 {
+            return libgit2::checkout_base(
+                &self.directory,
+                self.repository.default_branch(),
+                self.plan.base_ref.as_deref(),
+                &self.branch_name(),
+                self.plan.recreate_branch,
+            )
+            .await;
+        }
+        if self.on_branch().await? && !self.plan.recreate_branch {
             debug!("branch already checked out");
-            return Ok(());
+            return Ok(true);
         }
 
         self.git_output(&["reset", "--hard"])
             .await
             .wrap_err("failed to reset branch")?;
-        self.git_output(&["checkout", &self.repository.default_branch])
+        self.git_output(&["checkout", self.repository.default_branch()])
             .await
             .wrap_err("failed to checkout default branch")?;
 
@@ -94,163 +505,1222 @@ impl PlanExecutor {
             .await
             .wrap_err("failed to pull changes")?;
 
-        let _ = self
-            .git_output(&["checkout", "-b", self.plan.branch_name.as_str()])
+        if let Some(base_ref) = &self.plan.base_ref {
+            let base = match self.resolve_base_ref(base_ref).await? {
+                Some(base) => base,
+                None => return Ok(false),
+            };
+            self.git_output(&["checkout", "--detach", &base])
+                .await
+                .wrap_err("failed to checkout base_ref")?;
+        }
+        Ok(true)
+    }
+
+    /// Creates the branch where the base is checked out, keeping the changes
+    #[instrument(skip(self), fields(directory = self.directory.as_str()))]
+    async fn create_branch(&self) -> Result<()> {
+        #[cfg(feature = "libgit2")]
+        if self.plan.git_backend == GitBackend::Libgit2 {
+            return libgit2::create_branch(&self.directory, &self.branch_name()).await;
+        }
+        if self.on_branch().await? {
+            return Ok(());
+        }
+        // -B resets the branch to the base when it already exists
+        let checkout = if self.plan.recreate_branch {
+            "-B"
+        } else {
+            "-b"
+        };
+        let branch_name = self.branch_name();
+        self.git_output(&["checkout", checkout, &branch_name])
             .await
             .wrap_err("failed to checkout new branch")?;
-        debug!("changed to branch {}", self.plan.branch_name);
+        debug!("changed to branch {}", branch_name);
         Ok(())
     }
 
+    async fn on_branch(&self) -> Result<bool> {
+        // git branch --show-current is only on git 2.22+
+        let current_branch = self
+            .git_output(&["rev-parse", "--abbrev-ref", "HEAD"])
+            .await
+            .wrap_err("failed to list branch")?;
+        Ok(current_branch.trim() == self.branch_name())
+    }
+
+    /// The commit of `base_ref`, which can also be a branch that was never checked out
+    async fn resolve_base_ref(&self, base_ref: &str) -> Result<Option<String>> {
+        // Pulling only brings the tags of the default branch
+        self.git_output(&["fetch", "--tags", "origin"])
+            .await
+            .wrap_err("failed to fetch tags")?;
+        self.base_ref_commit(base_ref).await
+    }
+
+    /// The commit of `base_ref` among the fetched refs
+    async fn base_ref_commit(&self, base_ref: &str) -> Result<Option<String>> {
+        // The fetched branch first, as a local one left by an earlier run can be stale
+        for candidate in [format!("origin/{}", base_ref), base_ref.to_owned()] {
+            let commit = format!("{}^{{commit}}", candidate);
+            if let Ok(output) = self
+                .git_output(&["rev-parse", "--verify", "--quiet", &commit])
+                .await
+            {
+                return Ok(Some(output.trim().to_owned()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Waits for a free slot of `git_jobs`, held until the permit is dropped
+    async fn git_permit(&self) -> Result<Option<SemaphorePermit<'_>>> {
+        match &self.options.git_jobs {
+            Some(semaphore) => Ok(Some(semaphore.acquire().await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Uses the SSH command of the options or the plan, to pick which key authenticates
+    fn git(&self) -> Command {
+        let mut command = git_command();
+        if let Some(ssh_command) = self.ssh_command() {
+            command.env("GIT_SSH_COMMAND", ssh_command);
+        }
+        command
+    }
+
+    fn ssh_command(&self) -> Option<&str> {
+        self.options
+            .ssh_command
+            .as_deref()
+            .or(self.plan.ssh_command.as_deref())
+    }
+
+    /// The git state of the clone, to tell why processing it failed, `None` when it was not cloned
+    #[instrument(skip(self))]
+    pub async fn diagnostics(&self) -> Option<Diagnostics> {
+        if !self.directory.exists() {
+            return None;
+        }
+        Some(Diagnostics {
+            branch: self
+                .diagnostic_output(&["rev-parse", "--abbrev-ref", "HEAD"])
+                .await,
+            status: self.diagnostic_output(&["status"]).await,
+            log: self
+                .diagnostic_output(&["log", "-n", "5", "--oneline", "--decorate"])
+                .await,
+        })
+    }
+
+    /// The output of git, or its error, as the clone can be in any state
+    async fn diagnostic_output(&self, args: &[&str]) -> String {
+        match self.git_output(args).await {
+            Ok(output) => output.trim_end().to_owned(),
+            Err(err) => format!("{:#}", err),
+        }
+    }
+
     #[instrument(skip(self))]
     async fn git_output(&self, args: &[&str]) -> Result<String> {
-        let output = Command::new("git")
+        check_process(&self.run_git(args, None).await?)
+    }
+
+    /// Only stderr is limited unless `stdout_limit` is given, as stdout is usually parsed
+    async fn run_git(&self, args: &[&str], stdout_limit: Option<usize>) -> Result<Output> {
+        self.run_git_with_environment(args, stdout_limit, vec![])
+            .await
+    }
+
+    /// Like `run_git`, with variables only for this command
+    async fn run_git_with_environment(
+        &self,
+        args: &[&str],
+        stdout_limit: Option<usize>,
+        environment: Vec<(&str, String)>,
+    ) -> Result<Output> {
+        let _permit = self.git_permit().await?;
+        let child = self
+            .git()
             .args(args)
+            .envs(self.environment())
+            .envs(environment)
             .stdin(Stdio::null())
             .stderr(Stdio::piped())
             .stdout(Stdio::piped())
             .current_dir(&self.directory)
-            .spawn()?
-            .wait_with_output()
-            .await?;
-        Ok(check_process(&output)?)
+            .spawn()?;
+        Ok(wait_with_limited_output(child, stdout_limit, self.output_limit()).await?)
+    }
+
+    fn output_limit(&self) -> Option<usize> {
+        Some(self.options.max_output.unwrap_or(DEFAULT_OUTPUT_LIMIT))
     }
 
     async fn process_operations(&self) -> Result<bool> {
         let mut files_changed = false;
-        for operation in &self.plan.file_operations {
-            files_changed |= self.process_operation(operation).await?;
+        for (index, operation) in self.plan.file_operations.iter().enumerate() {
+            if !self
+                .plan
+                .operation_applies(operation, &self.repository)
+                .await?
+            {
+                debug!(
+                    "skipping operation {} on this repository",
+                    operation.description()
+                );
+                continue;
+            }
+            let changed_files = self.process_operation(operation).await?;
+            files_changed |= !changed_files.is_empty();
+            let name = format!("operation {}: {}", index + 1, operation.description());
+            if self.options.dry_run {
+                self.record_operation_diff(name.clone()).await?;
+            }
+            self.operation_files.lock().unwrap().push(OperationFiles {
+                operation: name,
+                files: changed_files,
+            });
+        }
+        if !self.plan.commands.is_empty() {
+            let before = self.changed_contents().await?;
+            self.run_commands().await?;
+            if self.options.dry_run {
+                self.record_operation_diff("commands".to_owned()).await?;
+            }
+            // Commands can change files too, or undo what the processors did
+            let after = self.changed_contents().await?;
+            files_changed = !after.is_empty();
+            let mut changed_files = after
+                .iter()
+                .filter(|(path, hash)| before.get(*path) != Some(hash))
+                .chain(before.iter().filter(|(path, _)| !after.contains_key(*path)))
+                .map(|(path, _)| path.clone())
+                .collect::<Vec<_>>();
+            changed_files.sort();
+            self.operation_files.lock().unwrap().push(OperationFiles {
+                operation: "commands".to_owned(),
+                files: changed_files,
+            });
         }
         Ok(files_changed)
     }
 
-    async fn process_operation(&self, operation: &FileOperation) -> Result<bool> {
-        let files = self.list_files(&self.directory, &operation.pattern).await?;
+    /// Files that differ from the last commit, with a hash of their content or `None` when they
+    /// were deleted. Dry runs stage the changes, so the working tree is compared to the commit
+    async fn changed_contents(&self) -> Result<HashMap<String, Option<u64>>> {
+        let changed_files = self
+            .git_output(&["diff", "HEAD", "--name-only"])
+            .await
+            .wrap_err("failed to check for changes")?;
+        let mut contents = HashMap::new();
+        for path in changed_files.lines().filter(|path| !path.is_empty()) {
+            let hash = match fs::read(self.directory.join(path)).await {
+                Ok(bytes) => {
+                    let mut hasher = DefaultHasher::new();
+                    bytes.hash(&mut hasher);
+                    Some(hasher.finish())
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+                Err(err) => return Err(eyre!(err).wrap_err(format!("failed to read {}", path))),
+            };
+            contents.insert(path.to_owned(), hash);
+        }
+        Ok(contents)
+    }
+
+    /// Saves the changes since the previous operation and stages them, so the next operation's
+    /// diff only has its own changes
+    async fn record_operation_diff(&self, operation: String) -> Result<()> {
+        let arguments = self.diff_arguments(&[]);
+        let arguments = arguments.iter().map(String::as_str).collect::<Vec<_>>();
+        let diff = self
+            .git_output(&arguments)
+            .await
+            .wrap_err("failed to show changes")?;
+        if diff.is_empty() {
+            return Ok(());
+        }
+        self.git_output(&["add", "--update"])
+            .await
+            .wrap_err("failed to stage changes")?;
+        self.operation_diffs
+            .lock()
+            .unwrap()
+            .push(OperationDiff { operation, diff });
+        Ok(())
+    }
+
+    /// `git diff` with the repository's name on the paths, for diffs of many repositories
+    fn diff_arguments(&self, extra: &[&str]) -> Vec<String> {
+        let src_prefix = format!("--src-prefix=a/{}/", self.repository.name);
+        let dst_prefix = format!("--dst-prefix=b/{}/", self.repository.name);
+        let mut arguments = vec!["diff".to_owned(), src_prefix, dst_prefix];
+        if let Some(lines) = self.options.context_lines {
+            arguments.push(format!("--unified={}", lines));
+        }
+        arguments.extend(extra.iter().map(|argument| argument.to_string()));
+        arguments
+    }
+
+    #[instrument(skip(self))]
+    async fn run_commands(&self) -> Result<()> {
+        for command in &self.plan.commands {
+            debug!("running {:?}", command);
+            let child = grouped_command("sh")
+                .arg("-c")
+                .arg(command)
+                .envs(self.environment())
+                .stdin(Stdio::null())
+                .stderr(Stdio::piped())
+                .stdout(Stdio::piped())
+                .current_dir(&self.directory)
+                .spawn()?;
+            let output =
+                wait_with_limited_output(child, self.output_limit(), self.output_limit()).await?;
+            check_process(&output).wrap_err_with(|| format!("failed to run {:?}", command))?;
+        }
+        Ok(())
+    }
+
+    /// The commit message and pull request of this repository, with their templates rendered
+    pub fn render_changes(&self) -> RenderedChanges {
+        let variables = self.environment();
+        let render = |text: &str| template::render(text, &variables);
+        RenderedChanges {
+            branch_name: self.branch_name(),
+            pull_request_head: self
+                .plan
+                .pull_request_head(&self.repository, &self.branch_name()),
+            commit_message: render(&self.plan.commit_message()),
+            pull_request_title: render(&self.plan.pull_request_title()),
+            pull_request_body: self.plan.pull_request_body().map(|body| render(&body)),
+            issue_title: render(&self.plan.issue_title()),
+            issue_body: self.plan.issue_body().map(|body| render(&body)),
+        }
+    }
+
+    fn environment(&self) -> Vec<(String, String)> {
+        self.plan.environment(&self.repository)
+    }
+
+    fn branch_name(&self) -> String {
+        self.plan.branch_name(&self.repository)
+    }
+
+    /// The files it changed
+    async fn process_operation(&self, operation: &FileOperation) -> Result<Vec<String>> {
+        let files = self.list_files(&self.directory, operation).await?;
         let files = files.iter().map(|f| f.as_path()).collect::<Vec<_>>();
 
         self.process_files(&files, operation).await
     }
 
-    #[instrument(skip(self))]
+    #[instrument(skip(self, operation), fields(pattern = operation.pattern.as_str()))]
     async fn list_files(
         &self,
         directory: &Utf8Path,
-        pattern: &GlobPattern,
+        operation: &FileOperation,
     ) -> Result<Vec<Utf8PathBuf>> {
         let mut output = vec![];
-        let glob_pattern = directory.join(pattern.as_str());
+        let generated_files = if operation.skip_generated {
+            GeneratedFiles::from_repository(directory).await?
+        } else {
+            GeneratedFiles::default()
+        };
+        let submodules = if self.plan.process_submodules {
+            Submodules::default()
+        } else {
+            Submodules::from_repository(directory).await?
+        };
+        let tracked_files = if operation.tracked_only {
+            Some(self.tracked_files(directory).await?)
+        } else {
+            None
+        };
 
-        for entry in glob::glob(&glob_pattern.as_str())? {
-            let entry = entry?;
+        let entries = if operation.files.is_empty() {
+            let glob_pattern = directory.join(operation.pattern.walk_pattern());
+            glob::glob(glob_pattern.as_str())?.collect::<Result<Vec<_>, _>>()?
+        } else {
+            operation
+                .files
+                .iter()
+                .filter_map(|file| {
+                    let path = directory.join(file);
+                    if path.is_file() {
+                        Some(path.into())
+                    } else {
+                        trace!("skipping missing file {}", file);
+                        None
+                    }
+                })
+                .collect()
+        };
+        for entry in entries {
             if !entry.is_file() {
                 continue;
             }
-            output.push(Utf8PathBuf::from_path_buf(entry).unwrap());
+            let entry = Utf8PathBuf::from_path_buf(entry).unwrap();
+            if let Ok(relative_path) = entry.strip_prefix(directory) {
+                let relative_path = relative_path.as_str().replace('\\', "/");
+                if relative_path.starts_with(".git/")
+                    || (operation.files.is_empty()
+                        && !operation.pattern.matches_path(&relative_path))
+                {
+                    continue;
+                }
+                if self.options.keep_backups && relative_path.ends_with(BACKUP_SUFFIX) {
+                    trace!("skipping backup {}", relative_path);
+                    continue;
+                }
+                if tracked_files
+                    .as_ref()
+                    .is_some_and(|files| !files.contains(&relative_path))
+                {
+                    trace!("skipping untracked file {}", relative_path);
+                    continue;
+                }
+                if submodules.contains(&relative_path) {
+                    trace!("skipping submodule file {}", relative_path);
+                    continue;
+                }
+                if generated_files.is_generated(&relative_path) {
+                    trace!("skipping generated file {}", relative_path);
+                    continue;
+                }
+            }
+            if let Some(regex) = &operation.contains {
+                if !file_contains(&entry, regex).await? {
+                    continue;
+                }
+            }
+            output.push(entry);
         }
 
+        // Processing in a stable order keeps diffs reproducible between runs
+        output.sort();
+        output.dedup();
         Ok(output)
     }
 
+    /// Paths relative to `directory`, with `/` as separator
+    async fn tracked_files(&self, directory: &Utf8Path) -> Result<HashSet<String>> {
+        let _permit = self.git_permit().await?;
+        let child = self
+            .git()
+            .args(["ls-files", "-z"])
+            .stdin(Stdio::null())
+            .stderr(Stdio::piped())
+            .stdout(Stdio::piped())
+            .current_dir(directory)
+            .spawn()?;
+        let output = wait_with_limited_output(child, None, self.output_limit()).await?;
+        let output = check_process(&output).wrap_err("failed to list tracked files")?;
+        Ok(output
+            .split('\0')
+            .filter(|path| !path.is_empty())
+            .map(str::to_owned)
+            .collect())
+    }
+
     #[instrument(skip(self, files))]
-    async fn process_files(&self, files: &[&Utf8Path], operation: &FileOperation) -> Result<bool> {
-        let mut files_changed = false;
+    async fn process_files(
+        &self,
+        files: &[&Utf8Path],
+        operation: &FileOperation,
+    ) -> Result<Vec<String>> {
+        let mut changed_files = vec![];
         for file in files {
-            files_changed |= self.process_file(file, operation).await?;
+            match self.process_file(file, operation).await {
+                Ok(true) => {
+                    let path = file.strip_prefix(&self.directory).unwrap_or(file);
+                    changed_files.push(path.as_str().replace('\\', "/"));
+                }
+                Ok(false) => {}
+                Err(err) if operation.on_error == OnError::Skip => {
+                    warn!("skipping {}: {:#}", file, err);
+                    let path = file.strip_prefix(&self.directory).unwrap_or(file);
+                    self.skipped_files.lock().unwrap().push(SkippedFile {
+                        path: path.to_string(),
+                        error: format!("{:#}", err),
+                    });
+                }
+                Err(err) => return Err(err),
+            }
         }
-        Ok(files_changed)
+        Ok(changed_files)
     }
 
     #[instrument(skip(self, operation))]
     async fn process_file(&self, file: &Utf8Path, operation: &FileOperation) -> Result<bool> {
         trace!("fixing file");
-        let mut text = fs::read_to_string(file).await?;
+        let processors = self
+            .plan
+            .processors(operation)
+            .filter(|processor| processor.applies_to(file))
+            .collect::<Vec<_>>();
+        let size = fs::metadata(file).await?.len();
+        let streamable = operation.verify.is_none()
+            && operation.encoding.is_ascii_compatible()
+            && processors.iter().all(|processor| processor.line_local());
+        if size > STREAMING_THRESHOLD && streamable {
+            let new_backup = self.options.keep_backups && self.mark_backed_up(file);
+            if new_backup {
+                fs::copy(file, backup_path(file))
+                    .await
+                    .wrap_err_with(|| format!("failed to back up {}", file))?;
+            }
+            let changed = stream_file(file, operation.encoding, &processors).await?;
+            if new_backup && !changed {
+                self.backed_up_files.lock().unwrap().remove(file);
+                fs::remove_file(backup_path(file)).await?;
+            }
+            return Ok(changed);
+        }
+
+        if size > operation.max_file_size {
+            warn!(
+                "skipping {}, its {} bytes are over the max_file_size of {}",
+                file, size, operation.max_file_size
+            );
+            return Ok(false);
+        }
+        let bytes = fs::read(file).await?;
+        let mut text = match operation.encoding.decode(&bytes) {
+            Some(text) => text,
+            None => {
+                trace!("skipping binary or non {} file", operation.encoding.name());
+                return Ok(false);
+            }
+        };
         let mut changed = false;
 
-        for processor in &operation.processors {
-            changed |= processor.process(&mut text);
+        for processor in processors {
+            changed |= processor
+                .process(&mut text)
+                .wrap_err_with(|| format!("failed to process {}", file))?;
         }
 
         if !changed {
             return Ok(changed);
         }
+        if let Some(format) = operation.verify {
+            format
+                .verify(&text)
+                .wrap_err_with(|| format!("failed to verify {}", file))?;
+        }
 
-        fs::write(file, &text).await?;
+        if self.options.keep_backups && self.mark_backed_up(file) {
+            fs::write(backup_path(file), &bytes)
+                .await
+                .wrap_err_with(|| format!("failed to back up {}", file))?;
+        }
+        let bytes = operation
+            .encoding
+            .encode(&text)
+            .wrap_err_with(|| format!("failed to encode {}", file))?;
+        write_atomically(file, &bytes)
+            .await
+            .wrap_err_with(|| format!("failed to write {}", file))?;
 
         trace!("done");
         Ok(true)
     }
 
-    #[instrument(skip(self))]
-    async fn commit(&self) -> Result<()> {
-        debug!("committing");
-        let last_commit = self.git_output(&["log", "--format=%B", "-n", "1"]).await?;
-        if last_commit.starts_with(&format!("{}\n", &self.plan.git_message)) {
-            debug!("commit already done");
-            return Ok(());
-        }
-        self.git_output(&["commit", "-a", "-m", &self.plan.git_message])
-            .await
-            .wrap_err("failed to commit changes")?;
-        Ok(())
+    /// `false` when `file` already has a backup from an earlier operation of this run
+    fn mark_backed_up(&self, file: &Utf8Path) -> bool {
+        self.backed_up_files.lock().unwrap().insert(file.to_owned())
     }
 
+    /// Removes what a previous run left on a reused clone, which `reset --hard` keeps, like
+    /// `git clean -fdx`
     #[instrument(skip(self))]
-    async fn push(&self) -> Result<()> {
-        debug!("pushing");
+    async fn clean(&self) -> Result<()> {
+        #[cfg(feature = "libgit2")]
+        if self.plan.git_backend == GitBackend::Libgit2 {
+            for path in libgit2::clean(&self.directory).await? {
+                info!("removed {}", path);
+            }
+            return Ok(());
+        }
         let output = self
-            .git_output(&["push", "-u", "-f", "origin", &self.plan.branch_name])
+            .git_output(&["clean", "-fdx"])
             .await
-            .wrap_err("failed to push changes")?;
-        trace!("git: {:?}", output);
+            .wrap_err("failed to clean the clone")?;
+        for line in output.lines() {
+            info!("removed {}", line.strip_prefix("Removing ").unwrap_or(line));
+        }
         Ok(())
     }
 
-    #[instrument(skip(self))]
-    async fn open_pr(&self) -> Result<()> {
-        if self
-            .plan
-            .get_provider()
-            .is_pr_open(&self.repository.name, &self.plan.branch_name)
-            .await?
-        {
-            info!("pr already opened");
+    /// Backups stay out of `git add` through the clone's own excludes, which are never committed
+    async fn ignore_backups(&self) -> Result<()> {
+        let exclude = self.directory.join(".git").join("info").join("exclude");
+        let pattern = format!("*{}", BACKUP_SUFFIX);
+        let mut contents = match fs::read_to_string(&exclude).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err).wrap_err_with(|| format!("failed to read {}", exclude)),
+        };
+        if contents.lines().any(|line| line == pattern) {
             return Ok(());
         }
-
-        let body = self.plan.pull_request_body.as_ref().map(|b| b.as_str());
-        let title = self
-            .plan
-            .pull_request_title
-            .as_ref()
-            .unwrap_or(&&self.plan.git_message);
-
-        self.plan
-            .get_provider()
-            .open_pr(
-                &self.repository.name,
-                &self.repository.default_branch,
-                &self.plan.branch_name,
-                title.as_str(),
-                body,
-            )
-            .await?;
-        info!("done");
-        Ok(())
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            contents.push('\n');
+        }
+        contents.push_str(&pattern);
+        contents.push('\n');
+        fs::create_dir_all(exclude.parent().unwrap()).await?;
+        fs::write(&exclude, contents)
+            .await
+            .wrap_err_with(|| format!("failed to write {}", exclude))
     }
-}
 
-impl Display for PlanExecutor {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.repository.name)
+    async fn confirm(&self) -> Result<bool> {
+        let confirmation = match &self.options.confirmation {
+            Some(confirmation) => confirmation,
+            None => return Ok(true),
+        };
+        let diff = self
+            .git_output(&["diff"])
+            .await
+            .wrap_err("failed to show changes")?;
+        confirmation.confirm(&self.repository.name, &diff).await
     }
-}
-
-fn check_process(output: &Output) -> Result<String> {
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let stderr = String::from_utf8_lossy(&output.stderr);
 
-    if output.status.success() {
-        return Ok(stdout.to_string());
+    /// Whatever the processors changed, with paths prefixed by the repository name so the diffs
+    /// of many repositories can be combined
+    #[instrument(skip(self))]
+    async fn discard_changes(&self) -> Result<Outcome> {
+        let changes = async {
+            // The operations staged their changes
+            let arguments = self.diff_arguments(&["HEAD"]);
+            let arguments = arguments.iter().map(String::as_str).collect::<Vec<_>>();
+            let diff = self
+                .git_output(&arguments)
+                .await
+                .wrap_err("failed to show changes")?;
+            let diff_stat = self
+                .git_output(&["diff", "HEAD", "--shortstat"])
+                .await
+                .wrap_err("failed to compute diff stat")?;
+            // Before the reset, as the files of per_file commits are the changed ones
+            let commits = self.planned_commits().await?;
+            Ok::<_, color_eyre::Report>((diff, diff_stat, commits))
+        }
+        .await;
+        // Even when showing the changes failed, so they are not left on the clone
+        self.git_output(&["reset", "--hard"])
+            .await
+            .wrap_err("failed to discard changes")?;
+        let (diff, diff_stat, commits) = changes?;
+        let commit = self.commit_preview(&commits).await;
+        let mut outcome = Outcome::dry_run(DiffStat::from_shortstat(&diff_stat), diff, commit);
+        outcome.operation_diffs = self.operation_diffs.lock().unwrap().clone();
+        Ok(outcome)
+    }
+
+    /// The author, from the repository's git config, and the rendered message of the commit
+    /// One entry per planned commit, in the order they would be made
+    async fn commit_preview(&self, commits: &[(String, Vec<String>)]) -> String {
+        let author = match self.git_output(&["var", "GIT_AUTHOR_IDENT"]).await {
+            // The identity ends with the timestamp and the timezone
+            Ok(ident) => ident
+                .trim()
+                .rsplitn(3, ' ')
+                .last()
+                .unwrap_or_default()
+                .to_owned(),
+            Err(err) => {
+                debug!("failed to get the commit author: {:#}", err);
+                UNKNOWN_AUTHOR.to_owned()
+            }
+        };
+        commits
+            .iter()
+            .map(|(message, _)| {
+                format!(
+                    "Author: {}\n\n{}\n",
+                    author,
+                    message
+                        .trim_end()
+                        .lines()
+                        .map(|line| format!("    {}", line).trim_end().to_owned())
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Returns `false` when git finds nothing to commit, like when `.gitattributes` normalizes the
+    /// changes away
+    #[instrument(skip(self))]
+    async fn commit(&self) -> Result<bool> {
+        debug!("committing");
+        let commits = self.planned_commits().await?;
+        // A recreated branch only has commits from the default branch
+        let done = if self.plan.recreate_branch {
+            vec![]
+        } else {
+            self.branch_commit_messages().await?
+        };
+        let mut committed = false;
+        for (message, paths) in &commits {
+            if done
+                .iter()
+                .any(|done| done.trim_end() == message.trim_end())
+            {
+                debug!("commit already done");
+                committed = true;
+                continue;
+            }
+            committed |= self.commit_files(message, paths).await?;
+        }
+        if !committed && !self.plan.commit_paths.is_empty() {
+            warn!("no changes match commit_paths");
+        }
+        Ok(committed)
+    }
+
+    /// The message and the paths of each commit of the plan's `commit_strategy`, where no paths
+    /// mean all changed files
+    async fn planned_commits(&self) -> Result<Vec<(String, Vec<String>)>> {
+        let groups: Vec<(String, Vec<String>)> = match self.plan.commit_strategy {
+            CommitStrategy::Single => {
+                let message = self.render_changes().commit_message;
+                return Ok(vec![(message, self.plan.commit_paths.clone())]);
+            }
+            CommitStrategy::PerOperation => {
+                // Files changed by several operations go with the first one
+                let mut grouped = HashSet::new();
+                self.operation_files
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter_map(|operation| {
+                        let files = operation
+                            .files
+                            .iter()
+                            .filter(|file| grouped.insert(file.to_string()))
+                            .cloned()
+                            .collect::<Vec<_>>();
+                        (!files.is_empty()).then(|| (operation.operation.clone(), files))
+                    })
+                    .collect()
+            }
+            CommitStrategy::PerFile => {
+                // Including the files committed by a previous run, which are not changed anymore,
+                // so a re-run finds their commits like the other strategies
+                let changed_files = self
+                    .git_output(&["diff", &self.branch_base().await?, "--name-only"])
+                    .await
+                    .wrap_err("failed to list changed files")?;
+                changed_files
+                    .lines()
+                    .filter(|file| !file.is_empty())
+                    .map(|file| (file.to_owned(), vec![file.to_owned()]))
+                    .collect()
+            }
+        };
+        let variable = self.plan.commit_strategy.variable().unwrap_or_default();
+        let template = self.plan.split_commit_message(variable);
+        Ok(groups
+            .into_iter()
+            .map(|(name, paths)| {
+                let mut variables = self.environment();
+                variables.push((variable.to_owned(), name));
+                (template::render(&template, &variables), paths)
+            })
+            .collect())
+    }
+
+    /// What the branch was created from, the fetched default branch rather than the local one,
+    /// which is not pulled by every backend
+    async fn branch_base(&self) -> Result<String> {
+        let base_ref = self
+            .plan
+            .base_ref
+            .as_deref()
+            .unwrap_or_else(|| self.repository.default_branch());
+        self.base_ref_commit(base_ref)
+            .await?
+            .ok_or_else(|| eyre!("failed to find the base {}", base_ref))
+    }
+
+    /// Where the pull request merges into: the plan's `base_ref` when it is a branch, otherwise
+    /// the default branch
+    async fn pull_request_base(&self) -> String {
+        if let Some(base_ref) = &self.plan.base_ref {
+            let branch = format!("refs/remotes/origin/{}", base_ref);
+            if self
+                .git_output(&["rev-parse", "--verify", "--quiet", &branch])
+                .await
+                .is_ok()
+            {
+                return base_ref.clone();
+            }
+        }
+        self.repository.default_branch().to_owned()
+    }
+
+    /// Messages of the commits of the branch since it was created
+    async fn branch_commit_messages(&self) -> Result<Vec<String>> {
+        let range = format!("{}..HEAD", self.branch_base().await?);
+        let output = self
+            .git_output(&["log", "--format=%B%x00", &range])
+            .await
+            .wrap_err("failed to list the branch commits")?;
+        Ok(output
+            .split('\0')
+            .map(str::trim)
+            .filter(|message| !message.is_empty())
+            .map(str::to_owned)
+            .collect())
+    }
+
+    /// Commits `paths`, all changed tracked files when empty, `false` when nothing changed
+    async fn commit_files(&self, message: &str, paths: &[String]) -> Result<bool> {
+        #[cfg(feature = "libgit2")]
+        if self.plan.git_backend == GitBackend::Libgit2 {
+            return libgit2::commit(&self.directory, message, paths, self.plan.commit_timestamp)
+                .await;
+        }
+        if paths.is_empty() {
+            self.git_output(&["add", "--update"])
+                .await
+                .wrap_err("failed to stage changes")?;
+        } else {
+            let mut add = vec!["add", "--"];
+            add.extend(paths.iter().map(String::as_str));
+            self.git_output(&add)
+                .await
+                .wrap_err("failed to add the commit paths")?;
+        }
+        // diff --quiet exits with an error when something is staged, telling apart having nothing
+        // to commit without reading git's messages, which can be translated
+        if self
+            .git_output(&["diff", "--cached", "--quiet"])
+            .await
+            .is_ok()
+        {
+            return Ok(false);
+        }
+        self.git_commit(message).await?;
+        Ok(true)
+    }
+
+    async fn git_commit(&self, message: &str) -> Result<()> {
+        let mut environment = vec![];
+        if let Some(timestamp) = self.plan.commit_timestamp {
+            // Git's internal date format
+            let date = format!("{} +0000", timestamp);
+            environment.push(("GIT_AUTHOR_DATE", date.clone()));
+            environment.push(("GIT_COMMITTER_DATE", date));
+        }
+        // Hooks can be chatty, and git's own message is at the end
+        let output = self
+            .run_git_with_environment(&["commit", "-m", message], self.output_limit(), environment)
+            .await?;
+        check_process(&output).wrap_err("failed to commit changes")?;
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn diff_stat(&self) -> Result<DiffStat> {
+        // From the merge base, as the default branch can move on after branching
+        let range = format!("{}...{}", self.branch_base().await?, self.branch_name());
+        let output = self
+            .git_output(&["diff", "--shortstat", &range])
+            .await
+            .wrap_err("failed to compute diff stat")?;
+        Ok(DiffStat::from_shortstat(&output))
+    }
+
+    #[instrument(skip(self))]
+    async fn push(&self) -> Result<()> {
+        debug!("pushing");
+        let branch_name = self.branch_name();
+        if self.plan.force_push {
+            match self.push_branch(&branch_name, true).await {
+                Ok(()) => return self.verify_push().await,
+                // Like a protected branch, which may still take the push without force
+                Err(err) if err.downcast_ref::<PushRejected>().is_some() => {
+                    warn!("force push was rejected, retrying without force: {:#}", err)
+                }
+                Err(err) => return Err(err.wrap_err("failed to push changes")),
+            }
+        }
+        self.push_branch(&branch_name, false)
+            .await
+            .wrap_err("failed to push changes")
+            .suggestion(
+                "if the branch is protected, allow pushes to it or delete the remote branch so \
+                it can be pushed again",
+            )?;
+        self.verify_push().await
+    }
+
+    /// Fails with `PushRejected` when the remote refused the branch
+    async fn push_branch(&self, branch_name: &str, force: bool) -> Result<()> {
+        #[cfg(feature = "libgit2")]
+        if self.plan.git_backend == GitBackend::Libgit2 {
+            return libgit2::push(&self.directory, branch_name, force)
+                .await
+                .map_err(|err| {
+                    if is_push_rejection(&format!("{:#}", err)) {
+                        err.wrap_err(PushRejected)
+                    } else {
+                        err
+                    }
+                });
+        }
+        let mut args = vec!["push", "-u"];
+        if force {
+            args.push("-f");
+        }
+        args.extend(["origin", branch_name]);
+        let output = self.run_git(&args, None).await?;
+        let rejected = is_push_rejection(&String::from_utf8_lossy(&output.stderr));
+        match check_process(&output) {
+            Ok(output) => trace!("git: {:?}", output),
+            Err(err) if rejected => return Err(err.wrap_err(PushRejected)),
+            Err(err) => return Err(err),
+        }
+        Ok(())
+    }
+
+    /// A zero exit code does not guarantee that the remote branch points to our commit, e.g. when
+    /// another run or a hook rewrote it
+    #[instrument(skip(self))]
+    async fn verify_push(&self) -> Result<()> {
+        let local = self
+            .git_output(&["rev-parse", &self.branch_name()])
+            .await
+            .wrap_err("failed to get the local branch commit")?;
+        let reference = format!("refs/heads/{}", self.branch_name());
+        let remote = self
+            .git_output(&["ls-remote", "origin", &reference])
+            .await
+            .wrap_err("failed to get the remote branch commit")?;
+        let local = local.trim();
+        let remote = remote.split_whitespace().next().unwrap_or_default();
+        if local != remote {
+            return Err(eyre!("pushed branch does not match the local branch")
+                .with_section(|| format!("Local: {}", local))
+                .with_section(|| format!("Remote: {}", remote)));
+        }
+        Ok(())
+    }
+
+    /// Tags that already exist on the remote are left as they are, so re-runs do not fail
+    #[instrument(skip(self))]
+    async fn push_tag(&self) -> Result<()> {
+        let tag = match &self.plan.tag {
+            Some(tag) => template::render(tag, &self.environment()),
+            None => return Ok(()),
+        };
+        let reference = format!("refs/tags/{}", tag);
+        let remote = self
+            .git_output(&["ls-remote", "--tags", "origin", &reference])
+            .await
+            .wrap_err("failed to check the remote tag")?;
+        if remote.trim().is_empty() {
+            let message = self.render_changes().commit_message;
+            // -f replaces a local tag left by a run that failed to push it
+            self.git_output(&["tag", "-a", "-f", &tag, "-m", &message])
+                .await
+                .wrap_err_with(|| format!("failed to create tag {}", tag))?;
+            self.git_output(&["push", "origin", &reference])
+                .await
+                .wrap_err_with(|| format!("failed to push tag {}", tag))?;
+            info!("pushed tag {}", tag);
+        } else {
+            info!("tag {} already exists", tag);
+        }
+
+        if self.plan.release {
+            self.plan
+                .provider(&self.repository)
+                .create_release(&self.repository.name, &tag)
+                .await
+                .wrap_err_with(|| format!("failed to create release {}", tag))?;
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn open_pr(&self, changes: &RenderedChanges, diff_stat: &DiffStat) -> Result<()> {
+        if self
+            .plan
+            .provider(&self.repository)
+            .is_pr_open(&self.repository.name, &changes.pull_request_head)
+            .await?
+        {
+            info!("pr already opened");
+            if let Some(milestone) = &self.plan.milestone {
+                self.plan
+                    .provider(&self.repository)
+                    .set_pr_milestone(&self.repository.name, &changes.pull_request_head, milestone)
+                    .await?;
+            }
+        } else {
+            let reviewers = self.reviewers().await?;
+            let base = self.pull_request_base().await;
+            let pull_request = PullRequest {
+                base: &base,
+                head: &changes.pull_request_head,
+                title: &changes.pull_request_title,
+                body: changes.pull_request_body.as_deref(),
+                milestone: self.plan.milestone.as_ref(),
+                draft: self.plan.draft(diff_stat),
+                reviewers: &reviewers,
+                extra: &self.plan.pull_request.extra,
+            };
+            self.plan
+                .provider(&self.repository)
+                .open_pr(&self.repository.name, &pull_request)
+                .await?;
+            info!("done");
+        }
+
+        if self.plan.comment_details() {
+            let body = details_comment(&self.operation_files.lock().unwrap());
+            self.plan
+                .provider(&self.repository)
+                .comment_on_pr(
+                    &self.repository.name,
+                    &changes.pull_request_head,
+                    &PrComment {
+                        marker: DETAILS_MARKER,
+                        body: &body,
+                    },
+                )
+                .await
+                .wrap_err("failed to comment the details on the pull request")?;
+        }
+        Ok(())
+    }
+
+    /// The `CODEOWNERS` owners of the changed files when the plan asks for them, otherwise the
+    /// plan's reviewers
+    #[instrument(skip(self))]
+    async fn reviewers(&self) -> Result<Vec<String>> {
+        if !self.plan.reviewers_from_codeowners() {
+            return Ok(self.plan.reviewers().to_vec());
+        }
+        let code_owners = match CodeOwners::from_repository(&self.directory).await? {
+            Some(code_owners) => code_owners,
+            None => {
+                debug!("no CODEOWNERS found");
+                return Ok(self.plan.reviewers().to_vec());
+            }
+        };
+        let range = format!("{}...{}", self.branch_base().await?, self.branch_name());
+        let changed_files = self
+            .git_output(&["diff", "--name-only", &range])
+            .await
+            .wrap_err("failed to list changed files")?;
+        let owners = code_owners.owners(changed_files.lines());
+        if owners.is_empty() {
+            debug!("no CODEOWNERS rule matches the changed files");
+            return Ok(self.plan.reviewers().to_vec());
+        }
+        Ok(owners)
+    }
+
+    #[instrument(skip(self))]
+    async fn open_issue(&self) -> Result<Outcome> {
+        let changes = self.render_changes();
+        if self.options.dry_run {
+            info!("would open issue {:?}", changes.issue_title);
+            return Ok(Outcome::skipped(SkipReason::DryRun));
+        }
+        let issue = Issue {
+            title: &changes.issue_title,
+            body: changes.issue_body.as_deref(),
+        };
+        let opened = self
+            .plan
+            .provider(&self.repository)
+            .open_issue(&self.repository.name, &issue)
+            .await
+            .wrap_err("failed to open issue")?;
+        Ok(if opened {
+            Outcome::issue_opened()
+        } else {
+            Outcome::unchanged()
+        })
+    }
+}
+
+impl Display for PlanExecutor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.repository.name)
+    }
+}
+
+/// Where `--keep-backups` saves the original of `file`
+fn backup_path(file: &Utf8Path) -> Utf8PathBuf {
+    Utf8PathBuf::from(format!("{}{}", file, BACKUP_SUFFIX))
+}
+
+/// Git runs on its own process group, so Ctrl-C does not interrupt it halfway through a push
+fn git_command() -> Command {
+    grouped_command("git")
+}
+
+/// Processes `file` one line at a time, for large files with only line-local processors. The
+/// changes go to a temporary file that replaces `file` at the end.
+#[instrument(skip(encoding, processors))]
+async fn stream_file(
+    file: &Utf8Path,
+    encoding: FileEncoding,
+    processors: &[&FileProcessor],
+) -> Result<bool> {
+    trace!("streaming file");
+    let temporary = temporary_path(file);
+    let mut reader = BufReader::new(fs::File::open(file).await?);
+    let mut writer = BufWriter::new(fs::File::create(&temporary).await?);
+    let mut bytes = vec![];
+    let mut changed = false;
+    let result: Result<()> = async {
+        loop {
+            bytes.clear();
+            if reader.read_until(b'\n', &mut bytes).await? == 0 {
+                break;
+            }
+            let mut line = match encoding.decode(&bytes) {
+                Some(line) => line,
+                None => {
+                    trace!("skipping binary or non {} file", encoding.name());
+                    changed = false;
+                    break;
+                }
+            };
+            let mut line_changed = false;
+            for processor in processors {
+                line_changed |= processor
+                    .process(&mut line)
+                    .wrap_err_with(|| format!("failed to process {}", file))?;
+            }
+            if line_changed {
+                changed = true;
+                let encoded = encoding
+                    .encode(&line)
+                    .wrap_err_with(|| format!("failed to encode {}", file))?;
+                writer.write_all(&encoded).await?;
+            } else {
+                writer.write_all(&bytes).await?;
+            }
+        }
+        writer.flush().await?;
+        writer.get_ref().sync_all().await?;
+        Ok(())
+    }
+    .await;
+
+    if result.is_err() || !changed {
+        fs::remove_file(&temporary).await?;
+        return result.map(|_| false);
+    }
+    replace_file(&temporary, file)
+        .await
+        .wrap_err_with(|| format!("failed to replace {}", file))?;
+    trace!("done");
+    Ok(true)
+}
+
+/// Writes to a temporary file that then replaces `file`, so a crash never leaves it half written
+async fn write_atomically(file: &Utf8Path, bytes: &[u8]) -> Result<()> {
+    let temporary = temporary_path(file);
+    let result: Result<()> = async {
+        let mut writer = fs::File::create(&temporary).await?;
+        writer.write_all(bytes).await?;
+        writer.sync_all().await?;
+        Ok(())
+    }
+    .await;
+    if result.is_err() {
+        fs::remove_file(&temporary).await.ok();
+        return result;
+    }
+    replace_file(&temporary, file).await
+}
+
+/// Next to `file`, as renaming is only atomic on the same file system
+fn temporary_path(file: &Utf8Path) -> Utf8PathBuf {
+    let file_name = file.file_name().unwrap_or_default();
+    file.with_file_name(format!(".{}.there-i-fixed-it", file_name))
+}
+
+/// Renames `temporary` over `file`, keeping the permissions of `file`
+async fn replace_file(temporary: &Utf8Path, file: &Utf8Path) -> Result<()> {
+    let result = async {
+        let permissions = fs::metadata(file).await?.permissions();
+        fs::set_permissions(temporary, permissions).await?;
+        fs::rename(temporary, file).await
+    }
+    .await;
+    if result.is_err() {
+        fs::remove_file(temporary).await.ok();
+    }
+    Ok(result?)
+}
+
+/// Binary files never match
+async fn file_contains(file: &Utf8Path, regex: &Regex) -> Result<bool> {
+    let mut bytes = vec![];
+    fs::File::open(file)
+        .await?
+        .take(MAX_SCANNED_BYTES)
+        .read_to_end(&mut bytes)
+        .await
+        .wrap_err_with(|| format!("failed to read {}", file))?;
+    Ok(!bytes.contains(&0) && regex.is_match(&bytes))
+}
+
+/// The remote refused to update the branch, rather than the push failing to get there
+#[derive(Debug)]
+struct PushRejected;
+
+impl Display for PushRejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the remote rejected the push")
+    }
+}
+
+impl std::error::Error for PushRejected {}
+
+/// Whether git's or the remote's messages tell that the branch is protected or cannot be
+/// rewritten, unlike authentication or network errors
+fn is_push_rejection(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "protected branch",
+        "non-fast-forward",
+        "force-push",
+        "force push",
+    ]
+    .iter()
+    .any(|rejection| message.contains(rejection))
+}
+
+fn check_process(output: &Output) -> Result<String> {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    if output.status.success() {
+        // git writes warnings on stderr even when it succeeds
+        let warnings = stderr.trim();
+        if !warnings.is_empty() {
+            debug!("stderr: {}", warnings);
+        }
+        return Ok(stdout.to_string());
     }
 
     let err = eyre!("failed to run command")
@@ -261,34 +1731,1523 @@ fn check_process(output: &Output) -> Result<String> {
     Err(err)
 }
 
+/// The branch `HEAD` points to in `git ls-remote --symref`, whose output isn't translated unlike
+/// `git remote show`, `None` when the remote has none
+fn remote_head_branch(output: &str) -> Option<&str> {
+    output.lines().find_map(|line| {
+        let (reference, name) = line.strip_prefix("ref: ")?.split_once('\t')?;
+        if name != "HEAD" {
+            return None;
+        }
+        reference
+            .strip_prefix("refs/heads/")
+            .filter(|branch| !branch.is_empty())
+    })
+}
+
+/// Markdown listing the files each operation changed, starting with the marker
+fn details_comment(operations: &[OperationFiles]) -> String {
+    let mut body = format!(
+        "{}\nOperations that ran on this repository:\n",
+        DETAILS_MARKER
+    );
+    for operation in operations {
+        let files = match operation.files.len() {
+            0 => "no files".to_owned(),
+            1 => "1 file".to_owned(),
+            count => format!("{} files", count),
+        };
+        body.push_str(&format!("\n- `{}` changed {}", operation.operation, files));
+        for file in &operation.files {
+            body.push_str(&format!("\n  - `{}`", file));
+        }
+    }
+    body.push('\n');
+    body
+}
+
 #[cfg(test)]
 mod tests {
-    use std::{process::Stdio, sync::Arc};
+    use std::{fs, process::Stdio, sync::Arc};
 
     use camino::{Utf8Path, Utf8PathBuf};
     use tempdir::TempDir;
-    use tokio::process::Command;
+    use tokio::{process::Command, sync::Semaphore};
 
-    use crate::{plan::plan_from_file, Repository};
+    use crate::{
+        config::GlobalConfig,
+        plan::{plan_from_file, plan_from_str, Plan},
+        report::{SkipReason, Status},
+        Repository,
+    };
 
-    use super::PlanExecutor;
+    use super::{
+        backup_path, details_comment, remote_head_branch, stream_file, write_atomically,
+        ExecutorError, ExecutorOptions, OperationFiles, PlanExecutor, RepositoryLock,
+    };
     use crate::plan::executor::check_process;
 
     #[tokio::test]
     async fn test_executor_flow() {
-        crate::setup_error_handlers().ok();
+        crate::setup_error_handlers("info").ok();
         let plan_file = Utf8PathBuf::from("tests/fixtures/simple-plan.toml");
-        let plan = Arc::new(plan_from_file(&plan_file).await.unwrap());
+        let plan = Arc::new(
+            plan_from_file(&plan_file, &GlobalConfig::default())
+                .await
+                .unwrap(),
+        );
 
-        let repositories = plan.get_provider().list_repositories(false).await.unwrap();
-        assert_eq!(repositories.len(), 1);
+        let (executor, _temp) = fake_executor_of(plan, Default::default()).await;
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Changed);
+        assert_eq!(outcome.diff_stat.unwrap().files_changed, 1);
+        assert_eq!(executor.timings().len(), 6);
 
-        for repository in repositories {
-            let (repository, temp) = create_fake_repository(repository).await;
-            let path = Utf8Path::from_path(temp.path()).unwrap();
-            let executor = PlanExecutor::new(plan.clone(), repository, path);
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Skipped);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        crate::setup_error_handlers("info").ok();
+        let plan_file = Utf8PathBuf::from("tests/fixtures/simple-plan.toml");
+        let plan = Arc::new(
+            plan_from_file(&plan_file, &GlobalConfig::default())
+                .await
+                .unwrap(),
+        );
+        let options = Arc::new(ExecutorOptions {
+            dry_run: true,
+            ..Default::default()
+        });
+
+        let (executor, _temp) = fake_executor_of(plan, options).await;
+        for _ in 0..2 {
+            let outcome = executor.process().await.unwrap();
+            assert_eq!(outcome.status, Status::Changed);
+            let commit = outcome.commit.unwrap();
+            assert!(commit.starts_with("Author: "), "{}", commit);
+            assert!(commit.ends_with("\n\n    chore: Changes\n"), "{}", commit);
+            let diff = outcome.diff.unwrap();
+            assert!(diff.contains("+++ b/working-repo/file.py"));
+            assert!(diff.contains("+enabled = False"));
+        }
+        let status = executor.git_output(&["status", "--porcelain"]).await;
+        assert_eq!(status.unwrap(), "");
+        let pushed = executor
+            .git_output(&["ls-remote", "origin", "refs/heads/test"])
+            .await;
+        assert_eq!(pushed.unwrap(), "");
+    }
+
+    #[tokio::test]
+    async fn test_context_lines() {
+        crate::setup_error_handlers("info").ok();
+        let options = |context_lines| {
+            Arc::new(ExecutorOptions {
+                dry_run: true,
+                force: true,
+                context_lines,
+                ..Default::default()
+            })
+        };
+        let (executor, temp) = fake_executor(
+            r#"
+            [[files]]
+            glob = "lines.txt"
+            processors = [
+                { type = "regex", operations = [{ from = "five", to = "5" }] }
+            ]
+            "#,
+            options(None),
+        )
+        .await;
+        let setup = temp.path().join("setup");
+        fs::write(setup.join("lines.txt"), "1\n2\n3\n4\nfive\n6\n7\n8\n9\n").unwrap();
+        for args in [
+            vec!["add", "lines.txt"],
+            vec!["commit", "-m", "Add lines.txt"],
+            vec!["push", "origin", "main"],
+        ] {
+            let output = Command::new("git")
+                .args(&args)
+                .current_dir(&setup)
+                .output()
+                .await
+                .unwrap();
+            check_process(&output).unwrap();
+        }
+        let outcome = executor.process().await.unwrap();
+        let diff = outcome.diff.unwrap();
+        assert!(diff.contains("@@ -2,7 +2,7 @@"), "{}", diff);
+
+        // The second run reuses the clone of the first one
+        let executor = next_executor(&executor, options(Some(1)), &temp).await;
+        let outcome = executor.process().await.unwrap();
+        let diff = outcome.diff.unwrap();
+        assert!(
+            diff.contains("@@ -4,3 +4,3 @@\n 4\n-five\n+5\n 6\n"),
+            "{}",
+            diff
+        );
+    }
+
+    #[tokio::test]
+    async fn test_operation_files() {
+        crate::setup_error_handlers("info").ok();
+        let options = Arc::new(ExecutorOptions {
+            dry_run: true,
+            ..Default::default()
+        });
+        let (executor, _temp) = fake_executor(
+            r#"
+            commands = ["sed -i s/False/None/ file.py"]
+
+            [[files]]
+            glob = "*.py"
+            processors = [
+                { type = "regex", operations = [{ from = "True", to = "False" }] }
+            ]
+
+            [[files]]
+            glob = "*.md"
+            processors = [
+                { type = "regex", operations = [{ from = "Missing", to = "Found" }] }
+            ]
+            "#,
+            options,
+        )
+        .await;
+
+        executor.process().await.unwrap();
+        let operation_files = executor.operation_files.lock().unwrap();
+        let operation_files = operation_files
+            .iter()
+            .map(|operation| (operation.operation.as_str(), operation.files.clone()))
+            .collect::<Vec<_>>();
+        // The commands changed a file that the processors had already changed
+        assert_eq!(
+            operation_files,
+            vec![
+                ("operation 1: *.py", vec!["file.py".to_owned()]),
+                ("operation 2: *.md", vec![]),
+                ("commands", vec!["file.py".to_owned()]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_detect_default_branch() {
+        let plan = test_plan(
+            r#"
+            [[files]]
+            glob = "*.md"
+            processors = [
+                { type = "regex", operations = [{ from = "Missing", to = "Found" }] }
+            ]
+            "#,
+        );
+        let options = Arc::new(ExecutorOptions {
+            dry_run: true,
+            ..Default::default()
+        });
+        let repository = plan.list_repositories(false).await.unwrap().remove(0);
+        let (repository, temp) = create_fake_repository(repository).await;
+        let repository = Repository {
+            default_branch: String::new(),
+            ..repository
+        };
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        let executor = PlanExecutor::new(plan, options, repository, path);
+
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Unchanged);
+        assert_eq!(executor.repository.default_branch(), "main");
+    }
+
+    #[test]
+    fn test_remote_head_branch() {
+        let output = "ref: refs/heads/trunk\tHEAD
+4b825dc642cb6eb9a060e54bf8d69288fbee4904\tHEAD
+";
+        assert_eq!(remote_head_branch(output), Some("trunk"));
+        assert_eq!(
+            remote_head_branch("4b825dc642cb6eb9a060e54bf8d69288fbee4904\tHEAD\n"),
+            None
+        );
+        assert_eq!(remote_head_branch(""), None);
+    }
+
+    #[test]
+    fn test_details_comment() {
+        let operations = vec![
+            OperationFiles {
+                operation: "operation 1: **/*.py".to_owned(),
+                files: vec!["a.py".to_owned(), "src/b.py".to_owned()],
+            },
+            OperationFiles {
+                operation: "operation 2: *.md".to_owned(),
+                files: vec![],
+            },
+            OperationFiles {
+                operation: "commands".to_owned(),
+                files: vec!["Cargo.lock".to_owned()],
+            },
+        ];
+        assert_eq!(
+            details_comment(&operations),
+            "<!-- there-i-fixed-it:details -->
+Operations that ran on this repository:
+
+- `operation 1: **/*.py` changed 2 files
+  - `a.py`
+  - `src/b.py`
+- `operation 2: *.md` changed no files
+- `commands` changed 1 file
+  - `Cargo.lock`
+"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_operation_diffs() {
+        crate::setup_error_handlers("info").ok();
+        let options = Arc::new(ExecutorOptions {
+            dry_run: true,
+            ..Default::default()
+        });
+        let (executor, _temp) = fake_executor(
+            r#"
+            [[files]]
+            glob = "*.py"
+            processors = [
+                { type = "regex", operations = [{ from = "True", to = "False" }] }
+            ]
+
+            [[files]]
+            glob = "*.md"
+            processors = [
+                { type = "regex", operations = [{ from = "Missing", to = "Found" }] }
+            ]
+
+            [[files]]
+            glob = "*"
+            processors = [
+                { type = "regex", operations = [{ from = "False", to = "None" }] }
+            ]
+            "#,
+            options,
+        )
+        .await;
+
+        let outcome = executor.process().await.unwrap();
+        let diff = outcome.diff.unwrap();
+        assert!(
+            diff.contains("-enabled = True\n+enabled = None\n"),
+            "{}",
+            diff
+        );
+        // The operation without changes has no diff
+        let operation_diffs = outcome.operation_diffs;
+        assert_eq!(operation_diffs.len(), 2);
+        assert_eq!(operation_diffs[0].operation, "operation 1: *.py");
+        assert!(operation_diffs[0]
+            .diff
+            .contains("-enabled = True\n+enabled = False\n"));
+        assert_eq!(operation_diffs[1].operation, "operation 3: *");
+        assert!(operation_diffs[1]
+            .diff
+            .contains("-enabled = False\n+enabled = None\n"));
+        let status = executor.git_output(&["status", "--porcelain"]).await;
+        assert_eq!(status.unwrap(), "");
+    }
+
+    #[cfg(feature = "libgit2")]
+    #[tokio::test]
+    async fn test_libgit2_backend() {
+        crate::setup_error_handlers("info").ok();
+        let (executor, _temp) =
+            fake_executor(r#"git_backend = "libgit2""#, Default::default()).await;
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Changed);
+        assert_eq!(outcome.diff_stat.unwrap().files_changed, 1);
+    }
+
+    #[cfg(feature = "libgit2")]
+    #[tokio::test]
+    async fn test_libgit2_existing_branch() {
+        crate::setup_error_handlers("info").ok();
+        let options = Arc::new(ExecutorOptions {
+            force: true,
+            ..Default::default()
+        });
+        let (executor, _temp) = fake_executor(r#"git_backend = "libgit2""#, options).await;
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Changed);
+
+        // The branch of the earlier run is checked out again instead of failing
+        executor.git_output(&["checkout", "main"]).await.unwrap();
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Unchanged);
+        let head = executor
+            .git_output(&["rev-parse", "--abbrev-ref", "HEAD"])
+            .await
+            .unwrap();
+        assert_eq!(head.trim(), "test");
+    }
+
+    #[tokio::test]
+    async fn test_commands() {
+        crate::setup_error_handlers("info").ok();
+        let (executor, _temp) = fake_executor(
+            r#"
+            name = "commands"
+            commands = ["echo \"$REPO_NAME $BRANCH_NAME $PLAN_NAME $EXTRA\" > file.py"]
+
+            [env]
+            EXTRA = "extra"
+
+            [[files]]
+            glob = "*"
+            processors = []
+            "#,
+            Default::default(),
+        )
+        .await;
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Changed);
+        let contents = fs::read_to_string(executor.directory.join("file.py")).unwrap();
+        assert_eq!(contents, "working-repo test commands extra\n");
+    }
+
+    #[tokio::test]
+    async fn test_tag() {
+        crate::setup_error_handlers("info").ok();
+        let options = Arc::new(ExecutorOptions {
+            force: true,
+            ..Default::default()
+        });
+        let (executor, _temp) = fake_executor(
+            r#"
+            tag = "v1-{{ REPO_NAME }}"
+            release = true
+            "#,
+            options,
+        )
+        .await;
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Changed);
+        let tags = executor
+            .git_output(&["ls-remote", "--tags", "origin"])
+            .await
+            .unwrap();
+        assert!(tags.contains("refs/tags/v1-working-repo"));
+
+        // The tag already exists on the remote
+        executor.push_tag().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_on_error_skip() {
+        crate::setup_error_handlers("info").ok();
+        let (executor, _temp) = fake_executor(
+            r#"
+            [[files]]
+            glob = "*"
+            on_error = "skip"
+            processors = [
+                { type = "regex", operations = [{ from = "True", to = "False" }] },
+                { type = "apply_patch", patch = "--- a/x\n+++ b/x\n@@ -1 +1 @@\n-missing\n+line\n" },
+            ]
+            "#,
+            Default::default(),
+        )
+        .await;
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Unchanged);
+        assert_eq!(outcome.skipped_files.len(), 1);
+        assert_eq!(outcome.skipped_files[0].path, "file.py");
+    }
+
+    #[tokio::test]
+    async fn test_verify() {
+        crate::setup_error_handlers("info").ok();
+        let (executor, _temp) = fake_executor(
+            r#"
+            [[files]]
+            glob = "*"
+            on_error = "skip"
+            verify = "json"
+            processors = [
+                { type = "regex", operations = [{ from = "True", to = "False" }] },
+            ]
+            "#,
+            Default::default(),
+        )
+        .await;
+
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Unchanged);
+        assert_eq!(outcome.skipped_files.len(), 1);
+        assert!(outcome.skipped_files[0].error.contains("not valid json"));
+    }
+
+    #[tokio::test]
+    async fn test_processor_error() {
+        crate::setup_error_handlers("info").ok();
+        let (executor, _temp) = fake_executor(
+            r#"
+            [[files]]
+            glob = "*"
+            verify = "json"
+            processors = [
+                { type = "regex", operations = [{ from = "True", to = "False" }] },
+            ]
+            "#,
+            Default::default(),
+        )
+        .await;
+
+        let error = executor.process().await.unwrap_err();
+        assert!(matches!(error, ExecutorError::Processor(_)));
+        assert!(format!("{:?}", error.into_report()).contains("not valid json"));
+    }
+
+    #[tokio::test]
+    async fn test_state_error() {
+        crate::setup_error_handlers("info").ok();
+        let (executor, temp) = fake_executor("", Default::default()).await;
+        // The folder of the state cannot be created once the branch is pushed
+        fs::write(temp.path().join("state"), "").unwrap();
+
+        let error = executor.process().await.unwrap_err();
+        assert!(matches!(error, ExecutorError::State(_)));
+    }
+
+    #[tokio::test]
+    async fn test_commit_preview_without_author() {
+        let temp = TempDir::new("preview").unwrap();
+        // git cannot even start without the clone
+        let path = Utf8Path::from_path(temp.path()).unwrap().join("missing");
+        let executor = executor_in(test_plan(""), Default::default(), &path).await;
+        let commits = [("chore: Changes".to_owned(), vec![])];
+        assert_eq!(
+            executor.commit_preview(&commits).await,
+            "Author: Unknown <unknown>\n\n    chore: Changes\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ssh_command() {
+        let plan = test_plan(r#"ssh_command = "ssh -i plan-key""#);
+        let temp = TempDir::new("ssh-command").unwrap();
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        let options = Arc::new(ExecutorOptions {
+            ssh_command: Some("ssh -i cli-key".to_owned()),
+            ..Default::default()
+        });
+        let executor = executor_in(plan.clone(), options, path).await;
+        assert_eq!(executor.ssh_command(), Some("ssh -i cli-key"));
+
+        let executor = executor_in(plan, Default::default(), path).await;
+        assert_eq!(executor.ssh_command(), Some("ssh -i plan-key"));
+    }
+
+    #[tokio::test]
+    async fn test_render_changes() {
+        let plan = r#"
+            branch_name = "test"
+            git_message = "chore: Fix {{ REPO_NAME }}"
+            pull_request_body = "Targets {{ DEFAULT_BRANCH }} of {{TEAM}}"
+            repositories = ["*"]
+
+            [env]
+            TEAM = "platform"
+
+            [pull_request]
+            head = "{{TEAM}}:{{ BRANCH_NAME }}"
+            heads = { other-repo = "elsewhere:{{ BRANCH_NAME }}" }
+
+            [provider]
+            name = "test"
+
+            [[files]]
+            processors = []
+        "#;
+        let plan = Arc::new(plan_from_str(plan, &GlobalConfig::default()).unwrap());
+        let temp = TempDir::new("render").unwrap();
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        let executor = executor_in(plan, Default::default(), path).await;
+
+        let changes = executor.render_changes();
+        assert_eq!(changes.branch_name, "test");
+        assert_eq!(changes.pull_request_head, "platform:test");
+        assert_eq!(changes.commit_message, "chore: Fix working-repo");
+        assert_eq!(changes.pull_request_title, "chore: Fix working-repo");
+        assert_eq!(
+            changes.pull_request_body.unwrap(),
+            "Targets main of platform"
+        );
+        assert_eq!(changes.issue_title, "chore: Fix working-repo");
+        assert_eq!(changes.issue_body.unwrap(), "Targets main of platform");
+    }
+
+    #[tokio::test]
+    async fn test_action_issue() {
+        let plan = test_plan(
+            r#"
+            action = "issue"
+
+            [issue]
+            title = "Upgrade {{ REPO_NAME }} to the new runtime"
+            "#,
+        );
+        let temp = TempDir::new("issue").unwrap();
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        let executor = executor_in(plan, Default::default(), path).await;
+
+        let changes = executor.render_changes();
+        assert_eq!(
+            changes.issue_title,
+            "Upgrade working-repo to the new runtime"
+        );
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::IssueOpened);
+        assert!(!executor.directory.exists());
+    }
+
+    #[tokio::test]
+    async fn test_commit_paths() {
+        crate::setup_error_handlers("info").ok();
+        let (executor, _temp) = fake_executor(
+            r#"
+            commit_paths = ["*.py", "generated.txt"]
+            commands = ["echo new > generated.txt", "echo ignored > notes.md"]
+            "#,
+            Default::default(),
+        )
+        .await;
+
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Changed);
+        let files = executor
+            .git_output(&["show", "--name-only", "--format=", "HEAD"])
+            .await
+            .unwrap();
+        assert_eq!(files, "file.py\ngenerated.txt\n");
+    }
+
+    #[tokio::test]
+    async fn test_commit_paths_without_changes() {
+        crate::setup_error_handlers("info").ok();
+        let (executor, _temp) = fake_executor(
+            r#"
+            commit_paths = ["file.py"]
+            commands = ["echo ignored > notes.md"]
+
+            [[files]]
+            glob = "*"
+            processors = []
+            "#,
+            Default::default(),
+        )
+        .await;
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Unchanged);
+    }
+
+    #[tokio::test]
+    async fn test_nothing_to_commit() {
+        crate::setup_error_handlers("info").ok();
+        let plan = Arc::new(
+            plan_from_str(
+                include_str!("../../tests/fixtures/simple-plan.toml"),
+                &GlobalConfig::default(),
+            )
+            .unwrap(),
+        );
+        let (executor, _temp) = fake_executor_of(plan, Default::default()).await;
+
+        // The processor changes file.py but git no longer looks at it
+        executor.clone_repository().await.unwrap();
+        executor
+            .git_output(&["update-index", "--assume-unchanged", "file.py"])
+            .await
+            .unwrap();
+        let outcome = executor.apply().await.unwrap();
+        assert_eq!(outcome.status, Status::Unchanged);
+        let message = executor
+            .git_output(&["log", "--format=%s", "-n", "1"])
+            .await
+            .unwrap();
+        assert_ne!(message.trim_end(), "chore: Changes");
+    }
+
+    #[tokio::test]
+    async fn test_no_branch_without_changes() {
+        crate::setup_error_handlers("info").ok();
+        let (executor, _temp) = fake_executor(
+            r#"
+            [[files]]
+            glob = "*"
+            processors = [
+                { type = "regex", operations = [{ from = "Missing", to = "Found" }] }
+            ]
+            "#,
+            Default::default(),
+        )
+        .await;
+
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Unchanged);
+        let remote_branches = executor
+            .git_output(&["ls-remote", "--heads", "origin", "test"])
+            .await
+            .unwrap();
+        assert_eq!(remote_branches, "");
+        let local_branches = executor
+            .git_output(&["branch", "--list", "test"])
+            .await
+            .unwrap();
+        assert_eq!(local_branches, "");
+    }
+
+    #[tokio::test]
+    async fn test_keep_backups() {
+        crate::setup_error_handlers("info").ok();
+        let options = Arc::new(ExecutorOptions {
+            keep_backups: true,
+            ..Default::default()
+        });
+        let (executor, _temp) = fake_executor(
+            r#"
+            commit_paths = ["."]
+
+            [[files]]
+            glob = "*"
+            processors = [
+                { type = "regex", operations = [{ from = "True", to = "False" }] }
+            ]
+
+            [[files]]
+            glob = "*"
+            processors = [
+                { type = "regex", operations = [{ from = "False", to = "None" }] }
+            ]
+            "#,
+            options,
+        )
+        .await;
+
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Changed);
+        let file = executor.directory.join("file.py");
+        assert_eq!(fs::read_to_string(&file).unwrap(), "enabled = None\n");
+        // The backup has the file before the first operation and is not committed
+        assert_eq!(
+            fs::read_to_string(backup_path(&file)).unwrap(),
+            "enabled = True\n"
+        );
+        let files = executor
+            .git_output(&["show", "--name-only", "--format=", "HEAD"])
+            .await
+            .unwrap();
+        assert_eq!(files, "file.py\n");
+    }
+
+    #[tokio::test]
+    async fn test_reviewers_from_codeowners() {
+        crate::setup_error_handlers("info").ok();
+        let (executor, temp) = fake_executor(
+            r#"
+            [pull_request]
+            reviewers = ["fallback"]
+            reviewers_from_codeowners = true
+            "#,
+            Default::default(),
+        )
+        .await;
+
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Changed);
+        assert_eq!(executor.reviewers().await.unwrap(), vec!["fallback"]);
+
+        fs::create_dir(executor.directory.join(".github")).unwrap();
+        fs::write(
+            executor.directory.join(".github/CODEOWNERS"),
+            "* @org/everyone\n*.py @org/python\n*.md @org/docs\n",
+        )
+        .unwrap();
+        assert_eq!(executor.reviewers().await.unwrap(), vec!["org/python"]);
+
+        // Changes of the default branch after branching are not the branch's
+        let setup = temp.path().join("setup");
+        fs::write(setup.join("README.md"), "# Readme\n").unwrap();
+        for args in [
+            vec!["add", "README.md"],
+            vec!["commit", "-m", "Add README.md"],
+            vec!["push", "origin", "main"],
+        ] {
+            let output = Command::new("git")
+                .args(&args)
+                .current_dir(&setup)
+                .output()
+                .await
+                .unwrap();
+            check_process(&output).unwrap();
+        }
+        executor
+            .git_output(&["fetch", "origin", "main:main"])
+            .await
+            .unwrap();
+        assert_eq!(executor.reviewers().await.unwrap(), vec!["org/python"]);
+        assert_eq!(executor.diff_stat().await.unwrap().files_changed, 1);
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics() {
+        let options = Arc::new(ExecutorOptions {
+            dry_run: true,
+            ..Default::default()
+        });
+        let (executor, _temp) = fake_executor("", options).await;
+        assert!(executor.diagnostics().await.is_none());
+
+        executor.process().await.unwrap();
+        let diagnostics = executor.diagnostics().await.unwrap();
+        assert_eq!(diagnostics.branch, "main");
+        assert!(diagnostics.status.contains("On branch main"));
+        assert!(diagnostics.log.ends_with("Initial commit"));
+    }
+
+    #[tokio::test]
+    async fn test_commit_strategy() {
+        crate::setup_error_handlers("info").ok();
+        for (strategy, messages) in [
+            (
+                "per_file",
+                "chore: Changes (other.py)\nchore: Changes (file.py)\n",
+            ),
+            (
+                "per_operation",
+                "chore: Changes (operation 2: other.py)\nchore: Changes (operation 1: *.py)\n",
+            ),
+        ] {
+            let plan = format!(
+                r#"
+                commit_strategy = "{}"
+
+                [[files]]
+                glob = "*.py"
+                processors = [
+                    {{ type = "regex", operations = [{{ from = "enabled = True", to = "enabled = False" }}] }}
+                ]
+
+                [[files]]
+                glob = "other.py"
+                processors = [
+                    {{ type = "regex", operations = [{{ from = "other", to = "changed" }}] }}
+                ]
+                "#,
+                strategy
+            );
+            let options = Arc::new(ExecutorOptions {
+                dry_run: true,
+                ..Default::default()
+            });
+            let (executor, temp) = fake_executor(&plan, options).await;
+            let setup = temp.path().join("setup");
+            fs::write(setup.join("other.py"), "other = 1\n").unwrap();
+            for args in [
+                vec!["add", "other.py"],
+                vec!["commit", "-m", "Add other.py"],
+                vec!["push", "origin", "main"],
+            ] {
+                let output = Command::new("git")
+                    .args(&args)
+                    .current_dir(&setup)
+                    .output()
+                    .await
+                    .unwrap();
+                check_process(&output).unwrap();
+            }
+
+            // The dry run shows the commits that are then made
+            let outcome = executor.process().await.unwrap();
+            let preview = outcome.commit.unwrap();
+            assert_eq!(preview.matches("Author: ").count(), 2, "{}", preview);
+            for message in messages.lines() {
+                assert!(
+                    preview.contains(&format!("    {}\n", message)),
+                    "{}",
+                    preview
+                );
+            }
+
+            let executor = next_executor(&executor, Default::default(), &temp).await;
+            let outcome = executor.process().await.unwrap();
+            assert_eq!(outcome.status, Status::Changed);
+            let log = executor
+                .git_output(&["log", "--format=%s", "main..HEAD"])
+                .await
+                .unwrap();
+            assert_eq!(log, messages);
+
+            // The commits already on the branch are not made again, and the files were committed
+            let committed = executor.commit().await.unwrap();
+            assert!(committed);
+            let log = executor
+                .git_output(&["log", "--format=%s", "main..HEAD"])
+                .await
+                .unwrap();
+            assert_eq!(log, messages);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_pr() {
+        crate::setup_error_handlers("info").ok();
+        let (executor, _temp) = fake_executor(
+            r#"
+            open_pr = false
+            "#,
+            Default::default(),
+        )
+        .await;
+
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Changed);
+        let pushed_branch = outcome.pushed_branch.unwrap();
+        assert_eq!(pushed_branch.name, "test");
+        assert_eq!(
+            pushed_branch.url,
+            "https://example.com/working-repo/tree/test"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_clean() {
+        crate::setup_error_handlers("info").ok();
+        for backend in git_backends() {
+            let plan = format!(
+                r#"
+                git_backend = "{}"
+
+                [[files]]
+                glob = "*.md"
+                processors = [
+                    {{ type = "regex", operations = [{{ from = "Missing", to = "Found" }}] }}
+                ]
+                "#,
+                backend
+            );
+            let (executor, temp) = fake_executor(&plan, Default::default()).await;
             executor.process().await.unwrap();
+
+            let leftovers = [
+                executor.directory.join("leftover.md"),
+                executor.directory.join("build").join("output.log"),
+                executor.directory.join("vendor").join("leftover.md"),
+            ];
+            fs::create_dir_all(executor.directory.join("build")).unwrap();
+            fs::write(executor.directory.join(".gitignore"), "build/\n").unwrap();
+            for leftover in &leftovers[..2] {
+                fs::write(leftover, "Missing").unwrap();
+            }
+            // Like `git clean -fdx`, nested repositories are kept
+            let nested = executor.directory.join("vendor").join("nested");
+            fs::create_dir_all(&nested).unwrap();
+            fs::write(&leftovers[2], "Missing").unwrap();
+            fs::write(nested.join("kept.md"), "Missing").unwrap();
+            let init = std::process::Command::new("git")
+                .args(["init", "-q"])
+                .current_dir(&nested)
+                .status()
+                .unwrap();
+            assert!(init.success());
+
+            let options = Arc::new(ExecutorOptions {
+                force: true,
+                clean: true,
+                ..Default::default()
+            });
+            let executor = next_executor(&executor, options, &temp).await;
+            let outcome = executor.process().await.unwrap();
+            assert_eq!(outcome.status, Status::Unchanged, "{}", backend);
+            for leftover in &leftovers {
+                assert!(!leftover.exists(), "{}: {}", backend, leftover);
+            }
+            assert!(nested.join("kept.md").exists(), "{}", backend);
+            assert!(executor.directory.join("file.py").exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commit_timestamp() {
+        crate::setup_error_handlers("info").ok();
+        let (executor, _temp) = fake_executor(
+            r#"
+            commit_timestamp = 1700000000
+            "#,
+            Default::default(),
+        )
+        .await;
+
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Changed);
+        let dates = executor
+            .git_output(&["log", "--format=%at %ct", "-n", "1"])
+            .await
+            .unwrap();
+        assert_eq!(dates, "1700000000 1700000000\n");
+    }
+
+    #[tokio::test]
+    async fn test_base_ref() {
+        crate::setup_error_handlers("info").ok();
+        for backend in git_backends() {
+            let plan = format!("base_ref = \"v1\"\ngit_backend = \"{}\"", backend);
+            let (executor, temp) = fake_executor(&plan, Default::default()).await;
+
+            let outcome = executor.process().await.unwrap();
+            assert_eq!(outcome.status, Status::Skipped);
+            assert_eq!(outcome.skip_reason, Some(SkipReason::MissingBaseRef));
+
+            // The default branch moves on after the tag
+            let setup = temp.path().join("setup");
+            for args in [
+                vec!["tag", "v1"],
+                vec!["commit", "--allow-empty", "-m", "Second commit"],
+                vec!["push", "origin", "main", "v1"],
+            ] {
+                let output = Command::new("git")
+                    .args(&args)
+                    .current_dir(&setup)
+                    .output()
+                    .await
+                    .unwrap();
+                check_process(&output).unwrap();
+            }
+            let outcome = executor.process().await.unwrap();
+            assert_eq!(outcome.status, Status::Changed);
+            let parent = executor
+                .git_output(&["log", "--format=%s", "-n", "1", "HEAD~1"])
+                .await
+                .unwrap();
+            assert_eq!(parent, "Initial commit\n");
+            // Pull requests of a tag still go to the default branch
+            assert_eq!(executor.pull_request_base().await, "main");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_base_ref_prefers_remote() {
+        crate::setup_error_handlers("info").ok();
+        for backend in git_backends() {
+            let options = Arc::new(ExecutorOptions {
+                dry_run: true,
+                ..Default::default()
+            });
+            let plan = format!("base_ref = \"release\"\ngit_backend = \"{}\"", backend);
+            let (executor, temp) = fake_executor(&plan, options).await;
+            let git = |directory: std::path::PathBuf, args: Vec<&'static str>| async move {
+                let output = Command::new("git")
+                    .args(&args)
+                    .current_dir(directory)
+                    .output()
+                    .await
+                    .unwrap();
+                check_process(&output).unwrap();
+            };
+            let setup = temp.path().join("setup");
+            git(setup.clone(), vec!["push", "origin", "main:release"]).await;
+            executor.process().await.unwrap();
+
+            // A local branch from an earlier run stays behind the remote one
+            git(
+                executor.directory.clone().into(),
+                vec!["branch", "release", "origin/release"],
+            )
+            .await;
+            git(
+                setup.clone(),
+                vec!["commit", "--allow-empty", "-m", "Release commit"],
+            )
+            .await;
+            git(setup, vec!["push", "origin", "HEAD:release"]).await;
+
+            let executor = next_executor(&executor, Default::default(), &temp).await;
+            let outcome = executor.process().await.unwrap();
+            assert_eq!(outcome.status, Status::Changed);
+            let parent = executor
+                .git_output(&["log", "--format=%s", "-n", "1", "HEAD~1"])
+                .await
+                .unwrap();
+            assert_eq!(parent, "Release commit\n");
+            assert_eq!(executor.pull_request_base().await, "release");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_push_without_force() {
+        crate::setup_error_handlers("info").ok();
+        let (executor, _temp) = fake_executor("force_push = false", Default::default()).await;
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Changed);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_push_retry_without_force() {
+        use std::os::unix::fs::PermissionsExt;
+
+        crate::setup_error_handlers("info").ok();
+        for (message, attempts) in [("protected branch", 2), ("Permission denied", 1)] {
+            let (executor, temp) = fake_executor("", Default::default()).await;
+            // The remote refuses the first push with the message
+            let hook = temp.path().join("destination.git/hooks/pre-receive");
+            fs::write(
+                &hook,
+                format!(
+                    "#!/bin/sh\necho >> attempts\n\
+                    if [ \"$(wc -l < attempts)\" -eq 1 ]; then echo '{}' >&2; exit 1; fi\n",
+                    message
+                ),
+            )
+            .unwrap();
+            fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+
+            let outcome = executor.process().await;
+            assert_eq!(outcome.is_ok(), attempts == 2, "{}", message);
+            let pushes = fs::read_to_string(temp.path().join("destination.git/attempts")).unwrap();
+            assert_eq!(pushes.lines().count(), attempts, "{}", message);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_git_jobs() {
+        let semaphore = Arc::new(Semaphore::new(1));
+        let options = Arc::new(ExecutorOptions {
+            git_jobs: Some(semaphore.clone()),
+            ..Default::default()
+        });
+        let (executor, _temp) = fake_executor("", options).await;
+
+        // A single permit is enough when each git command releases it
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Changed);
+        assert_eq!(semaphore.available_permits(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_locked_repository() {
+        let temp = TempDir::new("locked").unwrap();
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        let executor = executor_in(test_plan(""), Default::default(), path).await;
+
+        let _lock = RepositoryLock::try_acquire(&executor.lock_file)
+            .await
+            .unwrap()
+            .unwrap();
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Skipped);
+        assert!(!executor.directory.exists());
+    }
+
+    #[tokio::test]
+    async fn test_recreate_branch() {
+        crate::setup_error_handlers("info").ok();
+        let options = Arc::new(ExecutorOptions {
+            force: true,
+            ..Default::default()
+        });
+        let (executor, _temp) = fake_executor("recreate_branch = true", options).await;
+        for _ in 0..2 {
+            let outcome = executor.process().await.unwrap();
+            assert_eq!(outcome.status, Status::Changed);
         }
+        let commits = executor
+            .git_output(&["rev-list", "--count", "main..test"])
+            .await
+            .unwrap();
+        assert_eq!(commits.trim(), "1");
+    }
+
+    #[tokio::test]
+    async fn test_require_ci() {
+        crate::setup_error_handlers("info").ok();
+        let (executor, _temp) = fake_executor("require_ci = true", Default::default()).await;
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Skipped);
+
+        fs::write(executor.directory.join(".gitlab-ci.yml"), "").unwrap();
+        assert!(executor.has_ci());
+    }
+
+    #[tokio::test]
+    async fn test_require_merged_branch() {
+        crate::setup_error_handlers("info").ok();
+        let plan = test_plan(r#"require_merged_branch = "unmerged-branch""#);
+        let temp = TempDir::new("unmerged").unwrap();
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        let executor = executor_in(plan, Default::default(), path).await;
+        let outcome = executor.process().await.unwrap();
+        assert_eq!(outcome.status, Status::Skipped);
+        assert!(!path.join("repos").exists());
+    }
+
+    #[tokio::test]
+    async fn test_empty_repository() {
+        crate::setup_error_handlers("info").ok();
+        let plan_file = Utf8PathBuf::from("tests/fixtures/simple-plan.toml");
+        let plan = Arc::new(
+            plan_from_file(&plan_file, &GlobalConfig::default())
+                .await
+                .unwrap(),
+        );
+        let temp = TempDir::new("empty-repository").unwrap();
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        let remote = path.join("empty.git");
+        let output = Command::new("git")
+            .args(["init", "--bare", remote.as_str()])
+            .output()
+            .await
+            .unwrap();
+        check_process(&output).unwrap();
+
+        let repositories = plan.list_repositories(false).await.unwrap();
+        for repository in repositories {
+            let repository = Repository {
+                ssh_url: remote.to_string(),
+                ..repository
+            };
+            let executor = PlanExecutor::new(plan.clone(), Default::default(), repository, path);
+            let outcome = executor.process().await.unwrap();
+            assert_eq!(outcome.status, Status::Empty);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_explicit_files() {
+        let plan = test_plan(
+            r#"
+            [[files]]
+            files = ["sub/b.toml", "a.txt", "missing.txt", "a.txt"]
+            processors = []
+            "#,
+        );
+        let temp = TempDir::new("explicit").unwrap();
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        fs::create_dir_all(path.join("sub")).unwrap();
+        fs::write(path.join("a.txt"), "a").unwrap();
+        fs::write(path.join("sub/b.toml"), "b").unwrap();
+        fs::write(path.join("other.txt"), "other").unwrap();
+
+        let executor = executor_in(plan.clone(), Default::default(), path).await;
+        let files = executor
+            .list_files(path, &plan.file_operations[0])
+            .await
+            .unwrap();
+        assert_eq!(files, vec![path.join("a.txt"), path.join("sub/b.toml")]);
+    }
+
+    #[tokio::test]
+    async fn test_list_files_by_content() {
+        let plan = test_plan(
+            r#"
+            [[files]]
+            contains = "old_api\\("
+            processors = []
+            "#,
+        );
+        let temp = TempDir::new("content").unwrap();
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        fs::create_dir_all(path.join(".git")).unwrap();
+        fs::create_dir_all(path.join("src")).unwrap();
+        fs::write(path.join("src/main.c"), "old_api();").unwrap();
+        fs::write(path.join("README"), "new_api();").unwrap();
+        fs::write(path.join("binary"), b"\0old_api(").unwrap();
+        fs::write(path.join(".git/config"), "old_api(").unwrap();
+
+        let executor = executor_in(plan.clone(), Default::default(), path).await;
+        let files = executor
+            .list_files(path, &plan.file_operations[0])
+            .await
+            .unwrap();
+        assert_eq!(files, vec![path.join("src/main.c")]);
+    }
+
+    #[tokio::test]
+    async fn test_list_files_sorted() {
+        let plan = test_plan(
+            r#"
+            [[files]]
+            processors = []
+            "#,
+        );
+        let temp = TempDir::new("sorted").unwrap();
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        fs::create_dir_all(path.join("a/b")).unwrap();
+        for file in ["c.txt", "a/b/z.txt", "a.txt", "a/y.txt", "B.txt"] {
+            fs::write(path.join(file), "").unwrap();
+        }
+
+        let executor = executor_in(plan.clone(), Default::default(), path).await;
+        let files = executor
+            .list_files(path, &plan.file_operations[0])
+            .await
+            .unwrap();
+        let files = files
+            .iter()
+            .map(|file| file.strip_prefix(path).unwrap().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(files, ["B.txt", "a/b/z.txt", "a/y.txt", "a.txt", "c.txt"]);
+    }
+
+    #[tokio::test]
+    async fn test_max_file_size() {
+        let plan = test_plan(
+            r#"
+            [[files]]
+            processors = [{ type = "regex", operations = [{ from = "1", to = "one" }] }]
+            max_file_size = 4
+            "#,
+        );
+        let temp = TempDir::new("max-size").unwrap();
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        fs::write(path.join("small.txt"), "1234").unwrap();
+        fs::write(path.join("large.txt"), "12345").unwrap();
+
+        let executor = executor_in(plan.clone(), Default::default(), path).await;
+        let operation = &plan.file_operations[0];
+        assert!(executor
+            .process_file(&path.join("small.txt"), operation)
+            .await
+            .unwrap());
+        assert!(!executor
+            .process_file(&path.join("large.txt"), operation)
+            .await
+            .unwrap());
+        assert_eq!(fs::read_to_string(path.join("large.txt")).unwrap(), "12345");
+    }
+
+    #[tokio::test]
+    async fn test_stream_over_max_file_size() {
+        let plan = test_plan(
+            r#"
+            [[files]]
+            processors = [{ type = "trim_whitespace" }]
+            "#,
+        );
+        let temp = TempDir::new("stream-max-size").unwrap();
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        let file = path.join("large.log");
+        let line = "a line with trailing spaces  \n";
+        let lines = (11 * 1024 * 1024) / line.len() + 1;
+        fs::write(&file, line.repeat(lines)).unwrap();
+
+        let executor = executor_in(plan.clone(), Default::default(), path).await;
+        let operation = &plan.file_operations[0];
+        assert!(fs::metadata(&file).unwrap().len() > operation.max_file_size);
+        assert!(executor.process_file(&file, operation).await.unwrap());
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            "a line with trailing spaces\n".repeat(lines)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_files_tracked_only() {
+        let plan = test_plan(
+            r#"
+            [[files]]
+            processors = []
+            tracked_only = true
+            "#,
+        );
+        let temp = TempDir::new("tracked-only").unwrap();
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        fs::create_dir(path.join("src")).unwrap();
+        fs::write(path.join("src/tracked.txt"), "tracked").unwrap();
+        for args in [&["init"][..], &["add", "src/tracked.txt"]] {
+            let output = Command::new("git")
+                .args(args)
+                .current_dir(path)
+                .output()
+                .await
+                .unwrap();
+            check_process(&output).unwrap();
+        }
+        fs::write(path.join("untracked.txt"), "untracked").unwrap();
+
+        let executor = executor_in(plan.clone(), Default::default(), path).await;
+        let files = executor
+            .list_files(path, &plan.file_operations[0])
+            .await
+            .unwrap();
+        assert_eq!(files, [path.join("src/tracked.txt")]);
+    }
+
+    #[tokio::test]
+    async fn test_list_files_skips_submodules() {
+        let plan = test_plan(
+            r#"
+            [[files]]
+            processors = []
+            "#,
+        );
+        let temp = TempDir::new("submodules").unwrap();
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        fs::create_dir_all(path.join("vendor/lib")).unwrap();
+        fs::write(
+            path.join(".gitmodules"),
+            "[submodule \"lib\"]\n\tpath = vendor/lib\n\turl = ../lib.git\n",
+        )
+        .unwrap();
+        fs::write(path.join("vendor/lib/lib.txt"), "").unwrap();
+        fs::write(path.join("vendor/own.txt"), "").unwrap();
+
+        let executor = executor_in(plan.clone(), Default::default(), path).await;
+        let files = executor
+            .list_files(path, &plan.file_operations[0])
+            .await
+            .unwrap();
+        let files = files
+            .iter()
+            .map(|file| file.strip_prefix(path).unwrap().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(files, [".gitmodules", "vendor/own.txt"]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_file() {
+        let plan = r##"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            repositories = ["*"]
+
+            [provider]
+            name = "test"
+
+            [[files]]
+            processors = [
+                { type = "trim_whitespace" },
+                { type = "comment", pattern = "^debug", prefix = "#" },
+            ]
+        "##;
+        let plan = plan_from_str(plan, &GlobalConfig::default()).unwrap();
+        let operation = &plan.file_operations[0];
+        let processors = plan.processors(operation).collect::<Vec<_>>();
+        assert!(processors.iter().all(|processor| processor.line_local()));
+        let temp = TempDir::new("stream").unwrap();
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        let file = path.join("large.cfg");
+        fs::write(&file, "a = 1  \r\ndebug = true\nlast").unwrap();
+
+        assert!(stream_file(&file, operation.encoding, &processors)
+            .await
+            .unwrap());
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            "a = 1\r\n# debug = true\nlast"
+        );
+        assert!(!stream_file(&file, operation.encoding, &processors)
+            .await
+            .unwrap());
+        assert_eq!(fs::read_dir(path).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_write_atomically() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = TempDir::new("atomic").unwrap();
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        let file = path.join("script.sh");
+        fs::write(&file, "old").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).unwrap();
+
+        write_atomically(&file, b"new").await.unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new");
+        let mode = fs::metadata(&file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+        assert_eq!(fs::read_dir(path).unwrap().count(), 1);
+    }
+
+    /// The plan of the tests with the keys and tables of `delta`, and the operation turning
+    /// `True` into `False` on every file unless `delta` has its own `[[files]]`
+    fn test_plan(delta: &str) -> Arc<Plan> {
+        let files = if delta.contains("[[files]]") {
+            ""
+        } else {
+            r#"
+            [[files]]
+            glob = "*"
+            processors = [
+                { type = "regex", operations = [{ from = "True", to = "False" }] }
+            ]
+            "#
+        };
+        let plan = format!(
+            r#"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            repositories = ["*"]
+            {}
+
+            [provider]
+            name = "test"
+            {}
+            "#,
+            delta, files
+        );
+        Arc::new(plan_from_str(&plan, &GlobalConfig::default()).unwrap())
+    }
+
+    /// An executor of `plan` on the repository of the test provider, cloned under `folder`
+    async fn executor_in(
+        plan: Arc<Plan>,
+        options: Arc<ExecutorOptions>,
+        folder: &Utf8Path,
+    ) -> PlanExecutor {
+        let repository = plan.list_repositories(false).await.unwrap().remove(0);
+        PlanExecutor::new(plan, options, repository, folder)
+    }
+
+    /// An executor of `test_plan(delta)` on a fake repository, which lives as long as the folder
+    async fn fake_executor(delta: &str, options: Arc<ExecutorOptions>) -> (PlanExecutor, TempDir) {
+        fake_executor_of(test_plan(delta), options).await
+    }
+
+    async fn fake_executor_of(
+        plan: Arc<Plan>,
+        options: Arc<ExecutorOptions>,
+    ) -> (PlanExecutor, TempDir) {
+        let repository = plan.list_repositories(false).await.unwrap().remove(0);
+        let (repository, temp) = create_fake_repository(repository).await;
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        let executor = PlanExecutor::new(plan, options, repository, path);
+        (executor, temp)
+    }
+
+    /// The plan's `git_backend` values that the tests can use
+    fn git_backends() -> Vec<&'static str> {
+        let mut backends = vec!["cli"];
+        if cfg!(feature = "libgit2") {
+            backends.push("libgit2");
+        }
+        backends
+    }
+
+    /// Another executor of the same plan and clone, like the one of a later run
+    async fn next_executor(
+        executor: &PlanExecutor,
+        options: Arc<ExecutorOptions>,
+        temp: &TempDir,
+    ) -> PlanExecutor {
+        let path = Utf8Path::from_path(temp.path()).unwrap();
+        executor_in(executor.plan.clone(), options, path).await
     }
 
     async fn create_fake_repository(repository: Repository) -> (Repository, TempDir) {
@@ -304,7 +3263,7 @@ mod tests {
         let command = Command::new(bash_command)
             .arg("-x")
             .arg(&setup)
-            .arg(&temp.path())
+            .arg(temp.path())
             .stdin(Stdio::null())
             .stderr(Stdio::piped())
             .stdout(Stdio::piped())
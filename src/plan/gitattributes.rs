@@ -0,0 +1,138 @@
+use camino::Utf8Path;
+use color_eyre::{eyre::Context, Result};
+use glob::{MatchOptions, Pattern};
+use tokio::fs;
+
+const GENERATED_ATTRIBUTE: &str = "linguist-generated";
+
+/// Paths marked as generated on a repository's `.gitattributes`
+#[derive(Debug, Default)]
+pub struct GeneratedFiles {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    pattern: Pattern,
+    /// Patterns with a `/` match from the root of the repository, the others match file names
+    anchored: bool,
+    generated: bool,
+}
+
+impl GeneratedFiles {
+    pub async fn from_repository(directory: &Utf8Path) -> Result<Self> {
+        let path = directory.join(".gitattributes");
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read_to_string(&path)
+            .await
+            .wrap_err_with(|| format!("failed to read {}", path))?;
+        Ok(Self::parse(&contents))
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let mut rules = vec![];
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let pattern = match parts.next() {
+                Some(pattern) => pattern,
+                None => continue,
+            };
+            let generated = parts.filter_map(generated_value).next_back();
+            let generated = match generated {
+                Some(generated) => generated,
+                None => continue,
+            };
+            let anchored = pattern.contains('/');
+            let pattern = pattern.trim_start_matches('/');
+            let pattern = if pattern.ends_with('/') {
+                format!("{}**", pattern)
+            } else {
+                pattern.to_owned()
+            };
+            if let Ok(pattern) = Pattern::new(&pattern) {
+                rules.push(Rule {
+                    pattern,
+                    anchored,
+                    generated,
+                });
+            }
+        }
+        Self { rules }
+    }
+
+    /// `relative_path` is relative to the root of the repository and uses `/` as separator
+    pub fn is_generated(&self, relative_path: &str) -> bool {
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        let file_name = relative_path.rsplit('/').next().unwrap_or(relative_path);
+        // Like git, the last matching line wins
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                if rule.anchored {
+                    rule.pattern.matches_with(relative_path, options)
+                } else {
+                    rule.pattern.matches_with(file_name, options)
+                }
+            })
+            .map(|rule| rule.generated)
+            .unwrap_or(false)
+    }
+}
+
+fn generated_value(attribute: &str) -> Option<bool> {
+    if attribute == GENERATED_ATTRIBUTE {
+        return Some(true);
+    }
+    if let Some(unset) = attribute.strip_prefix('-') {
+        return if unset == GENERATED_ATTRIBUTE {
+            Some(false)
+        } else {
+            None
+        };
+    }
+    match attribute.split_once('=') {
+        Some((GENERATED_ATTRIBUTE, value)) => Some(value != "false"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GeneratedFiles;
+
+    #[test]
+    fn test_is_generated() {
+        let generated = GeneratedFiles::parse(
+            r#"
+# comments are ignored
+*.py text eol=lf
+*.pb.go linguist-generated
+/vendor/** linguist-generated=true
+vendor/keep.go -linguist-generated
+docs/ linguist-generated
+api/*.json linguist-generated=false
+/*.min.js linguist-generated
+"#,
+        );
+
+        assert!(generated.is_generated("api.pb.go"));
+        assert!(generated.is_generated("deep/inside/api.pb.go"));
+        assert!(generated.is_generated("vendor/lib/code.go"));
+        assert!(!generated.is_generated("vendor/keep.go"));
+        assert!(generated.is_generated("docs/index.md"));
+        assert!(!generated.is_generated("api/schema.json"));
+        assert!(!generated.is_generated("main.py"));
+        assert!(generated.is_generated("app.min.js"));
+        assert!(!generated.is_generated("static/app.min.js"));
+    }
+}
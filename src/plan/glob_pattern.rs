@@ -1,17 +1,88 @@
+use color_eyre::{eyre::eyre, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use serde::{de::Visitor, Deserialize, Deserializer};
 
+/// How the plan's glob patterns are interpreted
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GlobEngine {
+    /// The `glob` crate, where `*` also matches dotfiles and there is no brace expansion
+    #[default]
+    Glob,
+    /// `.gitignore` rules, with brace expansion and patterns without `/` matching at any depth
+    Gitignore,
+}
+
 #[derive(Debug)]
-pub struct GlobPattern(glob::Pattern);
+pub struct GlobPattern {
+    source: String,
+    matcher: Option<Matcher>,
+}
+
+#[derive(Debug)]
+enum Matcher {
+    Glob(glob::Pattern),
+    Gitignore(Gitignore),
+}
 
 impl GlobPattern {
     pub fn new(pattern: glob::Pattern) -> Self {
-        Self(pattern)
+        Self {
+            source: pattern.as_str().to_owned(),
+            matcher: Some(Matcher::Glob(pattern)),
+        }
+    }
+    /// Matches every file, recursively
+    pub fn all() -> Self {
+        Self::new(glob::Pattern::new("**/*").unwrap())
+    }
+    /// Patterns are only usable after being compiled, which happens when the plan is parsed
+    pub fn compile(&mut self, engine: GlobEngine) -> Result<()> {
+        let matcher = match engine {
+            GlobEngine::Glob => Matcher::Glob(
+                glob::Pattern::new(&self.source)
+                    .map_err(|e| eyre!("failed to parse glob {:?}: {}", self.source, e))?,
+            ),
+            GlobEngine::Gitignore => {
+                let mut builder = GitignoreBuilder::new("");
+                builder
+                    .add_line(None, &self.source)
+                    .map_err(|e| eyre!("failed to parse glob {:?}: {}", self.source, e))?;
+                Matcher::Gitignore(builder.build()?)
+            }
+        };
+        self.matcher = Some(matcher);
+        Ok(())
     }
     pub fn matches(&self, name: &str) -> bool {
-        self.0.matches(name)
+        match self.matcher() {
+            Matcher::Glob(pattern) => pattern.matches(name),
+            Matcher::Gitignore(gitignore) => gitignore.matched(name, false).is_ignore(),
+        }
+    }
+    /// Glob used to walk a directory, its files still have to be checked with `matches_path`
+    pub fn walk_pattern(&self) -> &str {
+        match self.matcher() {
+            Matcher::Glob(pattern) => pattern.as_str(),
+            Matcher::Gitignore(_) => "**/*",
+        }
+    }
+    /// Whether a file found with `walk_pattern` matches, relative to the walked directory
+    pub fn matches_path(&self, relative_path: &str) -> bool {
+        match self.matcher() {
+            Matcher::Glob(_) => true,
+            Matcher::Gitignore(gitignore) => gitignore
+                .matched_path_or_any_parents(relative_path, false)
+                .is_ignore(),
+        }
     }
     pub fn as_str(&self) -> &str {
-        self.0.as_str()
+        &self.source
+    }
+    fn matcher(&self) -> &Matcher {
+        self.matcher
+            .as_ref()
+            .expect("glob patterns are compiled when the plan is parsed")
     }
 }
 
@@ -27,13 +98,10 @@ impl<'de> Visitor<'de> for GlobPatternVisitor {
     where
         E: serde::de::Error,
     {
-        match glob::Pattern::new(v) {
-            Ok(glob) => Ok(GlobPattern::new(glob)),
-            Err(e) => {
-                let msg = format!("failed to parse glob {:?}", e);
-                Err(E::custom(msg))
-            }
-        }
+        Ok(GlobPattern {
+            source: v.to_owned(),
+            matcher: None,
+        })
     }
 }
 
@@ -45,3 +113,40 @@ impl<'de> Deserialize<'de> for GlobPattern {
         deserializer.deserialize_any(GlobPatternVisitor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{GlobEngine, GlobPattern};
+
+    fn compile(source: &str, engine: GlobEngine) -> GlobPattern {
+        let mut pattern = GlobPattern::all();
+        pattern.source = source.to_owned();
+        pattern.compile(engine).unwrap();
+        pattern
+    }
+
+    #[test]
+    fn test_gitignore_engine() {
+        let pattern = compile("*.{yml,yaml}", GlobEngine::Gitignore);
+        assert_eq!(pattern.walk_pattern(), "**/*");
+        assert!(pattern.matches_path("ci.yml"));
+        assert!(pattern.matches_path(".github/workflows/ci.yaml"));
+        assert!(!pattern.matches_path("ci.toml"));
+
+        let pattern = compile("/docs/", GlobEngine::Gitignore);
+        assert!(pattern.matches_path("docs/index.md"));
+        assert!(!pattern.matches_path("src/docs/index.md"));
+
+        let pattern = compile("python-*", GlobEngine::Gitignore);
+        assert!(pattern.matches("python-tool"));
+        assert!(!pattern.matches("rust-tool"));
+    }
+
+    #[test]
+    fn test_glob_engine() {
+        let pattern = compile("*.{yml,yaml}", GlobEngine::Glob);
+        assert_eq!(pattern.walk_pattern(), "*.{yml,yaml}");
+        assert!(!pattern.matches("ci.yml"));
+        assert!(compile("*-rs", GlobEngine::Glob).matches("abc-rs"));
+    }
+}
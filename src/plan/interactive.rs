@@ -0,0 +1,50 @@
+use std::{
+    io::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use color_eyre::Result;
+use tokio::{sync::Mutex, task};
+
+/// Asks the user to confirm the changes of one repository at a time
+#[derive(Debug, Default)]
+pub struct Confirmation {
+    lock: Mutex<()>,
+    yes_to_all: AtomicBool,
+}
+
+impl Confirmation {
+    pub async fn confirm(&self, repository_name: &str, diff: &str) -> Result<bool> {
+        let _guard = self.lock.lock().await;
+        if self.yes_to_all.load(Ordering::SeqCst) {
+            return Ok(true);
+        }
+
+        println!("{}", diff);
+        let question = format!(
+            "Commit, push and open a pull request for {}? [y]es/[n]o/[a]ll: ",
+            repository_name
+        );
+        loop {
+            let question = question.clone();
+            let answer = task::spawn_blocking(move || -> io::Result<String> {
+                print!("{}", question);
+                io::stdout().flush()?;
+                let mut answer = String::new();
+                io::stdin().read_line(&mut answer)?;
+                Ok(answer)
+            })
+            .await??;
+
+            match answer.trim().to_lowercase().as_str() {
+                "y" | "yes" => return Ok(true),
+                "n" | "no" | "" => return Ok(false),
+                "a" | "all" => {
+                    self.yes_to_all.store(true, Ordering::SeqCst);
+                    return Ok(true);
+                }
+                _ => continue,
+            }
+        }
+    }
+}
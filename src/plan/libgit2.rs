@@ -0,0 +1,299 @@
+//! Clone, branch, commit and push through libgit2 instead of the `git` command
+
+use camino::Utf8Path;
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use git2::{
+    build::{CheckoutBuilder, RepoBuilder},
+    BranchType, Cred, CredentialType, FetchOptions, IndexAddOption, PushOptions, RemoteCallbacks,
+    Repository, Signature, Status, StatusOptions, SubmoduleUpdateOptions, Time,
+};
+use tokio::task;
+
+const MAX_CREDENTIAL_ATTEMPTS: usize = 3;
+
+pub async fn clone(url: &str, directory: &Utf8Path, recurse_submodules: bool) -> Result<()> {
+    let url = url.to_owned();
+    let directory = directory.to_owned();
+    blocking(move || {
+        let mut fetch_options = FetchOptions::new();
+        fetch_options.remote_callbacks(callbacks());
+        let repository = RepoBuilder::new()
+            .fetch_options(fetch_options)
+            .clone(&url, directory.as_ref())?;
+        if recurse_submodules {
+            for mut submodule in repository.submodules()? {
+                let mut options = SubmoduleUpdateOptions::new();
+                let mut fetch_options = FetchOptions::new();
+                fetch_options.remote_callbacks(callbacks());
+                options.fetch(fetch_options);
+                submodule.update(true, Some(&mut options))?;
+            }
+        }
+        Ok(())
+    })
+    .await
+    .wrap_err("failed to clone repository")
+}
+
+/// Checks out `base_ref` or the latest `default_branch`, detached, unless `branch` already exists
+/// and should not be recreated, in which case it is checked out. `false` when `base_ref` does
+/// not exist
+pub async fn checkout_base(
+    directory: &Utf8Path,
+    default_branch: &str,
+    base_ref: Option<&str>,
+    branch: &str,
+    recreate: bool,
+) -> Result<bool> {
+    let directory = directory.to_owned();
+    let default_branch = default_branch.to_owned();
+    let base_ref = base_ref.map(str::to_owned);
+    let branch = branch.to_owned();
+    blocking(move || {
+        let repository = Repository::open(&directory)?;
+        if !recreate {
+            let head = repository.head()?;
+            if head.is_branch() && head.shorthand() == Some(branch.as_str()) {
+                return Ok(true);
+            }
+            // Reused before anything moves, like the branch of an earlier run
+            if repository.find_branch(&branch, BranchType::Local).is_ok() {
+                repository.set_head(&format!("refs/heads/{}", branch))?;
+                repository.checkout_head(Some(CheckoutBuilder::new().force()))?;
+                return Ok(true);
+            }
+        }
+
+        let mut refspecs = vec![default_branch.clone()];
+        if let Some(base_ref) = &base_ref {
+            // A base branch or tag created after the clone
+            refspecs.push(format!("+refs/heads/{0}:refs/remotes/origin/{0}", base_ref));
+            refspecs.push("+refs/tags/*:refs/tags/*".to_owned());
+        }
+        let mut fetch_options = FetchOptions::new();
+        fetch_options.remote_callbacks(callbacks());
+        repository
+            .find_remote("origin")?
+            .fetch(&refspecs, Some(&mut fetch_options), None)?;
+        let default_base = repository
+            .find_branch(&format!("origin/{}", default_branch), BranchType::Remote)?
+            .get()
+            .peel_to_commit()?;
+        let base = match &base_ref {
+            Some(base_ref) => {
+                // The fetched branch first, as a local one left by an earlier run can be stale
+                let found = [format!("origin/{}", base_ref), base_ref.clone()]
+                    .iter()
+                    .find_map(|candidate| repository.revparse_single(candidate).ok());
+                match found {
+                    Some(object) => object.peel_to_commit()?,
+                    None => return Ok(false),
+                }
+            }
+            None => default_base.clone(),
+        };
+
+        // Branches can only be moved while they are not checked out
+        repository.set_head_detached(base.id())?;
+        repository.checkout_head(Some(CheckoutBuilder::new().force()))?;
+        repository.branch(&default_branch, &default_base, true)?;
+        Ok(true)
+    })
+    .await
+    .wrap_err("failed to checkout base")
+}
+
+/// Removes the untracked and ignored files, like `git clean -fdx`, returning their paths. Like
+/// it, nested repositories are kept
+pub async fn clean(directory: &Utf8Path) -> Result<Vec<String>> {
+    let directory = directory.to_owned();
+    blocking(move || {
+        let repository = Repository::open(&directory)?;
+        let mut options = StatusOptions::new();
+        options
+            .include_untracked(true)
+            .include_ignored(true)
+            .recurse_untracked_dirs(false)
+            .recurse_ignored_dirs(false);
+        let mut removed = vec![];
+        for entry in repository.statuses(Some(&mut options))?.iter() {
+            if !entry.status().intersects(Status::WT_NEW | Status::IGNORED) {
+                continue;
+            }
+            if let Some(path) = entry.path() {
+                remove_untracked(&directory, path.trim_end_matches('/'), &mut removed)?;
+            }
+        }
+        Ok(removed)
+    })
+    .await
+    .wrap_err("failed to clean the clone")
+}
+
+/// Whether `path` is gone, as directories with nested repositories are only emptied around them
+fn remove_untracked(
+    directory: &Utf8Path,
+    path: &str,
+    removed: &mut Vec<String>,
+) -> std::io::Result<bool> {
+    let full_path = directory.join(path);
+    if !full_path.symlink_metadata()?.is_dir() {
+        std::fs::remove_file(&full_path)?;
+        removed.push(path.to_owned());
+        return Ok(true);
+    }
+    if full_path.join(".git").exists() {
+        return Ok(false);
+    }
+    let mut children = vec![];
+    let mut emptied = true;
+    for entry in full_path.read_dir()? {
+        let child = format!("{}/{}", path, entry?.file_name().to_string_lossy());
+        emptied &= remove_untracked(directory, &child, &mut children)?;
+    }
+    if emptied {
+        std::fs::remove_dir(&full_path)?;
+        removed.push(format!("{}/", path));
+    } else {
+        removed.extend(children);
+    }
+    Ok(emptied)
+}
+
+/// Creates `branch` on the checked out commit and checks it out, keeping the changes of the
+/// working tree. Does nothing when it is already checked out
+pub async fn create_branch(directory: &Utf8Path, branch: &str) -> Result<()> {
+    let directory = directory.to_owned();
+    let branch = branch.to_owned();
+    blocking(move || {
+        let repository = Repository::open(&directory)?;
+        let head = repository.head()?;
+        if head.is_branch() && head.shorthand() == Some(branch.as_str()) {
+            return Ok(());
+        }
+        let commit = head.peel_to_commit()?;
+        repository.branch(&branch, &commit, true)?;
+        repository.set_head(&format!("refs/heads/{}", branch))?;
+        Ok(())
+    })
+    .await
+    .wrap_err("failed to create branch")
+}
+
+/// Commits the files matching `paths`, or all changes to tracked files when empty, like
+/// `git commit -a`. Returns `false` when there is nothing to commit. `timestamp` replaces the
+/// current time of the signature, in UTC
+pub async fn commit(
+    directory: &Utf8Path,
+    message: &str,
+    paths: &[String],
+    timestamp: Option<i64>,
+) -> Result<bool> {
+    let directory = directory.to_owned();
+    let message = message.to_owned();
+    let paths = paths.to_owned();
+    blocking(move || {
+        let repository = Repository::open(&directory)?;
+        let mut index = repository.index()?;
+        if paths.is_empty() {
+            index.update_all(["*"], None)?;
+        } else {
+            // Deleted files are only staged by updating
+            index.update_all(&paths, None)?;
+            index.add_all(&paths, IndexAddOption::DEFAULT, None)?;
+        }
+        index.write()?;
+        let tree = repository.find_tree(index.write_tree()?)?;
+        let parent = repository.head()?.peel_to_commit()?;
+        if tree.id() == parent.tree_id() {
+            return Ok(false);
+        }
+        let mut signature = repository.signature()?;
+        if let Some(timestamp) = timestamp {
+            signature = Signature::new(
+                signature.name().unwrap_or_default(),
+                signature.email().unwrap_or_default(),
+                &Time::new(timestamp, 0),
+            )?;
+        }
+        repository.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            &message,
+            &tree,
+            &[&parent],
+        )?;
+        Ok(true)
+    })
+    .await
+    .wrap_err("failed to commit changes")
+}
+
+pub async fn push(directory: &Utf8Path, branch: &str, force: bool) -> Result<()> {
+    let directory = directory.to_owned();
+    let branch = branch.to_owned();
+    blocking(move || {
+        let repository = Repository::open(&directory)?;
+        let mut rejection = None;
+        let mut callbacks = callbacks();
+        callbacks.push_update_reference(|reference, status| {
+            if let Some(status) = status {
+                rejection = Some(format!("{} was rejected: {}", reference, status));
+            }
+            Ok(())
+        });
+        let mut push_options = PushOptions::new();
+        push_options.remote_callbacks(callbacks);
+
+        let refspec = format!(
+            "{}refs/heads/{}:refs/heads/{}",
+            if force { "+" } else { "" },
+            branch,
+            branch
+        );
+        repository
+            .find_remote("origin")?
+            .push(&[refspec.as_str()], Some(&mut push_options))?;
+        drop(push_options);
+        match rejection {
+            Some(rejection) => Err(eyre!(rejection)),
+            None => Ok(()),
+        }
+    })
+    .await
+    .wrap_err("failed to push changes")
+}
+
+/// SSH keys come from the agent and passwords from git's credential helpers
+fn callbacks<'a>() -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    let mut attempts = 0;
+    callbacks.credentials(move |url, username, allowed| {
+        attempts += 1;
+        if attempts > MAX_CREDENTIAL_ATTEMPTS {
+            return Err(git2::Error::from_str("authentication failed"));
+        }
+        if allowed.contains(CredentialType::SSH_KEY) {
+            return Cred::ssh_key_from_agent(username.unwrap_or("git"));
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            let config = git2::Config::open_default()?;
+            return Cred::credential_helper(&config, url, username);
+        }
+        Cred::default()
+    });
+    callbacks
+}
+
+/// libgit2 blocks, so it runs outside of the async workers
+async fn blocking<F, T>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    task::spawn_blocking(f).await?
+}
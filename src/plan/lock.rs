@@ -0,0 +1,82 @@
+use std::fs::{File, OpenOptions, TryLockError};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use tokio::{fs, task};
+
+/// Exclusive lock on a repository's clone, released when dropped or when the process exits, so
+/// runs that overlap never touch the same directory at the same time
+#[derive(Debug)]
+pub struct RepositoryLock {
+    _file: File,
+}
+
+impl RepositoryLock {
+    /// `None` when another process holds the lock
+    pub async fn try_acquire(path: &Utf8Path) -> Result<Option<Self>> {
+        let file = open(path).await?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(Self { _file: file })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(err)) => {
+                Err(eyre!(err).wrap_err(format!("failed to lock {}", path)))
+            }
+        }
+    }
+
+    /// Waits until other processes release the lock
+    pub async fn acquire(path: &Utf8Path) -> Result<Self> {
+        let file = open(path).await?;
+        let path = path.to_owned();
+        task::spawn_blocking(move || {
+            file.lock()
+                .wrap_err_with(|| format!("failed to lock {}", path))?;
+            Ok(Self { _file: file })
+        })
+        .await?
+    }
+}
+
+async fn open(path: &Utf8Path) -> Result<File> {
+    let folder = path
+        .parent()
+        .ok_or_else(|| eyre!("failed to determine lock folder"))?;
+    fs::create_dir_all(folder).await?;
+    let path: Utf8PathBuf = path.to_owned();
+    task::spawn_blocking(move || {
+        OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .wrap_err_with(|| format!("failed to open lock {}", path))
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8Path;
+    use tempdir::TempDir;
+
+    use super::RepositoryLock;
+
+    #[tokio::test]
+    async fn test_lock() {
+        let temp = TempDir::new("lock").unwrap();
+        let path = Utf8Path::from_path(temp.path())
+            .unwrap()
+            .join("locks/repo.lock");
+
+        let lock = RepositoryLock::try_acquire(&path).await.unwrap();
+        assert!(lock.is_some());
+        assert!(RepositoryLock::try_acquire(&path).await.unwrap().is_none());
+
+        drop(lock);
+        assert!(RepositoryLock::try_acquire(&path).await.unwrap().is_some());
+        RepositoryLock::acquire(&path).await.unwrap();
+    }
+}
@@ -1,95 +1,909 @@
+pub mod codeowners;
+pub mod encoding;
+pub mod error;
 pub mod executor;
+pub mod gitattributes;
 pub mod glob_pattern;
+pub mod interactive;
+#[cfg(feature = "libgit2")]
+mod libgit2;
+pub mod lock;
+pub mod output;
+pub mod process;
+pub mod processors;
+pub mod state;
+pub mod submodules;
+pub mod template;
+pub mod verify;
 
-use std::borrow::Cow;
+use std::collections::BTreeMap;
 
-use camino::Utf8Path;
-use color_eyre::{eyre::Context, Result};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use color_eyre::{
+    eyre::{eyre, Context},
+    Help, Report, Result,
+};
+use lazy_static::lazy_static;
 use regex::Regex;
-use serde::Deserialize;
-use tokio::fs;
+use serde::{de, Deserialize, Deserializer};
+use serde_json::Map;
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs,
+    io::{AsyncRead, AsyncReadExt},
+};
+use toml::{value::Table, Value};
 use tracing::instrument;
 
-use crate::providers::{GithubProvider, Provider};
+use crate::{
+    config::GlobalConfig,
+    providers::{GithubProvider, Milestone, Provider},
+    report::{DiffStat, PlanIdentity},
+    Repository,
+};
 
-pub use self::executor::PlanExecutor;
-use self::glob_pattern::GlobPattern;
+use self::{
+    encoding::FileEncoding,
+    glob_pattern::{GlobEngine, GlobPattern},
+    processors::FileProcessor,
+    verify::FileFormat,
+};
+pub use self::{
+    error::ExecutorError,
+    executor::{ExecutorOptions, PlanExecutor},
+};
 
 #[cfg(test)]
 use crate::providers::tests::TestProvider;
 
+const FIXED_BY_TRAILER: &str = "X-Fixed-By";
+/// The variables of each repository that `branch_name` can use besides `[env]`
+const REPOSITORY_VARIABLES: [&str; 3] = ["REPO_NAME", "DEFAULT_BRANCH", "PLAN_NAME"];
+
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Plan {
+    /// Defaults to the plan's file name
+    name: Option<String>,
+    /// It can use the variables of `[env]` and `--plan-var`, like `bump-{{ VERSION }}`, and of
+    /// the repository, like `{{ REPO_NAME }}`
     branch_name: String,
+    /// Prepended to `branch_name`, like `bot/`, to keep automated branches under a namespace
+    branch_prefix: Option<String>,
+    /// The commit message, also the default pull request title. Like `pull_request_title` and
+    /// `pull_request_body`, it can use the variables of `commands` like `{{ REPO_NAME }}`
     git_message: String,
+    /// Add trailers identifying the commits made by this tool
+    #[serde(default)]
+    git_trailer: bool,
+    /// Pathspecs, like `src/**` or `Cargo.toml`, of the files added to the commit, which also
+    /// includes new files. Without them, the commit has all changed files that git tracks
+    #[serde(default)]
+    commit_paths: Vec<String>,
+    /// Recreate the branch from the default branch on every run, so it always has a single commit
+    #[serde(default)]
+    recreate_branch: bool,
+    /// How the changes are split into commits
+    #[serde(default)]
+    commit_strategy: CommitStrategy,
+    /// Message of each commit of the `per_operation` and `per_file` strategies, which can also use
+    /// `{{ OPERATION }}` or `{{ FILE }}` respectively. Defaults to `git_message` with the operation
+    /// or the file after its first line
+    commit_message_template: Option<String>,
+    /// Author and committer date of the commits as seconds since the Unix epoch, in UTC, so runs
+    /// with the same changes make the same commits
+    commit_timestamp: Option<i64>,
+    /// Tag, branch or commit the branch is created from instead of the latest default branch.
+    /// Repositories without it are skipped
+    base_ref: Option<String>,
+    /// Which git implementation clones, branches, commits and pushes
+    #[serde(default)]
+    git_backend: GitBackend,
+    /// Sets `GIT_SSH_COMMAND`, like `ssh -i ~/.ssh/deploy-key -o IdentitiesOnly=yes`, to choose the
+    /// SSH key of the git commands
+    ssh_command: Option<String>,
+    /// Disable for repositories where branch protection blocks force pushes
+    #[serde(default = "default_force_push")]
+    force_push: bool,
+    /// Disable to only push the branches, for teams that open the pull requests on their own
+    #[serde(default = "default_open_pr")]
+    open_pr: bool,
     pull_request_title: Option<String>,
     pull_request_body: Option<String>,
-    #[serde(rename = "files")]
+    /// Files relative to the plan, added before and after the pull request body
+    pull_request_body_header_file: Option<Utf8PathBuf>,
+    pull_request_body_footer_file: Option<Utf8PathBuf>,
+    #[serde(skip)]
+    pull_request_body_header: Option<String>,
+    #[serde(skip)]
+    pull_request_body_footer: Option<String>,
+    /// Milestone set on the pull requests, by title or number
+    milestone: Option<Milestone>,
+    #[serde(default)]
+    pull_request: PullRequestOptions,
+    /// Whether repositories get pull requests with the changes, issues asking for them, or both
+    #[serde(default)]
+    pub action: Action,
+    #[serde(default)]
+    issue: IssueOptions,
+    /// Annotated tag created on the commit and pushed with the branch, it can use the variables of
+    /// `commands` like `v1-{{ REPO_NAME }}`
+    tag: Option<String>,
+    /// Create a release from `tag` on providers that support it
+    #[serde(default)]
+    release: bool,
+    /// Only optional with `action = "issue"`, which changes nothing
+    #[serde(rename = "files", default)]
     file_operations: Vec<FileOperation>,
-    provider: PlanProvider,
+    /// Shell commands run on the repository after the processors, like formatters
+    #[serde(default)]
+    commands: Vec<String>,
+    /// Extra environment for `commands` and git hooks, besides `REPO_NAME`, `DEFAULT_BRANCH`,
+    /// `BRANCH_NAME` and `PLAN_NAME`, also used by templates. `--plan-var` overrides them
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    /// Processors that run on every operation, besides the operation's own processors
+    #[serde(default)]
+    default_processors: Vec<FileProcessor>,
+    #[serde(default)]
+    default_processors_position: ProcessorsPosition,
+    /// A single `[provider]` or a list of `[[provider]]` whose repositories are merged
+    #[serde(rename = "provider", deserialize_with = "deserialize_providers")]
+    providers: Vec<PlanProvider>,
+    /// How the `glob` of files and the repository filters are interpreted
+    #[serde(default)]
+    glob_engine: GlobEngine,
     #[serde(rename = "repositories")]
     /// There is no default just to be explicit and avoid applying changes on all repositories
     repository_allow_filters: Vec<GlobPattern>,
     #[serde(rename = "deny_repositories", default)]
     repository_deny_filters: Vec<GlobPattern>,
+    /// Names of the repositories to process, like `name` or `owner/name`, fetched one by one
+    /// instead of listing all repositories before the filters
+    #[serde(default)]
+    pub repository_names: Vec<String>,
+    /// Search query on the provider's syntax, like `org:foo language:rust archived:false`, used
+    /// instead of listing all repositories before the filters
+    repository_query: Option<String>,
+    /// Only repositories with one of these languages, like `Rust`, ignoring case
+    #[serde(default)]
+    languages: Vec<String>,
+    #[serde(default)]
+    language_match: LanguageMatch,
+    /// Exact names of repositories that are never touched
+    #[serde(default)]
+    repository_exclude: Vec<String>,
+    /// Clone submodules and process their files, which are skipped by default
+    #[serde(default)]
+    process_submodules: bool,
+    /// Skip repositories without CI configuration, where pull requests would not be checked
+    #[serde(default)]
+    require_ci: bool,
+    /// Only process repositories where the pull request of this branch, with its full name, was merged
+    require_merged_branch: Option<String>,
+    /// How many repositories are processed at the same time
+    pub concurrency: Option<usize>,
+    /// Where repositories are cloned, defaults to the cache folder
+    pub repositories_folder: Option<Utf8PathBuf>,
+    #[serde(skip)]
+    checksum: String,
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(tag = "name", rename_all = "snake_case")]
 pub enum PlanProvider {
-    Github(GithubProvider),
+    Github(Box<GithubProvider>),
     #[cfg(test)]
     Test(TestProvider),
 }
 
+impl PlanProvider {
+    fn as_provider(&self) -> &dyn Provider {
+        match self {
+            PlanProvider::Github(provider) => provider.as_ref(),
+            #[cfg(test)]
+            PlanProvider::Test(provider) => provider,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommitStrategy {
+    /// All changes on a single commit
+    #[default]
+    Single,
+    /// A commit with the files changed by each operation, and another one for the commands
+    PerOperation,
+    /// A commit for each changed file
+    PerFile,
+}
+
+impl CommitStrategy {
+    /// The variable naming each commit in the messages of the strategy
+    pub fn variable(self) -> Option<&'static str> {
+        match self {
+            Self::Single => None,
+            Self::PerOperation => Some("OPERATION"),
+            Self::PerFile => Some("FILE"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GitBackend {
+    /// The `git` command
+    #[default]
+    Cli,
+    /// libgit2, needs the `libgit2` feature
+    Libgit2,
+}
+
+/// The plan's `[pull_request]` table
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PullRequestOptions {
+    /// Fields forwarded as-is to the provider when opening pull requests, like `maintainer_can_modify`
+    #[serde(default)]
+    extra: Map<String, serde_json::Value>,
+    /// Prepended as is to the title, like `chore(deps): `, unless the title already starts with it
+    title_prefix: Option<String>,
+    /// Open pull requests with more inserted and deleted lines than this as drafts, so they get
+    /// reviewed instead of merged right away
+    draft_if_lines_changed_over: Option<u64>,
+    /// Head of the pull requests as `owner:branch`, like `staging:{{ BRANCH_NAME }}` for changes
+    /// that go through a shared fork, regardless of where the branch was pushed
+    head: Option<String>,
+    /// `head` of some repositories by their name, for the ones whose fork is elsewhere
+    #[serde(default)]
+    heads: BTreeMap<String, String>,
+    /// Users or `org/team` teams asked to review the pull requests
+    #[serde(default)]
+    reviewers: Vec<String>,
+    /// Ask the `CODEOWNERS` owners of the changed files to review instead, falling back to
+    /// `reviewers` when the repository has no `CODEOWNERS` or none of its rules match
+    #[serde(default)]
+    reviewers_from_codeowners: bool,
+    /// Comment on the pull requests with the operations that ran and the files each changed,
+    /// keeping the body for the plan's own description
+    #[serde(default)]
+    comment_details: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    #[default]
+    PullRequest,
+    /// Only open an issue, without cloning or changing the repository
+    Issue,
+    PullRequestAndIssue,
+}
+
+/// Which languages of a repository are compared with the plan's `languages`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LanguageMatch {
+    /// The primary language, which comes with the list of repositories
+    #[default]
+    Primary,
+    /// Any language of the repository, which needs a request per repository
+    Any,
+}
+
+/// The plan's `[issue]` table, which can use the variables of `commands` like `{{ REPO_NAME }}`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IssueOptions {
+    /// Defaults to the pull request title
+    title: Option<String>,
+    /// Defaults to the pull request body
+    body: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct FileOperation {
-    #[serde(rename = "glob")]
+    /// Defaults to all files of the repository
+    #[serde(rename = "glob", default = "GlobPattern::all")]
     pattern: GlobPattern,
-    processors: Vec<Processor>,
+    /// Paths relative to the repository root selected instead of globbing, the ones missing from
+    /// a repository are skipped
+    #[serde(default)]
+    files: Vec<String>,
+    /// Only select the files whose content matches this regex
+    #[serde(default, with = "serde_regex")]
+    contains: Option<regex::bytes::Regex>,
+    processors: Vec<FileProcessor>,
+    /// Skip files marked as `linguist-generated` on the repository's `.gitattributes`
+    #[serde(default)]
+    skip_generated: bool,
+    #[serde(default)]
+    encoding: FileEncoding,
+    /// Whether a file that fails to be processed fails the whole repository or is skipped
+    #[serde(default)]
+    on_error: OnError,
+    /// Larger files are skipped when they are read entirely in memory, files whose processors
+    /// can stream them one line at a time are processed whatever their size
+    #[serde(default = "default_max_file_size")]
+    max_file_size: u64,
+    /// Fail the files that no longer parse as this format after being processed
+    verify: Option<FileFormat>,
+    /// Only select files tracked by git, leaving out untracked and ignored files of reused clones
+    #[serde(default)]
+    tracked_only: bool,
+    /// Only run the operation on the plan's repositories matching one of these, on all of them
+    /// when empty
+    #[serde(default)]
+    repositories: Vec<GlobPattern>,
+    /// Only run the operation on repositories with one of these languages, matched like the
+    /// plan's `languages`
+    #[serde(default)]
+    languages: Vec<String>,
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(tag = "type", rename_all = "snake_case")]
-pub enum Processor {
-    Regex(RegexProcessor),
+impl FileOperation {
+    /// The glob, or the files when the operation lists them
+    pub fn description(&self) -> String {
+        if self.files.is_empty() {
+            return self.pattern.as_str().to_owned();
+        }
+        self.files.join(", ")
+    }
+
+    fn check_files(&self) -> Result<()> {
+        if self.files.is_empty() {
+            return Ok(());
+        }
+        if self.pattern.as_str() != GlobPattern::all().as_str() {
+            return Err(eyre!("operations take either glob or files")
+                .suggestion("move the files to an operation of their own"));
+        }
+        for file in &self.files {
+            let path = Utf8Path::new(file);
+            if path.is_absolute()
+                || path
+                    .components()
+                    .any(|component| component == Utf8Component::ParentDir)
+            {
+                return Err(eyre!(
+                    "file {} must be relative to the repository, without ..",
+                    file
+                ));
+            }
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct RegexProcessor {
-    operations: Vec<RegexOperation>,
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessorsPosition {
+    Before,
+    #[default]
+    After,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct RegexOperation {
-    #[serde(with = "serde_regex")]
-    from: Regex,
-    to: String,
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnError {
+    #[default]
+    Fail,
+    /// Leaves the file untouched and records it on the report
+    Skip,
+}
+
+fn deserialize_providers<'de, D>(deserializer: D) -> Result<Vec<PlanProvider>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let providers = match Value::deserialize(deserializer)? {
+        Value::Array(values) => values
+            .into_iter()
+            .map(Value::try_into)
+            .collect::<Result<Vec<_>, _>>(),
+        value => value.try_into().map(|provider| vec![provider]),
+    }
+    .map_err(|error| de::Error::custom(provider_error(error)))?;
+    if providers.is_empty() {
+        return Err(de::Error::custom("the plan needs at least one provider"));
+    }
+    Ok(providers)
+}
+
+/// Serde leaves the expected keys out of unknown ones when a struct flattens another, like the
+/// token source of providers, so they are added back for the typo suggestions
+fn provider_error(error: toml::de::Error) -> String {
+    let message = error.to_string();
+    if !message.starts_with("unknown field") || message.contains(", expected") {
+        return message;
+    }
+    let fields = GithubProvider::FIELDS
+        .iter()
+        .map(|field| format!("`{}`", field))
+        .collect::<Vec<_>>();
+    format!("{}, expected one of {}", message, fields.join(", "))
 }
 
-#[instrument]
-pub async fn plan_from_file(path: &Utf8Path) -> Result<Plan> {
+fn default_force_push() -> bool {
+    true
+}
+
+fn default_open_pr() -> bool {
+    true
+}
+
+fn default_max_file_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+#[instrument(skip(config))]
+pub async fn plan_from_file(path: &Utf8Path, config: &GlobalConfig) -> Result<Plan> {
     let contents = fs::read_to_string(path)
         .await
         .wrap_err_with(|| format!("failed to read plan file from {:?}", path))?;
 
-    plan_from_str(&contents).wrap_err_with(|| format!("failed to parse {:?}", path))
+    let mut plan =
+        plan_from_str(&contents, config).wrap_err_with(|| format!("failed to parse {:?}", path))?;
+    if plan.name.is_none() {
+        plan.name = path.file_stem().map(|name| name.to_owned());
+    }
+    let directory = path.parent().unwrap_or_else(|| Utf8Path::new(""));
+    plan.load_files(directory).await?;
+    Ok(plan)
 }
 
-#[instrument(skip(plan))]
-pub fn plan_from_str(plan: &str) -> Result<Plan> {
-    toml::from_str(plan).wrap_err("failed to parse plan")
+/// Reads a plan from `reader`, like stdin, with the files it refers to relative to `directory`
+#[instrument(skip(reader, config))]
+pub async fn plan_from_reader<R>(
+    mut reader: R,
+    directory: &Utf8Path,
+    config: &GlobalConfig,
+) -> Result<Plan>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut contents = String::new();
+    reader
+        .read_to_string(&mut contents)
+        .await
+        .wrap_err("failed to read plan")?;
+    let mut plan = plan_from_str(&contents, config)?;
+    plan.load_files(directory).await?;
+    Ok(plan)
+}
+
+pub(crate) async fn read_plan_file(directory: &Utf8Path, path: &Utf8Path) -> Result<String> {
+    let path = directory.join(path);
+    fs::read_to_string(&path)
+        .await
+        .wrap_err_with(|| format!("failed to read {:?}", path))
+}
+
+#[instrument(skip(plan, config))]
+pub fn plan_from_str(plan: &str, config: &GlobalConfig) -> Result<Plan> {
+    let table: Table = toml::from_str(plan).wrap_err("failed to parse plan")?;
+    let table = Value::Table(config.apply_to(table));
+    // After the global config and the command line variables, which change the plan too
+    let checksum = format!("{:x}", Sha256::digest(table.to_string().as_bytes()));
+    let has_files = table.get("files").is_some();
+    let mut parsed: Plan = table.try_into().map_err(schema_error)?;
+    parsed.checksum = checksum;
+    if !has_files && parsed.action != Action::Issue {
+        return Err(eyre!("the plan has no [[files]]")
+            .suggestion("add the files to change, or use action = \"issue\" to only open issues"));
+    }
+    parsed.compile_patterns()?;
+    parsed.check_git_backend()?;
+    parsed.check_ssh_command(parsed.ssh_command.as_deref())?;
+    parsed.check_commit_message_template()?;
+    if parsed.commit_strategy != CommitStrategy::Single && !parsed.commit_paths.is_empty() {
+        return Err(
+            eyre!("commit_paths only works with the single commit_strategy")
+                .suggestion("remove commit_paths or commit_strategy"),
+        );
+    }
+    for operation in &parsed.file_operations {
+        operation.check_files()?;
+    }
+    if parsed.release && parsed.tag.is_none() {
+        return Err(eyre!("release needs a tag").suggestion("set the plan's tag"));
+    }
+    // Unlike messages, branch names cannot keep a placeholder as text
+    let unknown = template::variable_names(&parsed.branch_name)
+        .into_iter()
+        .find(|name| !REPOSITORY_VARIABLES.contains(name) && !parsed.env.contains_key(*name));
+    if let Some(name) = unknown {
+        return Err(eyre!(
+            "branch_name {:?} has the unknown variable {}",
+            parsed.branch_name,
+            name
+        )
+        .suggestion("set it on the plan's env or with --plan-var"));
+    }
+    Ok(parsed)
+}
+
+/// Serde's errors for typos, with the closest valid key or type as a suggestion
+fn schema_error(error: toml::de::Error) -> Report {
+    let suggestion = closest_name(&error.to_string());
+    let report = eyre!(error).wrap_err("failed to parse plan");
+    match suggestion {
+        Some((unknown, name)) => {
+            report.suggestion(format!("did you mean `{}` instead of `{}`?", name, unknown))
+        }
+        None => report,
+    }
+}
+
+/// The unknown field or variant of `message` and the closest expected one, if any is close
+fn closest_name(message: &str) -> Option<(String, String)> {
+    lazy_static! {
+        static ref UNKNOWN: Regex =
+            Regex::new(r"unknown (?:field|variant) `([^`]*)`, expected (.*)").unwrap();
+        static ref NAME: Regex = Regex::new(r"`([^`]+)`").unwrap();
+    }
+    let captures = UNKNOWN.captures(message)?;
+    let unknown = &captures[1];
+    let (distance, name) = NAME
+        .captures_iter(&captures[2])
+        .map(|name| (edit_distance(unknown, &name[1]), name[1].to_owned()))
+        .min()?;
+    if distance > 2.max(unknown.len() / 3) {
+        return None;
+    }
+    Some((unknown.to_owned(), name))
+}
+
+/// Levenshtein distance between `a` and `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
 }
 
 impl Plan {
-    pub fn get_provider(&self) -> Box<&dyn Provider> {
-        match &self.provider {
-            PlanProvider::Github(provider) => Box::new(provider),
-            #[cfg(test)]
-            PlanProvider::Test(provider) => Box::new(provider),
+    fn check_git_backend(&self) -> Result<()> {
+        if self.git_backend == GitBackend::Cli || cfg!(feature = "libgit2") {
+            return Ok(());
+        }
+        Err(eyre!("libgit2 support is disabled").suggestion(
+            "build there-i-fixed-it with `--features libgit2` or use git_backend = \"cli\"",
+        ))
+    }
+
+    /// libgit2 authenticates with the SSH agent without running `ssh`, so it would ignore the
+    /// command of the plan or `--ssh-command`
+    pub fn check_ssh_command(&self, ssh_command: Option<&str>) -> Result<()> {
+        if self.git_backend == GitBackend::Libgit2 && ssh_command.is_some() {
+            return Err(
+                eyre!("an SSH command does not work with git_backend = \"libgit2\"")
+                    .suggestion("use git_backend = \"cli\" or add the key to the SSH agent"),
+            );
+        }
+        Ok(())
+    }
+
+    /// The variable of another strategy would be left as text on every commit
+    fn check_commit_message_template(&self) -> Result<()> {
+        let template = match &self.commit_message_template {
+            Some(template) => template,
+            None => return Ok(()),
+        };
+        let variable = self.commit_strategy.variable();
+        let names = template::variable_names(template);
+        let other = [CommitStrategy::PerOperation, CommitStrategy::PerFile]
+            .iter()
+            .filter_map(|strategy| strategy.variable())
+            .find(|other| Some(*other) != variable && names.contains(other));
+        let other = match other {
+            Some(other) => other,
+            None => return Ok(()),
+        };
+        let suggestion = match variable {
+            Some(variable) => format!("use {{{{ {} }}}} instead", variable),
+            None => "use the per_operation or per_file commit_strategy".to_owned(),
+        };
+        Err(eyre!(
+            "commit_message_template uses {{{{ {} }}}}, which the commit_strategy does not set",
+            other
+        )
+        .suggestion(suggestion))
+    }
+
+    fn compile_patterns(&mut self) -> Result<()> {
+        let patterns = self
+            .repository_allow_filters
+            .iter_mut()
+            .chain(self.repository_deny_filters.iter_mut())
+            .chain(self.file_operations.iter_mut().flat_map(|operation| {
+                std::iter::once(&mut operation.pattern).chain(operation.repositories.iter_mut())
+            }));
+        for pattern in patterns {
+            pattern.compile(self.glob_engine)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the files referenced by the plan, relative to the plan's `directory`
+    async fn load_files(&mut self, directory: &Utf8Path) -> Result<()> {
+        let processors = self
+            .file_operations
+            .iter_mut()
+            .flat_map(|operation| operation.processors.iter_mut())
+            .chain(self.default_processors.iter_mut());
+        for processor in processors {
+            processor.load_files(directory).await?;
+        }
+        if let Some(path) = &self.pull_request_body_header_file {
+            self.pull_request_body_header = Some(read_plan_file(directory, path).await?);
+        }
+        if let Some(path) = &self.pull_request_body_footer_file {
+            self.pull_request_body_footer = Some(read_plan_file(directory, path).await?);
+        }
+        Ok(())
+    }
+
+    pub fn pull_request_body(&self) -> Option<String> {
+        let parts = [
+            &self.pull_request_body_header,
+            &self.pull_request_body,
+            &self.pull_request_body_footer,
+        ]
+        .iter()
+        .filter_map(|part| part.as_deref())
+        .map(|part| part.trim())
+        .collect::<Vec<_>>();
+        if parts.is_empty() {
+            return None;
+        }
+        Some(parts.join("\n\n"))
+    }
+
+    /// Loads the providers' credentials, which only happens once per run
+    pub async fn load_token(&mut self) -> Result<()> {
+        for provider in &mut self.providers {
+            match provider {
+                PlanProvider::Github(provider) => provider.load_token().await?,
+                #[cfg(test)]
+                PlanProvider::Test(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn providers(&self) -> impl Iterator<Item = &dyn Provider> {
+        self.providers.iter().map(PlanProvider::as_provider)
+    }
+
+    /// The provider that `repository` was listed from
+    pub fn provider(&self, repository: &Repository) -> &dyn Provider {
+        self.providers[repository.provider].as_provider()
+    }
+
+    /// Repositories of all the providers, listed or found with `repository_query`, without
+    /// duplicated clone urls
+    pub async fn list_repositories(&self, use_cache: bool) -> Result<Vec<Repository>> {
+        if !self.repository_names.is_empty() {
+            return self.get_repositories().await;
+        }
+        let mut output: Vec<Repository> = vec![];
+        for (index, provider) in self.providers.iter().enumerate() {
+            let provider = provider.as_provider();
+            let repositories = match &self.repository_query {
+                Some(query) => provider.search_repositories(query).await?,
+                None => provider.list_repositories(use_cache).await?,
+            };
+            for mut repository in repositories {
+                if output.iter().any(|r| r.ssh_url == repository.ssh_url) {
+                    continue;
+                }
+                repository.provider = index;
+                output.push(repository);
+            }
+        }
+        Ok(output)
+    }
+
+    /// The repositories of `repository_names`, each from the first provider that has it
+    async fn get_repositories(&self) -> Result<Vec<Repository>> {
+        let mut output = vec![];
+        'names: for name in &self.repository_names {
+            for (index, provider) in self.providers.iter().enumerate() {
+                if let Some(mut repository) = provider.as_provider().get_repository(name).await? {
+                    repository.provider = index;
+                    output.push(repository);
+                    continue 'names;
+                }
+            }
+            return Err(eyre!("repository {} not found", name));
+        }
+        Ok(output)
+    }
+
+    /// Variables of `[env]`, including the ones of the command line
+    fn variables(&self) -> Vec<(String, String)> {
+        self.env
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+
+    pub fn identity(&self) -> PlanIdentity {
+        PlanIdentity {
+            // The variables of the repositories are left as placeholders
+            branch_name: format!(
+                "{}{}",
+                self.branch_prefix.as_deref().unwrap_or_default(),
+                template::render(&self.branch_name, &self.variables())
+            ),
+            checksum: self.checksum.clone(),
         }
     }
 
+    /// File operations, with the commands counting as one more
+    pub fn operations_count(&self) -> usize {
+        self.file_operations.len() + usize::from(!self.commands.is_empty())
+    }
+
+    pub fn branch_prefix(&self) -> Option<&str> {
+        self.branch_prefix.as_deref()
+    }
+
+    /// Name of the branch with the changes on `repository`, including the prefix
+    pub fn branch_name(&self, repository: &Repository) -> String {
+        format!(
+            "{}{}",
+            self.branch_prefix.as_deref().unwrap_or_default(),
+            template::render(&self.branch_name, &self.repository_variables(repository))
+        )
+    }
+
+    pub fn reviewers(&self) -> &[String] {
+        &self.pull_request.reviewers
+    }
+
+    pub fn reviewers_from_codeowners(&self) -> bool {
+        self.pull_request.reviewers_from_codeowners
+    }
+
+    pub fn comment_details(&self) -> bool {
+        self.pull_request.comment_details
+    }
+
+    /// The head of the pull request of `branch_name` on `repository`, the branch itself unless
+    /// the plan opens them from another fork
+    pub fn pull_request_head(&self, repository: &Repository, branch_name: &str) -> String {
+        let head = match self
+            .pull_request
+            .heads
+            .get(&repository.name)
+            .or(self.pull_request.head.as_ref())
+        {
+            Some(head) => head,
+            None => return branch_name.to_owned(),
+        };
+        let mut variables = self.environment(repository);
+        for (name, value) in &mut variables {
+            if name == "BRANCH_NAME" {
+                *value = branch_name.to_owned();
+            }
+        }
+        template::render(head, &variables)
+    }
+
+    /// Variables for commands and git hooks that run on `repository`, also used by templates
+    pub fn environment(&self, repository: &Repository) -> Vec<(String, String)> {
+        let mut environment = self.repository_variables(repository);
+        environment.insert(2, ("BRANCH_NAME".to_owned(), self.branch_name(repository)));
+        environment
+    }
+
+    /// The variables of `environment()` that `branch_name` can use
+    fn repository_variables(&self, repository: &Repository) -> Vec<(String, String)> {
+        let mut variables = vec![
+            ("REPO_NAME".to_owned(), repository.name.clone()),
+            (
+                "DEFAULT_BRANCH".to_owned(),
+                repository.default_branch().to_owned(),
+            ),
+            (
+                "PLAN_NAME".to_owned(),
+                self.name.clone().unwrap_or_default(),
+            ),
+        ];
+        variables.extend(self.env.clone());
+        variables
+    }
+
+    pub fn pull_request_title(&self) -> String {
+        let title = self
+            .pull_request_title
+            .as_ref()
+            .unwrap_or(&self.git_message);
+        match &self.pull_request.title_prefix {
+            Some(prefix) if !title.starts_with(prefix.as_str()) => format!("{}{}", prefix, title),
+            _ => title.clone(),
+        }
+    }
+
+    /// Whether the pull request of changes of this size is opened as a draft
+    pub fn draft(&self, diff_stat: &DiffStat) -> bool {
+        self.pull_request
+            .draft_if_lines_changed_over
+            .is_some_and(|limit| diff_stat.insertions + diff_stat.deletions > limit)
+    }
+
+    pub fn issue_title(&self) -> String {
+        self.issue
+            .title
+            .clone()
+            .unwrap_or_else(|| self.pull_request_title())
+    }
+
+    pub fn issue_body(&self) -> Option<String> {
+        self.issue.body.clone().or_else(|| self.pull_request_body())
+    }
+
+    pub fn commit_message(&self) -> String {
+        self.with_trailers(&self.git_message)
+    }
+
+    /// The template of the commits of the `per_operation` and `per_file` strategies, with `name`
+    /// as the value of `variable` when there is no `commit_message_template`
+    pub fn split_commit_message(&self, variable: &str) -> String {
+        if let Some(template) = &self.commit_message_template {
+            return self.with_trailers(template);
+        }
+        let (first_line, rest) = match self.git_message.find('\n') {
+            Some(end) => self.git_message.split_at(end),
+            None => (self.git_message.as_str(), ""),
+        };
+        self.with_trailers(&format!(
+            "{} ({{{{ {} }}}}){}",
+            first_line.trim_end(),
+            variable,
+            rest
+        ))
+    }
+
+    fn with_trailers(&self, message: &str) -> String {
+        if !self.git_trailer {
+            return message.to_owned();
+        }
+        let mut message = format!(
+            "{}\n\n{}: {}",
+            message.trim_end(),
+            FIXED_BY_TRAILER,
+            env!("CARGO_PKG_NAME")
+        );
+        if let Some(name) = &self.name {
+            message.push_str(&format!("\nPlan: {}", name));
+        }
+        message
+    }
+
+    /// The operation's processors together with the default ones, in the order they run
+    pub fn processors<'a>(
+        &'a self,
+        operation: &'a FileOperation,
+    ) -> impl Iterator<Item = &'a FileProcessor> {
+        let (first, second) = match self.default_processors_position {
+            ProcessorsPosition::Before => (&self.default_processors, &operation.processors),
+            ProcessorsPosition::After => (&operation.processors, &self.default_processors),
+        };
+        first.iter().chain(second.iter())
+    }
+
     pub fn repository_allowed(&self, repository_name: &str) -> bool {
         self.repository_allow_filters
             .iter()
@@ -97,43 +911,700 @@ impl Plan {
             && !self.repository_denied(repository_name)
     }
 
-    fn repository_denied(&self, repository_name: &str) -> bool {
-        self.repository_deny_filters
+    pub fn repository_excluded(&self, repository_name: &str) -> bool {
+        self.repository_exclude
             .iter()
-            .any(|f| f.matches(repository_name))
+            .any(|name| name == repository_name)
     }
-}
 
-impl Processor {
-    pub fn process(&self, text: &mut String) -> bool {
-        let mut changed = false;
-        match self {
-            Processor::Regex(processor) => {
-                for operation in &processor.operations {
-                    if let Cow::Owned(new_text) = operation.from.replace_all(&text, &operation.to) {
-                        *text = new_text;
-                        changed = true;
-                    }
-                }
-                changed
-            }
+    #[instrument(skip(self, repository), fields(repository_name = repository.name.as_str()))]
+    pub async fn language_allowed(&self, repository: &Repository) -> Result<bool> {
+        self.has_language(repository, &self.languages).await
+    }
+
+    /// Whether the operation's `repositories` and `languages` select the repository
+    pub async fn operation_applies(
+        &self,
+        operation: &FileOperation,
+        repository: &Repository,
+    ) -> Result<bool> {
+        let name_matches = operation.repositories.is_empty()
+            || operation
+                .repositories
+                .iter()
+                .any(|pattern| pattern.matches(&repository.name));
+        Ok(name_matches && self.has_language(repository, &operation.languages).await?)
+    }
+
+    /// Whether the repository has any of `allowed`, which is always true when it is empty
+    async fn has_language(&self, repository: &Repository, allowed: &[String]) -> Result<bool> {
+        if allowed.is_empty() {
+            return Ok(true);
         }
+        let languages = match self.language_match {
+            LanguageMatch::Primary => repository.language.iter().cloned().collect(),
+            LanguageMatch::Any => {
+                self.provider(repository)
+                    .languages(&repository.name)
+                    .await?
+            }
+        };
+        Ok(languages.iter().any(|language| {
+            allowed
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(language))
+        }))
+    }
+
+    fn repository_denied(&self, repository_name: &str) -> bool {
+        self.repository_deny_filters
+            .iter()
+            .any(|f| f.matches(repository_name))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use camino::Utf8PathBuf;
+    use std::fs;
+
+    use camino::{Utf8Path, Utf8PathBuf};
+    use tempdir::TempDir;
+
+    use crate::{config::GlobalConfig, report::DiffStat};
+
+    use super::{
+        closest_name, edit_distance, plan_from_file, plan_from_reader, plan_from_str,
+        ProcessorsPosition,
+    };
+
+    #[test]
+    fn test_unknown_fields() {
+        let plan = r#"
+            branch_name = "test"
+            git_mesage = "chore: Changes"
+            repositories = ["*"]
+
+            [provider]
+            name = "test"
+
+            [[files]]
+            processors = [{ type = "trim_whitespace", collapse_final_newlines = true }]
+        "#;
+        let err = plan_from_str(plan, &GlobalConfig::default()).unwrap_err();
+        let message = err.root_cause().to_string();
+        assert!(
+            message.starts_with("unknown field `git_mesage`"),
+            "{}",
+            message
+        );
+        assert_eq!(
+            closest_name(&message),
+            Some(("git_mesage".to_owned(), "git_message".to_owned()))
+        );
+
+        let plan = plan
+            .replace("git_mesage", "git_message")
+            .replace("collapse_", "colapse_");
+        let err = plan_from_str(&plan, &GlobalConfig::default()).unwrap_err();
+        let message = err.root_cause().to_string();
+        assert_eq!(closest_name(&message).unwrap().1, "collapse_final_newlines");
+
+        let plan = plan
+            .replace("colapse_", "collapse_")
+            .replace("trim_whitespace", "something_else");
+        let err = plan_from_str(&plan, &GlobalConfig::default()).unwrap_err();
+        assert_eq!(closest_name(&err.root_cause().to_string()), None);
+        let plan = r#"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            repositories = ["*"]
+
+            [[provider]]
+            name = "github"
+            user = "bot"
+            token_env = "GITHUB_TOKEN"
+            organization = "fix-it"
+            max_attemps = 3
+
+            [[files]]
+            processors = []
+        "#;
+        let err = plan_from_str(plan, &GlobalConfig::default()).unwrap_err();
+        assert_eq!(
+            closest_name(&err.root_cause().to_string()),
+            Some(("max_attemps".to_owned(), "max_attempts".to_owned()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plan_from_reader() {
+        let plan = r#"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            repositories = ["*"]
+
+            [provider]
+            name = "test"
+
+            [[files]]
+            processors = [{ type = "apply_patch", patch_file = "tests/fixtures/disable.patch" }]
+        "#;
+        assert!(
+            plan_from_reader(plan.as_bytes(), Utf8Path::new(""), &GlobalConfig::default())
+                .await
+                .is_ok()
+        );
+        assert!(plan_from_reader(
+            plan.as_bytes(),
+            Utf8Path::new("src"),
+            &GlobalConfig::default()
+        )
+        .await
+        .is_err());
+    }
 
-    use super::plan_from_file;
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("glob", "glob"), 0);
+        assert_eq!(edit_distance("glbo", "glob"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[tokio::test]
+    async fn test_multiple_providers() {
+        let plan = r#"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            repositories = ["*"]
+
+            [[provider]]
+            name = "test"
+
+            [[provider]]
+            name = "test"
+
+            [[files]]
+            processors = []
+        "#;
+        let plan = plan_from_str(plan, &GlobalConfig::default()).unwrap();
+        assert_eq!(plan.providers.len(), 2);
+        let repositories = plan.list_repositories(false).await.unwrap();
+        assert_eq!(repositories.len(), 1);
+
+        let plan = r#"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            repositories = ["*"]
+            provider = []
+
+            [[files]]
+            processors = []
+        "#;
+        assert!(plan_from_str(plan, &GlobalConfig::default()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_repository_names() {
+        let plan = r#"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            repositories = ["*"]
+            repository_names = ["api", "my-org/web"]
+
+            [provider]
+            name = "test"
+
+            [[files]]
+            processors = []
+        "#;
+        let mut plan = plan_from_str(plan, &GlobalConfig::default()).unwrap();
+        let names = plan
+            .list_repositories(false)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|repository| repository.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["api", "web"]);
+
+        plan.repository_names.push("missing-repo".to_owned());
+        assert!(plan.list_repositories(false).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_language_allowed() {
+        let plan = |languages: &str, language_match: &str| {
+            let plan = format!(
+                r#"
+                branch_name = "test"
+                git_message = "chore: Changes"
+                repositories = ["*"]
+                languages = {}
+                language_match = "{}"
+
+                [provider]
+                name = "test"
+
+                [[files]]
+                processors = []
+                "#,
+                languages, language_match
+            );
+            plan_from_str(&plan, &GlobalConfig::default()).unwrap()
+        };
+        let repository = plan("[]", "primary")
+            .list_repositories(false)
+            .await
+            .unwrap()
+            .remove(0);
+
+        let allowed = |languages, language_match| {
+            let plan = plan(languages, language_match);
+            let repository = &repository;
+            async move { plan.language_allowed(repository).await.unwrap() }
+        };
+        assert!(allowed("[]", "primary").await);
+        assert!(allowed(r#"["rust"]"#, "primary").await);
+        assert!(!allowed(r#"["Shell"]"#, "primary").await);
+        assert!(allowed(r#"["Shell"]"#, "any").await);
+        assert!(!allowed(r#"["Go"]"#, "any").await);
+    }
+
+    #[test]
+    fn test_split_commit_message() {
+        let plan = |extra: &str| {
+            plan_from_str(
+                &format!(
+                    r#"
+                    branch_name = "test"
+                    git_message = "chore: Changes\n\nMore details"
+                    repositories = ["*"]
+                    commit_strategy = "per_file"
+                    {}
+
+                    [provider]
+                    name = "test"
+
+                    [[files]]
+                    processors = []
+                    "#,
+                    extra
+                ),
+                &GlobalConfig::default(),
+            )
+        };
+        assert_eq!(
+            plan("").unwrap().split_commit_message("FILE"),
+            "chore: Changes ({{ FILE }})\n\nMore details"
+        );
+        assert_eq!(
+            plan(r#"commit_message_template = "fix: {{ FILE }}""#)
+                .unwrap()
+                .split_commit_message("FILE"),
+            "fix: {{ FILE }}"
+        );
+        assert!(plan(r#"commit_paths = ["src/**"]"#).is_err());
+        assert!(plan(r#"commit_message_template = "fix: {{ OPERATION }}""#).is_err());
+    }
+
+    #[test]
+    fn test_operation_files() {
+        let plan = |files: &str| {
+            plan_from_str(
+                &format!(
+                    r#"
+                    branch_name = "test"
+                    git_message = "chore: Changes"
+                    repositories = ["*"]
+
+                    [provider]
+                    name = "test"
+
+                    [[files]]
+                    {}
+                    processors = []
+                    "#,
+                    files
+                ),
+                &GlobalConfig::default(),
+            )
+        };
+        let parsed = plan(r#"files = ["a.txt", "sub/b.toml"]"#).unwrap();
+        assert_eq!(parsed.file_operations[0].description(), "a.txt, sub/b.toml");
+        assert!(plan("files = [\"a.txt\"]\nglob = \"*.txt\"").is_err());
+        assert!(plan(r#"files = ["../other/a.txt"]"#).is_err());
+        assert!(plan(r#"files = ["/etc/passwd"]"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_operation_applies() {
+        let plan = r#"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            repositories = ["*"]
+
+            [provider]
+            name = "test"
+
+            [[files]]
+            processors = []
+
+            [[files]]
+            repositories = ["other-*"]
+            processors = []
+
+            [[files]]
+            languages = ["Go"]
+            processors = []
+
+            [[files]]
+            repositories = ["working-*"]
+            languages = ["rust"]
+            processors = []
+        "#;
+        let plan = plan_from_str(plan, &GlobalConfig::default()).unwrap();
+        let repository = plan.list_repositories(false).await.unwrap().remove(0);
+        let mut applies = vec![];
+        for operation in &plan.file_operations {
+            applies.push(
+                plan.operation_applies(operation, &repository)
+                    .await
+                    .unwrap(),
+            );
+        }
+        assert_eq!(applies, vec![true, false, false, true]);
+    }
 
     #[tokio::test]
     async fn test_filters() {
         let plan_file = Utf8PathBuf::from("tests/fixtures/simple-plan.toml");
-        let plan = plan_from_file(&plan_file).await.unwrap();
+        let plan = plan_from_file(&plan_file, &GlobalConfig::default())
+            .await
+            .unwrap();
 
         assert!(plan.repository_allowed("my-repo"));
         assert!(plan.repository_allowed("abc-rs-my-repo"));
         assert!(!plan.repository_allowed("my-repo-rs"));
+        assert!(plan.repository_excluded("legacy-thing"));
+        assert!(!plan.repository_excluded("legacy-thing-2"));
+    }
+
+    #[test]
+    fn test_commit_message() {
+        let plan = r#"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            repositories = ["*"]
+
+            [provider]
+            name = "test"
+
+            [[files]]
+            glob = "*"
+            processors = []
+        "#;
+        let mut plan = plan_from_str(plan, &GlobalConfig::default()).unwrap();
+        assert_eq!(plan.commit_message(), "chore: Changes");
+
+        plan.git_trailer = true;
+        assert_eq!(
+            plan.commit_message(),
+            "chore: Changes\n\nX-Fixed-By: there-i-fixed-it"
+        );
+
+        plan.name = Some("simple-plan".to_string());
+        assert_eq!(
+            plan.commit_message(),
+            "chore: Changes\n\nX-Fixed-By: there-i-fixed-it\nPlan: simple-plan"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_branch_prefix() {
+        let plan = r#"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            repositories = ["*"]
+
+            [provider]
+            name = "test"
+
+            [[files]]
+            glob = "*"
+            processors = []
+        "#;
+        let config = GlobalConfig::parse(r#"branch_prefix = "bot/""#).unwrap();
+        let mut plan = plan_from_str(plan, &config).unwrap();
+        let repository = plan.list_repositories(false).await.unwrap().remove(0);
+        assert_eq!(plan.branch_name(&repository), "bot/test");
+
+        plan.branch_prefix = None;
+        assert_eq!(plan.branch_name(&repository), "test");
+    }
+
+    #[test]
+    fn test_processor_extensions() {
+        let plan = r#"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            repositories = ["*"]
+
+            [provider]
+            name = "test"
+
+            [[files]]
+            glob = "**"
+            processors = [
+                { type = "regex", extensions = ["py", ".pyi"], operations = [] },
+                { type = "regex", operations = [] },
+            ]
+        "#;
+        let plan = plan_from_str(plan, &GlobalConfig::default()).unwrap();
+        let processors = &plan.file_operations[0].processors;
+
+        assert!(processors[0].applies_to(Utf8Path::new("src/main.py")));
+        assert!(processors[0].applies_to(Utf8Path::new("src/main.pyi")));
+        assert!(!processors[0].applies_to(Utf8Path::new("src/main.rs")));
+        assert!(!processors[0].applies_to(Utf8Path::new("Makefile")));
+        assert!(processors[1].applies_to(Utf8Path::new("Makefile")));
+    }
+
+    #[test]
+    fn test_default_processors() {
+        let plan = r#"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            repositories = ["*"]
+            default_processors = [
+                { type = "regex", operations = [{ from = "b", to = "c" }] },
+            ]
+
+            [provider]
+            name = "test"
+
+            [[files]]
+            glob = "**"
+            processors = [
+                { type = "regex", operations = [{ from = "a", to = "b" }] },
+            ]
+        "#;
+        let mut plan = plan_from_str(plan, &GlobalConfig::default()).unwrap();
+        let operation = &plan.file_operations[0];
+
+        let mut text = "a".to_string();
+        for processor in plan.processors(operation) {
+            processor.process(&mut text).unwrap();
+        }
+        assert_eq!(text, "c");
+
+        plan.default_processors_position = ProcessorsPosition::Before;
+        let operation = &plan.file_operations[0];
+        let mut text = "a".to_string();
+        for processor in plan.processors(operation) {
+            processor.process(&mut text).unwrap();
+        }
+        assert_eq!(text, "b");
+    }
+
+    #[tokio::test]
+    async fn test_pull_request_body() {
+        let temp = TempDir::new("plan").unwrap();
+        let directory = Utf8Path::from_path(temp.path()).unwrap();
+        fs::write(directory.join("footer.md"), "Footer\n").unwrap();
+        let plan_file = directory.join("plan.toml");
+        fs::write(
+            &plan_file,
+            r#"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            pull_request_body = "Body"
+            pull_request_body_footer_file = "footer.md"
+            repositories = ["*"]
+
+            [provider]
+            name = "test"
+
+            [[files]]
+            glob = "*"
+            processors = []
+            "#,
+        )
+        .unwrap();
+
+        let mut plan = plan_from_file(&plan_file, &GlobalConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(plan.pull_request_body().unwrap(), "Body\n\nFooter");
+
+        plan.pull_request_body = None;
+        assert_eq!(plan.pull_request_body().unwrap(), "Footer");
+
+        plan.pull_request_body_footer = None;
+        assert_eq!(plan.pull_request_body(), None);
+    }
+
+    #[test]
+    fn test_ssh_command_with_libgit2() {
+        let plan = r#"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            repositories = ["*"]
+
+            [provider]
+            name = "test"
+
+            [[files]]
+            processors = []
+        "#;
+        let mut plan = plan_from_str(plan, &GlobalConfig::default()).unwrap();
+        assert!(plan.check_ssh_command(Some("ssh -i key")).is_ok());
+        plan.git_backend = super::GitBackend::Libgit2;
+        assert!(plan.check_ssh_command(Some("ssh -i key")).is_err());
+        assert!(plan.check_ssh_command(None).is_ok());
+    }
+
+    #[test]
+    fn test_files_optional_for_issues() {
+        let plan = r#"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            repositories = ["*"]
+
+            [provider]
+            name = "test"
+        "#;
+        assert!(plan_from_str(plan, &GlobalConfig::default()).is_err());
+        let issue = format!("action = \"issue\"\n{}", plan);
+        let parsed = plan_from_str(&issue, &GlobalConfig::default()).unwrap();
+        assert!(parsed.file_operations.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_plan_variables() {
+        let plan = r#"
+            branch_name = "{{ REPO_NAME }}/bump-{{ VERSION }}"
+            git_message = "chore: Bump to {{ VERSION }}"
+            repositories = ["*"]
+
+            [env]
+            VERSION = "1"
+
+            [provider]
+            name = "test"
+
+            [[files]]
+            processors = []
+        "#;
+        let default = plan_from_str(plan, &GlobalConfig::default()).unwrap();
+        let repository = default.list_repositories(false).await.unwrap().remove(0);
+        assert_eq!(default.branch_name(&repository), "working-repo/bump-1");
+        assert_eq!(default.identity().branch_name, "{{ REPO_NAME }}/bump-1");
+
+        let mut config = GlobalConfig::default();
+        config.override_variables(&[("VERSION".to_owned(), "2".to_owned())]);
+        let overridden = plan_from_str(plan, &config).unwrap();
+        assert_eq!(overridden.branch_name(&repository), "working-repo/bump-2");
+        assert_eq!(overridden.env["VERSION"], "2");
+        assert_ne!(default.checksum, overridden.checksum);
+        let config = GlobalConfig::parse(r#"branch_prefix = "bot/""#).unwrap();
+        assert_ne!(
+            default.checksum,
+            plan_from_str(plan, &config).unwrap().checksum
+        );
+        assert_eq!(
+            default.checksum,
+            plan_from_str(plan, &GlobalConfig::default())
+                .unwrap()
+                .checksum
+        );
+
+        let plan = plan.replace("{{ VERSION }}\"", "{{ MISSING }}\"");
+        assert!(plan_from_str(&plan, &GlobalConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_title_prefix() {
+        let plan = r#"
+            branch_name = "test"
+            git_message = "Bump dependencies"
+            repositories = ["*"]
+
+            [pull_request]
+            title_prefix = "chore(deps): "
+
+            [provider]
+            name = "test"
+
+            [[files]]
+            processors = []
+        "#;
+        let mut plan = plan_from_str(plan, &GlobalConfig::default()).unwrap();
+        assert_eq!(plan.pull_request_title(), "chore(deps): Bump dependencies");
+
+        plan.pull_request_title = Some("chore(deps): Bump them".to_owned());
+        assert_eq!(plan.pull_request_title(), "chore(deps): Bump them");
+    }
+
+    #[tokio::test]
+    async fn test_pull_request_head() {
+        let plan = r#"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            repositories = ["*"]
+
+            [provider]
+            name = "test"
+
+            [[files]]
+            processors = []
+        "#;
+        let mut plan = plan_from_str(plan, &GlobalConfig::default()).unwrap();
+        let repository = plan.list_repositories(false).await.unwrap().remove(0);
+        assert_eq!(plan.pull_request_head(&repository, "other"), "other");
+
+        plan.pull_request.head = Some("staging:{{ BRANCH_NAME }}".to_owned());
+        assert_eq!(
+            plan.pull_request_head(&repository, "other"),
+            "staging:other"
+        );
+
+        plan.pull_request.heads.insert(
+            repository.name.clone(),
+            "{{ REPO_NAME }}-fork:{{ BRANCH_NAME }}".to_owned(),
+        );
+        assert_eq!(
+            plan.pull_request_head(&repository, "other"),
+            "working-repo-fork:other"
+        );
+    }
+
+    #[test]
+    fn test_draft() {
+        let plan = r#"
+            branch_name = "test"
+            git_message = "chore: Changes"
+            repositories = ["*"]
+
+            [pull_request]
+            draft_if_lines_changed_over = 50
+
+            [provider]
+            name = "test"
+
+            [[files]]
+            processors = []
+        "#;
+        let mut plan = plan_from_str(plan, &GlobalConfig::default()).unwrap();
+        let diff_stat = |insertions, deletions| DiffStat {
+            files_changed: 1,
+            insertions,
+            deletions,
+        };
+        assert!(!plan.draft(&diff_stat(30, 20)));
+        assert!(plan.draft(&diff_stat(30, 21)));
+
+        plan.pull_request.draft_if_lines_changed_over = None;
+        assert!(!plan.draft(&diff_stat(1000, 0)));
     }
 }
@@ -0,0 +1,147 @@
+use std::{collections::VecDeque, process::Output};
+
+use tokio::{
+    io::{self, AsyncRead, AsyncReadExt},
+    process::Child,
+};
+
+use super::process::RunningGroup;
+
+/// Bytes of each output kept by default, half from its beginning and half from its end
+pub const DEFAULT_OUTPUT_LIMIT: usize = 1024 * 1024;
+
+/// Like `Child::wait_with_output`, but keeping at most the given bytes of each output so chatty
+/// commands cannot use up the memory. `None` keeps all of it, for outputs that are parsed
+pub async fn wait_with_limited_output(
+    mut child: Child,
+    stdout_limit: Option<usize>,
+    stderr_limit: Option<usize>,
+) -> io::Result<Output> {
+    let _group = child.id().map(RunningGroup::new);
+    let stdout = read_limited(child.stdout.take(), stdout_limit);
+    let stderr = read_limited(child.stderr.take(), stderr_limit);
+    let (status, stdout, stderr) = tokio::try_join!(child.wait(), stdout, stderr)?;
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+async fn read_limited<R: AsyncRead + Unpin>(
+    reader: Option<R>,
+    limit: Option<usize>,
+) -> io::Result<Vec<u8>> {
+    let mut output = vec![];
+    let mut reader = match reader {
+        Some(reader) => reader,
+        None => return Ok(output),
+    };
+    let limit = match limit {
+        Some(limit) => limit,
+        None => {
+            reader.read_to_end(&mut output).await?;
+            return Ok(output);
+        }
+    };
+    let mut truncated = Truncated::new(limit);
+    let mut buffer = vec![0; 8192];
+    loop {
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(truncated.finish());
+        }
+        truncated.push(&buffer[..read]);
+    }
+}
+
+/// Keeps the beginning and the end of what is pushed, with a note of how much was left out
+struct Truncated {
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    head_limit: usize,
+    tail_limit: usize,
+    skipped: usize,
+}
+
+impl Truncated {
+    fn new(limit: usize) -> Self {
+        Self {
+            head: vec![],
+            tail: VecDeque::new(),
+            head_limit: limit / 2,
+            tail_limit: limit - limit / 2,
+            skipped: 0,
+        }
+    }
+
+    fn push(&mut self, mut bytes: &[u8]) {
+        let head_space = self.head_limit - self.head.len();
+        if head_space > 0 {
+            let taken = head_space.min(bytes.len());
+            self.head.extend_from_slice(&bytes[..taken]);
+            bytes = &bytes[taken..];
+        }
+        self.tail.extend(bytes);
+        if self.tail.len() > self.tail_limit {
+            let excess = self.tail.len() - self.tail_limit;
+            self.tail.drain(..excess);
+            self.skipped += excess;
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut output = self.head;
+        if self.skipped > 0 {
+            output.extend(format!("\n[... {} bytes truncated ...]\n", self.skipped).bytes());
+        }
+        output.extend(self.tail);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Stdio;
+
+    use tokio::process::Command;
+
+    use super::{wait_with_limited_output, Truncated};
+
+    #[test]
+    fn test_truncated() {
+        let mut truncated = Truncated::new(8);
+        truncated.push(b"0123");
+        truncated.push(b"45678");
+        truncated.push(b"9");
+        assert_eq!(
+            String::from_utf8(truncated.finish()).unwrap(),
+            "0123\n[... 2 bytes truncated ...]\n6789"
+        );
+
+        let mut truncated = Truncated::new(8);
+        truncated.push(b"01234567");
+        assert_eq!(truncated.finish(), b"01234567");
+    }
+
+    #[tokio::test]
+    async fn test_wait_with_limited_output() {
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg("seq 1 10000; seq 1 10000 >&2")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let output = wait_with_limited_output(child, None, Some(16))
+            .await
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout.len(), 48894);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with("1\n2\n3\n4\n"));
+        assert!(stderr.contains("bytes truncated"));
+        assert!(stderr.ends_with("9\n10000\n"));
+    }
+}
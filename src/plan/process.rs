@@ -0,0 +1,85 @@
+use std::{collections::BTreeSet, sync::Mutex};
+
+use tokio::process::Command;
+
+/// Process groups of the children still running, which exiting right away would leave behind
+static RUNNING_GROUPS: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+/// A command on its own process group, so Ctrl-C does not interrupt it halfway through
+pub fn grouped_command(program: &str) -> Command {
+    let mut command = std::process::Command::new(program);
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    command.into()
+}
+
+/// Keeps the process group of a child on the running ones until it is dropped
+pub struct RunningGroup(u32);
+
+impl RunningGroup {
+    pub fn new(id: u32) -> Self {
+        RUNNING_GROUPS.lock().unwrap().insert(id);
+        Self(id)
+    }
+}
+
+impl Drop for RunningGroup {
+    fn drop(&mut self) {
+        RUNNING_GROUPS.lock().unwrap().remove(&self.0);
+    }
+}
+
+/// Kills the process groups still running, with the children their commands started
+pub fn kill_running_groups() {
+    let groups = RUNNING_GROUPS.lock().unwrap().clone();
+    for group in groups {
+        kill_group(group);
+    }
+}
+
+#[cfg(unix)]
+fn kill_group(group: u32) {
+    let _ = std::process::Command::new("kill")
+        .args(["-KILL", "--", &format!("-{}", group)])
+        .status();
+}
+
+#[cfg(not(unix))]
+fn kill_group(_group: u32) {}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{process::Stdio, time::Duration};
+
+    use tokio::time;
+
+    use crate::plan::output::wait_with_limited_output;
+
+    use super::{grouped_command, kill_group, RUNNING_GROUPS};
+
+    #[tokio::test]
+    async fn test_kill_group() {
+        let child = grouped_command("sh")
+            .args(["-c", "sleep 60 & wait"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let id = child.id().unwrap();
+        let output = tokio::spawn(wait_with_limited_output(child, None, None));
+        while !RUNNING_GROUPS.lock().unwrap().contains(&id) {
+            time::sleep(Duration::from_millis(10)).await;
+        }
+
+        kill_group(id);
+        // The output only ends once the background sleep, which shares it, is gone too
+        let output = time::timeout(Duration::from_secs(10), output)
+            .await
+            .expect("the process group is still running")
+            .unwrap()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(!RUNNING_GROUPS.lock().unwrap().contains(&id));
+    }
+}
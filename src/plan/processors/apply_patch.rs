@@ -0,0 +1,78 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use diffy::Patch;
+use serde::Deserialize;
+
+use crate::plan::read_plan_file;
+
+/// Applies a unified diff of a single file to each matched file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApplyPatchProcessor {
+    patch: Option<String>,
+    /// File relative to the plan with the patch, used when `patch` is missing
+    patch_file: Option<Utf8PathBuf>,
+}
+
+impl ApplyPatchProcessor {
+    pub async fn load_files(&mut self, directory: &Utf8Path) -> Result<()> {
+        if let (None, Some(path)) = (&self.patch, &self.patch_file) {
+            self.patch = Some(read_plan_file(directory, path).await?);
+        }
+        self.parse().map(|_| ())
+    }
+
+    fn parse(&self) -> Result<Patch<'_, str>> {
+        let patch = self
+            .patch
+            .as_deref()
+            .ok_or_else(|| eyre!("apply_patch requires patch or patch_file"))?;
+        Patch::from_str(patch).wrap_err("failed to parse patch")
+    }
+
+    pub fn process(&self, text: &mut String) -> Result<bool> {
+        let patch = self.parse()?;
+        match diffy::apply(text, &patch) {
+            Ok(new_text) => {
+                let changed = new_text != *text;
+                *text = new_text;
+                Ok(changed)
+            }
+            // A patch that can be reverted was already applied
+            Err(_) if diffy::apply(text, &patch.reverse()).is_ok() => Ok(false),
+            Err(err) => Err(eyre!(err).wrap_err("patch does not apply cleanly")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApplyPatchProcessor;
+
+    fn processor() -> ApplyPatchProcessor {
+        let patch = diffy::create_patch("a\nb\nc\n", "a\nB\nc\n").to_string();
+        ApplyPatchProcessor {
+            patch: Some(patch),
+            patch_file: None,
+        }
+    }
+
+    #[test]
+    fn test_apply_patch() {
+        let processor = processor();
+
+        let mut text = "a\nb\nc\n".to_string();
+        assert!(processor.process(&mut text).unwrap());
+        assert_eq!(text, "a\nB\nc\n");
+
+        assert!(!processor.process(&mut text).unwrap());
+        assert_eq!(text, "a\nB\nc\n");
+
+        let mut text = "x\ny\nz\n".to_string();
+        assert!(processor.process(&mut text).is_err());
+        assert_eq!(text, "x\ny\nz\n");
+    }
+}
@@ -0,0 +1,111 @@
+use heck::{ToKebabCase, ToLowerCamelCase, ToSnakeCase, ToUpperCamelCase};
+use regex::{Captures, Regex};
+use serde::Deserialize;
+
+/// Changes the case of what `pattern` matches, or of its first group when it has one, like the
+/// key of `(\w+) =` without the ` =`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChangeCaseProcessor {
+    #[serde(with = "serde_regex")]
+    pattern: Regex,
+    case: Case,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Case {
+    Snake,
+    Camel,
+    Kebab,
+    Pascal,
+    Upper,
+    Lower,
+}
+
+impl Case {
+    fn convert(self, token: &str) -> String {
+        match self {
+            Case::Snake => token.to_snake_case(),
+            Case::Camel => token.to_lower_camel_case(),
+            Case::Kebab => token.to_kebab_case(),
+            Case::Pascal => token.to_upper_camel_case(),
+            Case::Upper => token.to_uppercase(),
+            Case::Lower => token.to_lowercase(),
+        }
+    }
+}
+
+impl ChangeCaseProcessor {
+    pub fn process(&self, text: &mut String) -> bool {
+        let mut changed = false;
+        let new_text = self.pattern.replace_all(text, |captures: &Captures| {
+            let matched = captures.get(0).unwrap();
+            let token = captures.get(1).unwrap_or(matched);
+            let converted = self.case.convert(token.as_str());
+            changed |= converted != token.as_str();
+            let start = token.start() - matched.start();
+            let end = token.end() - matched.start();
+            let matched = matched.as_str();
+            format!("{}{}{}", &matched[..start], converted, &matched[end..])
+        });
+        if !changed {
+            return false;
+        }
+        *text = new_text.into_owned();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::{Case, ChangeCaseProcessor};
+
+    fn change_case(pattern: &str, case: Case, text: &str) -> (String, bool) {
+        let processor = ChangeCaseProcessor {
+            pattern: Regex::new(pattern).unwrap(),
+            case,
+        };
+        let mut text = text.to_string();
+        let changed = processor.process(&mut text);
+        (text, changed)
+    }
+
+    #[test]
+    fn test_change_case() {
+        let text = "max_retries = 3\nretry_delay_ms = 10\n# max_retries stays\n";
+        assert_eq!(
+            change_case(r"(?m)^(\w+) =", Case::Camel, text),
+            (
+                "maxRetries = 3\nretryDelayMs = 10\n# max_retries stays\n".to_string(),
+                true
+            )
+        );
+        assert_eq!(
+            change_case(r"(?m)^\w+", Case::Kebab, "maxRetries = 3").0,
+            "max-retries = 3"
+        );
+        assert_eq!(
+            change_case(r"[a-z_]+_ms", Case::Pascal, text).0,
+            "max_retries = 3\nRetryDelayMs = 10\n# max_retries stays\n"
+        );
+        assert_eq!(
+            change_case(r"\bmax_\w+", Case::Upper, "max_retries").0,
+            "MAX_RETRIES"
+        );
+    }
+
+    #[test]
+    fn test_change_case_unchanged() {
+        assert_eq!(
+            change_case(r"(\w+) =", Case::Snake, "max_retries = 3"),
+            ("max_retries = 3".to_string(), false)
+        );
+        assert_eq!(
+            change_case(r"missing", Case::Lower, "max_retries = 3"),
+            ("max_retries = 3".to_string(), false)
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use regex::Regex;
+use serde::Deserialize;
+
+use super::split_line_ending;
+
+/// Comments or uncomments the lines matching `pattern`.
+///
+/// `pattern` is matched against the line without its indentation and, when uncommenting, without
+/// the comment prefix.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommentProcessor {
+    #[serde(with = "serde_regex")]
+    pattern: Regex,
+    /// Like `#` or `//`
+    prefix: String,
+}
+
+impl CommentProcessor {
+    pub fn comment(&self, text: &mut String) -> bool {
+        self.map_lines(text, |indentation, content| {
+            if content.starts_with(&self.prefix) || !self.pattern.is_match(content) {
+                return None;
+            }
+            Some(format!("{}{} {}", indentation, self.prefix, content))
+        })
+    }
+
+    pub fn uncomment(&self, text: &mut String) -> bool {
+        self.map_lines(text, |indentation, content| {
+            let uncommented = content.strip_prefix(&self.prefix)?;
+            let uncommented = uncommented.strip_prefix(' ').unwrap_or(uncommented);
+            if !self.pattern.is_match(uncommented) {
+                return None;
+            }
+            Some(format!("{}{}", indentation, uncommented))
+        })
+    }
+
+    /// `f` receives the indentation and the rest of each line and returns the replacement line
+    fn map_lines<F>(&self, text: &mut String, f: F) -> bool
+    where
+        F: Fn(&str, &str) -> Option<String>,
+    {
+        let mut changed = false;
+        let mut new_text = String::with_capacity(text.len());
+        for line in text.split_inclusive('\n') {
+            let (line, ending) = split_line_ending(line);
+            let content = line.trim_start();
+            let indentation = &line[..line.len() - content.len()];
+            match f(indentation, content) {
+                Some(new_line) => {
+                    new_text.push_str(&new_line);
+                    changed = true;
+                }
+                None => new_text.push_str(line),
+            }
+            new_text.push_str(ending);
+        }
+        if changed {
+            *text = new_text;
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::CommentProcessor;
+
+    fn processor() -> CommentProcessor {
+        CommentProcessor {
+            pattern: Regex::new(r"^enabled\s*=").unwrap(),
+            prefix: "#".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_comment() {
+        let processor = processor();
+        let mut text = "[section]\n  enabled = true\nother = 1\n".to_string();
+
+        assert!(processor.comment(&mut text));
+        assert_eq!(text, "[section]\n  # enabled = true\nother = 1\n");
+
+        assert!(!processor.comment(&mut text));
+        assert_eq!(text, "[section]\n  # enabled = true\nother = 1\n");
+    }
+
+    #[test]
+    fn test_uncomment() {
+        let processor = processor();
+        let mut text = "  # enabled = true\r\n#other = 1\r\n#enabled=false".to_string();
+
+        assert!(processor.uncomment(&mut text));
+        assert_eq!(text, "  enabled = true\r\n#other = 1\r\nenabled=false");
+
+        assert!(!processor.uncomment(&mut text));
+    }
+}
@@ -0,0 +1,71 @@
+use serde::Deserialize;
+
+/// Ends files with exactly one newline, dropping the blank lines after their last line but not the
+/// trailing whitespace of that line. Empty files are left empty
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnsureFinalNewlineProcessor {
+    /// Remove the final newline instead
+    #[serde(default)]
+    remove: bool,
+}
+
+impl EnsureFinalNewlineProcessor {
+    pub fn process(&self, text: &mut String) -> bool {
+        // Files with Windows line endings get one too
+        let ending = if text.contains("\r\n") { "\r\n" } else { "\n" };
+        let content = match text.rfind(|c: char| !c.is_whitespace()) {
+            Some(last) => match text[last..].find(['\r', '\n']) {
+                Some(line_end) => &text[..last + line_end],
+                None => text.as_str(),
+            },
+            None => "",
+        };
+        let mut new_text = content.to_owned();
+        if !self.remove && !content.is_empty() {
+            new_text.push_str(ending);
+        }
+        if new_text == *text {
+            return false;
+        }
+        *text = new_text;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EnsureFinalNewlineProcessor;
+
+    fn ensure(text: &str, remove: bool) -> (String, bool) {
+        let processor = EnsureFinalNewlineProcessor { remove };
+        let mut text = text.to_string();
+        let changed = processor.process(&mut text);
+        (text, changed)
+    }
+
+    #[test]
+    fn test_ensure_final_newline() {
+        assert_eq!(ensure("a\nb", false), ("a\nb\n".to_string(), true));
+        assert_eq!(ensure("a\nb\n", false), ("a\nb\n".to_string(), false));
+        assert_eq!(
+            ensure("a\nb \n\n\t\n", false),
+            ("a\nb \n".to_string(), true)
+        );
+        assert_eq!(ensure("a\nb\t", false), ("a\nb\t\n".to_string(), true));
+        assert_eq!(
+            ensure("a\r\nb\r\n\r\n", false),
+            ("a\r\nb\r\n".to_string(), true)
+        );
+        assert_eq!(ensure("", false), ("".to_string(), false));
+        assert_eq!(ensure("\n\n", false), ("".to_string(), true));
+    }
+
+    #[test]
+    fn test_remove_final_newline() {
+        assert_eq!(ensure("a\nb\n\n", true), ("a\nb".to_string(), true));
+        assert_eq!(ensure("a\nb", true), ("a\nb".to_string(), false));
+        assert_eq!(ensure("a\nb \n", true), ("a\nb ".to_string(), true));
+        assert_eq!(ensure("", true), ("".to_string(), false));
+    }
+}
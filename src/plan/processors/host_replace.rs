@@ -0,0 +1,114 @@
+use serde::Deserialize;
+
+/// Replaces the host `from` with `to` only where it appears as a whole host, so neither
+/// `sub.old.example.com` nor `old.example.com.evil.org` change when replacing `old.example.com`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostReplaceProcessor {
+    from: String,
+    to: String,
+    /// Only replace hosts inside URLs, right after `scheme://` or `scheme://user@`
+    #[serde(default)]
+    urls_only: bool,
+}
+
+impl HostReplaceProcessor {
+    pub fn process(&self, text: &mut String) -> bool {
+        if self.from.is_empty() {
+            return false;
+        }
+        let lowercase = text.to_ascii_lowercase();
+        let from = self.from.to_ascii_lowercase();
+        let mut new_text = String::with_capacity(text.len());
+        let mut last_end = 0;
+        for (start, _) in lowercase.match_indices(&from) {
+            let end = start + from.len();
+            if start < last_end || !self.is_whole_host(text, start, end) {
+                continue;
+            }
+            new_text.push_str(&text[last_end..start]);
+            new_text.push_str(&self.to);
+            last_end = end;
+        }
+        if last_end == 0 {
+            return false;
+        }
+        new_text.push_str(&text[last_end..]);
+        *text = new_text;
+        true
+    }
+
+    fn is_whole_host(&self, text: &str, start: usize, end: usize) -> bool {
+        let before = &text[..start];
+        let after = &text[end..];
+        if before
+            .chars()
+            .next_back()
+            .is_some_and(|c| is_host_char(c) || c == '.')
+        {
+            return false;
+        }
+        let mut next = after.chars();
+        match next.next() {
+            Some('.') if next.next().is_some_and(is_host_char) => return false,
+            Some(c) if is_host_char(c) => return false,
+            _ => {}
+        }
+        !self.urls_only || is_url_host(before)
+    }
+}
+
+/// Characters that continue a host or a longer token
+fn is_host_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+/// Whether the host that follows `before` is the host of a URL
+fn is_url_host(before: &str) -> bool {
+    let userinfo = match before.rsplit_once("://") {
+        Some((scheme, userinfo)) if scheme.ends_with(|c: char| c.is_ascii_alphanumeric()) => {
+            userinfo
+        }
+        _ => return false,
+    };
+    match userinfo.strip_suffix('@') {
+        Some(userinfo) => !userinfo.contains(|c: char| "/@".contains(c) || c.is_whitespace()),
+        None => userinfo.is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HostReplaceProcessor;
+
+    fn processor(urls_only: bool) -> HostReplaceProcessor {
+        HostReplaceProcessor {
+            from: "old.example.com".to_string(),
+            to: "new.example.com".to_string(),
+            urls_only,
+        }
+    }
+
+    #[test]
+    fn test_host_replace() {
+        let mut text = "url = https://old.example.com/api\nhost: OLD.example.com.\nother: sub.old.example.com, old.example.com.evil.org, old.example.community\n".to_string();
+        assert!(processor(false).process(&mut text));
+        assert_eq!(
+            text,
+            "url = https://new.example.com/api\nhost: new.example.com.\nother: sub.old.example.com, old.example.com.evil.org, old.example.community\n"
+        );
+        assert!(!processor(false).process(&mut text));
+    }
+
+    #[test]
+    fn test_host_replace_urls_only() {
+        let mut text =
+            "a = \"ssh://git@old.example.com:22/repo\"\nb = \"old.example.com\"\nc = \"https://x.org/old.example.com\"\n"
+                .to_string();
+        assert!(processor(true).process(&mut text));
+        assert_eq!(
+            text,
+            "a = \"ssh://git@new.example.com:22/repo\"\nb = \"old.example.com\"\nc = \"https://x.org/old.example.com\"\n"
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::{eyre::eyre, Result};
+use serde::Deserialize;
+
+use crate::plan::read_plan_file;
+
+/// Replaces the lines between the lines containing `begin` and `end` with the content of a file,
+/// keeping the markers
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IncludeBetweenProcessor {
+    begin: String,
+    end: String,
+    /// File relative to the plan with the content to include
+    source_file: Utf8PathBuf,
+    #[serde(skip)]
+    content: Option<String>,
+}
+
+impl IncludeBetweenProcessor {
+    pub async fn load_files(&mut self, directory: &Utf8Path) -> Result<()> {
+        let mut content = read_plan_file(directory, &self.source_file).await?;
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        self.content = Some(content);
+        Ok(())
+    }
+
+    pub fn process(&self, text: &mut String) -> Result<bool> {
+        let content = self
+            .content
+            .as_deref()
+            .ok_or_else(|| eyre!("{} was not loaded", self.source_file))?;
+        let mut new_text = String::with_capacity(text.len());
+        let mut inside = false;
+        for line in text.split_inclusive('\n') {
+            if inside {
+                if !line.contains(&self.end) {
+                    continue;
+                }
+                inside = false;
+                new_text.push_str(content);
+            } else if line.contains(&self.begin) {
+                inside = true;
+                new_text.push_str(line);
+                if !line.ends_with('\n') {
+                    new_text.push('\n');
+                }
+                continue;
+            }
+            new_text.push_str(line);
+        }
+        if inside {
+            return Err(eyre!("{:?} has no matching {:?}", self.begin, self.end));
+        }
+
+        if new_text == *text {
+            return Ok(false);
+        }
+        *text = new_text;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IncludeBetweenProcessor;
+
+    fn processor(content: &str) -> IncludeBetweenProcessor {
+        IncludeBetweenProcessor {
+            begin: "<!-- begin shared -->".to_string(),
+            end: "<!-- end shared -->".to_string(),
+            source_file: "shared.md".into(),
+            content: Some(content.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_include_between() {
+        let processor = processor("## License\nMIT\n");
+        let mut text =
+            "# Title\n<!-- begin shared -->\nold\nlines\n<!-- end shared -->\nEnd\n".to_string();
+        assert!(processor.process(&mut text).unwrap());
+        assert_eq!(
+            text,
+            "# Title\n<!-- begin shared -->\n## License\nMIT\n<!-- end shared -->\nEnd\n"
+        );
+        assert!(!processor.process(&mut text).unwrap());
+
+        let mut text = "<!-- begin shared -->\n<!-- end shared -->".to_string();
+        assert!(processor.process(&mut text).unwrap());
+        assert_eq!(
+            text,
+            "<!-- begin shared -->\n## License\nMIT\n<!-- end shared -->"
+        );
+
+        let mut text = "no markers\n".to_string();
+        assert!(!processor.process(&mut text).unwrap());
+
+        let mut text = "<!-- begin shared -->\nnever ends\n".to_string();
+        assert!(processor.process(&mut text).is_err());
+    }
+}
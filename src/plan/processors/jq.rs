@@ -0,0 +1,153 @@
+use std::{convert::TryFrom, fmt};
+
+use color_eyre::{
+    eyre::{eyre, Context},
+    Report, Result,
+};
+use jaq_interpret::{Ctx, Filter, FilterT, ParseCtx, RcIter, Val};
+use serde::{Deserialize, Serialize};
+use serde_json::{ser::PrettyFormatter, Serializer, Value};
+
+/// Replaces JSON files with the output of a jq `program`, compiled when the plan is loaded
+#[derive(Deserialize)]
+#[serde(try_from = "JqOptions")]
+pub struct JqProcessor {
+    program: String,
+    filter: Filter,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JqOptions {
+    program: String,
+}
+
+impl TryFrom<JqOptions> for JqProcessor {
+    type Error = Report;
+
+    fn try_from(options: JqOptions) -> Result<Self, Self::Error> {
+        let program = options.program;
+        let (parsed, errors) = jaq_parse::parse(&program, jaq_parse::main());
+        let parsed = match parsed {
+            Some(parsed) if errors.is_empty() => parsed,
+            _ => {
+                let errors = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+                return Err(eyre!(
+                    "invalid jq program {:?}: {}",
+                    program,
+                    errors.join(", ")
+                ));
+            }
+        };
+        let mut context = ParseCtx::new(vec![]);
+        context.insert_natives(jaq_core::core());
+        context.insert_defs(jaq_std::std());
+        let filter = context.compile(parsed);
+        if !context.errs.is_empty() {
+            let errors = context
+                .errs
+                .iter()
+                .map(|(error, span)| format!("{} {}", error, &program[span.clone()]))
+                .collect::<Vec<_>>();
+            return Err(eyre!(
+                "invalid jq program {:?}: {}",
+                program,
+                errors.join(", ")
+            ));
+        }
+        Ok(Self { program, filter })
+    }
+}
+
+impl fmt::Debug for JqProcessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JqProcessor")
+            .field("program", &self.program)
+            .finish()
+    }
+}
+
+impl JqProcessor {
+    pub fn process(&self, text: &mut String) -> Result<bool> {
+        let input: Value = serde_json::from_str(text).wrap_err("file is not valid json")?;
+        let inputs = RcIter::new(std::iter::empty());
+        let mut outputs = self
+            .filter
+            .run((Ctx::new(vec![], &inputs), Val::from(input.clone())))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| eyre!("jq program {:?} failed: {}", self.program, err))?;
+        if outputs.len() != 1 {
+            return Err(eyre!(
+                "jq program {:?} returned {} values instead of one",
+                self.program,
+                outputs.len()
+            ));
+        }
+        let output = Value::from(outputs.remove(0));
+        // Comparing the values leaves the formatting of unchanged files alone
+        if output == input {
+            return Ok(false);
+        }
+
+        let indentation = indentation(text);
+        let mut new_text = vec![];
+        let mut serializer = Serializer::with_formatter(
+            &mut new_text,
+            PrettyFormatter::with_indent(indentation.as_bytes()),
+        );
+        output.serialize(&mut serializer)?;
+        let mut new_text = String::from_utf8(new_text)?;
+        if text.ends_with('\n') {
+            new_text.push('\n');
+        }
+        *text = new_text;
+        Ok(true)
+    }
+}
+
+/// Indentation of the first indented line, two spaces when there is none
+fn indentation(text: &str) -> &str {
+    text.lines()
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .find(|indentation| !indentation.is_empty())
+        .unwrap_or("  ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JqProcessor;
+
+    fn processor(program: &str) -> JqProcessor {
+        toml::from_str(&format!("program = {:?}", program)).unwrap()
+    }
+
+    #[test]
+    fn test_jq() {
+        let processor = processor(r#".scripts.lint = "eslint ." | del(.private)"#);
+        let mut text =
+            "{\n    \"name\": \"web\",\n    \"private\": true,\n    \"scripts\": {}\n}\n"
+                .to_string();
+        assert!(processor.process(&mut text).unwrap());
+        assert_eq!(
+            text,
+            "{\n    \"name\": \"web\",\n    \"scripts\": {\n        \"lint\": \"eslint .\"\n    }\n}\n"
+        );
+        assert!(!processor.process(&mut text).unwrap());
+
+        // Same value with another formatting
+        let mut text = r#"{"name": "web", "scripts": {"lint": "eslint ."}}"#.to_string();
+        assert!(!processor.process(&mut text).unwrap());
+    }
+
+    #[test]
+    fn test_jq_errors() {
+        assert!(toml::from_str::<JqProcessor>(r#"program = ".a |""#).is_err());
+        assert!(toml::from_str::<JqProcessor>(r#"program = "undefined_filter""#).is_err());
+
+        let mut text = "not json".to_string();
+        assert!(processor(".").process(&mut text).is_err());
+        let mut text = "[1, 2]".to_string();
+        assert!(processor(".[]").process(&mut text).is_err());
+        assert!(processor(".a").process(&mut text).is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Appends `value` to the JSON array at `path` unless the array already has it, keeping the
+/// formatting of the rest of the file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonArrayAppendProcessor {
+    /// Keys and array indexes separated by dots, like `cors.allowed_origins`, empty for the root
+    path: String,
+    value: Value,
+}
+
+impl JsonArrayAppendProcessor {
+    pub fn process(&self, text: &mut String) -> Result<bool> {
+        let document: Value = serde_json::from_str(text).wrap_err("file is not valid json")?;
+        let items = self
+            .segments()
+            .try_fold(&document, |value, segment| match value {
+                Value::Object(members) => members.get(segment),
+                Value::Array(items) => segment
+                    .parse()
+                    .ok()
+                    .and_then(|index: usize| items.get(index)),
+                _ => None,
+            })
+            .ok_or_else(|| eyre!("{:?} not found", self.path))?
+            .as_array()
+            .ok_or_else(|| eyre!("{:?} is not an array", self.path))?;
+        if items.contains(&self.value) {
+            return Ok(false);
+        }
+
+        let (open, close) = find_array(text, self.segments())
+            .ok_or_else(|| eyre!("failed to find {:?}", self.path))?;
+        let element = serde_json::to_string(&self.value)?;
+        if items.is_empty() {
+            text.replace_range(open + 1..close, &element);
+            return Ok(true);
+        }
+        // New items follow the layout of the first one, on its own line or after a space
+        let inside = &text[open + 1..close];
+        let indentation = &inside[..inside.len() - inside.trim_start().len()];
+        let separator = if indentation.contains('\n') {
+            format!(",{}", indentation)
+        } else {
+            ", ".to_owned()
+        };
+        let last_item_end = open + 1 + inside.trim_end().len();
+        text.insert_str(last_item_end, &format!("{}{}", separator, element));
+        Ok(true)
+    }
+
+    fn segments(&self) -> impl Iterator<Item = &str> {
+        self.path.split('.').filter(|segment| !segment.is_empty())
+    }
+}
+
+/// Positions of the `[` and `]` of the array at `segments` of `text`, which must be valid JSON
+fn find_array<'a>(text: &str, segments: impl Iterator<Item = &'a str>) -> Option<(usize, usize)> {
+    let bytes = text.as_bytes();
+    let mut position = skip_whitespace(bytes, 0);
+    for segment in segments {
+        position = match bytes.get(position)? {
+            b'{' => find_member(text, position, segment)?,
+            b'[' => find_item(bytes, position, segment.parse().ok()?)?,
+            _ => return None,
+        };
+    }
+    if bytes.get(position) != Some(&b'[') {
+        return None;
+    }
+    Some((position, value_end(bytes, position)? - 1))
+}
+
+/// Start of the value of `key` on the object that starts at `open`
+fn find_member(text: &str, open: usize, key: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut position = skip_whitespace(bytes, open + 1);
+    while bytes.get(position) == Some(&b'"') {
+        let key_end = value_end(bytes, position)?;
+        let name: String = serde_json::from_str(&text[position..key_end]).ok()?;
+        // Skips the colon too
+        position = skip_whitespace(bytes, skip_whitespace(bytes, key_end) + 1);
+        if name == key {
+            return Some(position);
+        }
+        position = skip_whitespace(bytes, value_end(bytes, position)?);
+        if bytes.get(position) == Some(&b',') {
+            position = skip_whitespace(bytes, position + 1);
+        }
+    }
+    None
+}
+
+/// Start of the item at `index` on the array that starts at `open`
+fn find_item(bytes: &[u8], open: usize, index: usize) -> Option<usize> {
+    let mut position = skip_whitespace(bytes, open + 1);
+    for _ in 0..index {
+        position = skip_whitespace(bytes, value_end(bytes, position)?);
+        if bytes.get(position) != Some(&b',') {
+            return None;
+        }
+        position = skip_whitespace(bytes, position + 1);
+    }
+    (bytes.get(position) != Some(&b']')).then_some(position)
+}
+
+/// Position right after the value that starts at `start`
+fn value_end(bytes: &[u8], start: usize) -> Option<usize> {
+    match bytes.get(start)? {
+        b'"' => {
+            let mut position = start + 1;
+            loop {
+                match bytes.get(position)? {
+                    b'\\' => position += 2,
+                    b'"' => return Some(position + 1),
+                    _ => position += 1,
+                }
+            }
+        }
+        b'{' | b'[' => {
+            let mut depth = 0;
+            let mut position = start;
+            loop {
+                match bytes.get(position)? {
+                    b'"' => {
+                        position = value_end(bytes, position)?;
+                        continue;
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(position + 1);
+                        }
+                    }
+                    _ => {}
+                }
+                position += 1;
+            }
+        }
+        // Numbers, true, false and null
+        _ => {
+            let mut position = start;
+            while bytes
+                .get(position)
+                .is_some_and(|byte| !b" \t\r\n,]}".contains(byte))
+            {
+                position += 1;
+            }
+            Some(position)
+        }
+    }
+}
+
+fn skip_whitespace(bytes: &[u8], mut position: usize) -> usize {
+    while bytes.get(position).is_some_and(u8::is_ascii_whitespace) {
+        position += 1;
+    }
+    position
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::JsonArrayAppendProcessor;
+
+    fn processor(path: &str) -> JsonArrayAppendProcessor {
+        JsonArrayAppendProcessor {
+            path: path.to_string(),
+            value: json!("https://new.example.com"),
+        }
+    }
+
+    #[test]
+    fn test_json_array_append() {
+        let mut text = r#"{
+  "name": "api",
+  "other": {"allowed_origins": ["x"]},
+  "cors": {
+    "allowed_origins": [
+      "https://old.example.com"
+    ]
+  }
+}
+"#
+        .to_string();
+        assert!(processor("cors.allowed_origins")
+            .process(&mut text)
+            .unwrap());
+        assert_eq!(
+            text,
+            r#"{
+  "name": "api",
+  "other": {"allowed_origins": ["x"]},
+  "cors": {
+    "allowed_origins": [
+      "https://old.example.com",
+      "https://new.example.com"
+    ]
+  }
+}
+"#
+        );
+        assert!(!processor("cors.allowed_origins")
+            .process(&mut text)
+            .unwrap());
+        assert!(processor("name").process(&mut text).is_err());
+        assert!(processor("cors.missing").process(&mut text).is_err());
+    }
+
+    #[test]
+    fn test_json_array_append_inline() {
+        let mut text = r#"[{"origins": ["a, [b]", "c"]}, {"origins": []}]"#.to_string();
+        assert!(processor("0.origins").process(&mut text).unwrap());
+        assert!(processor("1.origins").process(&mut text).unwrap());
+        assert_eq!(
+            text,
+            r#"[{"origins": ["a, [b]", "c", "https://new.example.com"]}, {"origins": ["https://new.example.com"]}]"#
+        );
+    }
+}
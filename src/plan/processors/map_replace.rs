@@ -0,0 +1,179 @@
+use std::{collections::BTreeMap, convert::TryFrom};
+
+use color_eyre::{
+    eyre::{eyre, Context},
+    Report,
+};
+use regex::{Captures, Regex};
+use serde::Deserialize;
+
+/// Replaces each key of `map` with its value in a single pass, trying longer keys first so a
+/// key that is part of another one does not break it
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "MapReplaceOptions")]
+pub struct MapReplaceProcessor {
+    /// All keys as alternatives, longest first
+    pattern: Regex,
+    replacements: Vec<Replacement>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MapReplaceOptions {
+    map: BTreeMap<String, String>,
+    /// Only replace keys that are not part of a longer word
+    #[serde(default)]
+    whole_word: bool,
+    /// Keys are regular expressions, whose groups can be used on their values like `${1}`
+    #[serde(default)]
+    regex: bool,
+}
+
+#[derive(Debug)]
+struct Replacement {
+    from: String,
+    to: String,
+    /// The key anchored to the whole match, to tell which key matched, only for regex keys
+    matcher: Option<Regex>,
+}
+
+impl TryFrom<MapReplaceOptions> for MapReplaceProcessor {
+    type Error = Report;
+
+    fn try_from(options: MapReplaceOptions) -> Result<Self, Self::Error> {
+        let MapReplaceOptions {
+            map,
+            whole_word,
+            regex: keys_are_regexes,
+        } = options;
+        if map.is_empty() {
+            return Err(eyre!("map needs at least one key"));
+        }
+        let mut replacements = map
+            .into_iter()
+            .map(|(from, to)| {
+                let matcher = if keys_are_regexes {
+                    let matcher = Regex::new(&format!("^(?:{})$", from))
+                        .wrap_err_with(|| format!("invalid regex {:?}", from))?;
+                    Some(matcher)
+                } else {
+                    None
+                };
+                Ok(Replacement { from, to, matcher })
+            })
+            .collect::<Result<Vec<_>, Report>>()?;
+        replacements.sort_by_key(|replacement| std::cmp::Reverse(replacement.from.len()));
+
+        let alternatives = replacements
+            .iter()
+            .map(|replacement| {
+                if keys_are_regexes {
+                    format!("(?:{})", replacement.from)
+                } else {
+                    regex::escape(&replacement.from)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("|");
+        let pattern = if whole_word {
+            format!(r"\b(?:{})\b", alternatives)
+        } else {
+            alternatives
+        };
+        Ok(Self {
+            pattern: Regex::new(&pattern)?,
+            replacements,
+        })
+    }
+}
+
+impl MapReplaceProcessor {
+    pub fn process(&self, text: &mut String) -> bool {
+        let mut changed = false;
+        let new_text = self.pattern.replace_all(text, |captures: &Captures| {
+            let matched = &captures[0];
+            let replacement = self.replace(matched);
+            changed |= replacement != matched;
+            replacement
+        });
+        if !changed {
+            return false;
+        }
+        *text = new_text.into_owned();
+        true
+    }
+
+    fn replace(&self, matched: &str) -> String {
+        for replacement in &self.replacements {
+            match &replacement.matcher {
+                None if replacement.from == matched => return replacement.to.clone(),
+                Some(matcher) => {
+                    if let Some(captures) = matcher.captures(matched) {
+                        let mut output = String::new();
+                        captures.expand(&replacement.to, &mut output);
+                        return output;
+                    }
+                }
+                None => {}
+            }
+        }
+        matched.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MapReplaceProcessor;
+
+    fn processor(options: &str) -> MapReplaceProcessor {
+        toml::from_str(options).unwrap()
+    }
+
+    #[test]
+    fn test_map_replace() {
+        let processor = processor(
+            r#"
+            map = { "old-api" = "new-api", "old-api-client" = "api-client", "new-api" = "newer-api" }
+            "#,
+        );
+        let mut text = "uses old-api-client and old-api, not new-api\n".to_string();
+        assert!(processor.process(&mut text));
+        // A single pass does not replace new-api again
+        assert_eq!(text, "uses api-client and new-api, not newer-api\n");
+
+        let mut text = "nothing to rename\n".to_string();
+        assert!(!processor.process(&mut text));
+        assert_eq!(text, "nothing to rename\n");
+    }
+
+    #[test]
+    fn test_map_replace_whole_word() {
+        let processor = processor(
+            r#"
+            map = { user = "account" }
+            whole_word = true
+            "#,
+        );
+        let mut text = "user users user_id superuser user.name".to_string();
+        assert!(processor.process(&mut text));
+        assert_eq!(text, "account users user_id superuser account.name");
+    }
+
+    #[test]
+    fn test_map_replace_regex() {
+        let processor = processor(
+            r#"
+            map = { 'v(\d+)_client' = "client_v${1}", 'legacy_\w+' = "modern" }
+            regex = true
+            "#,
+        );
+        let mut text = "v2_client legacy_thing".to_string();
+        assert!(processor.process(&mut text));
+        assert_eq!(text, "client_v2 modern");
+
+        assert!(toml::from_str::<MapReplaceProcessor>("map = {}").is_err());
+        assert!(
+            toml::from_str::<MapReplaceProcessor>("map = { '(' = 'x' }\nregex = true").is_err()
+        );
+    }
+}
@@ -0,0 +1,179 @@
+mod apply_patch;
+mod change_case;
+mod comment;
+mod ensure_final_newline;
+mod host_replace;
+mod include_between;
+mod jq;
+mod json_array_append;
+mod map_replace;
+mod regex;
+mod section_key_edit;
+mod strip_bom;
+mod trim_whitespace;
+mod wrap_lines;
+
+use camino::Utf8Path;
+use color_eyre::Result;
+use serde::Deserialize;
+
+pub use self::apply_patch::ApplyPatchProcessor;
+pub use self::change_case::ChangeCaseProcessor;
+pub use self::comment::CommentProcessor;
+pub use self::ensure_final_newline::EnsureFinalNewlineProcessor;
+pub use self::host_replace::HostReplaceProcessor;
+pub use self::include_between::IncludeBetweenProcessor;
+pub use self::jq::JqProcessor;
+pub use self::json_array_append::JsonArrayAppendProcessor;
+pub use self::map_replace::MapReplaceProcessor;
+pub use self::regex::RegexProcessor;
+pub use self::section_key_edit::SectionKeyEditProcessor;
+pub use self::strip_bom::StripBomProcessor;
+pub use self::trim_whitespace::TrimWhitespaceProcessor;
+pub use self::wrap_lines::WrapLinesProcessor;
+
+#[derive(Debug, Deserialize)]
+pub struct FileProcessor {
+    #[serde(flatten)]
+    processor: Processor,
+    /// Only run the processor on files with these extensions, all files when empty
+    #[serde(default)]
+    extensions: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Processor {
+    Regex(RegexProcessor),
+    TrimWhitespace(TrimWhitespaceProcessor),
+    ApplyPatch(ApplyPatchProcessor),
+    Comment(CommentProcessor),
+    Uncomment(CommentProcessor),
+    SectionKeyEdit(SectionKeyEditProcessor),
+    HostReplace(HostReplaceProcessor),
+    IncludeBetween(IncludeBetweenProcessor),
+    StripBom(StripBomProcessor),
+    JsonArrayAppend(JsonArrayAppendProcessor),
+    ChangeCase(ChangeCaseProcessor),
+    MapReplace(MapReplaceProcessor),
+    EnsureFinalNewline(EnsureFinalNewlineProcessor),
+    Jq(JqProcessor),
+    WrapLines(WrapLinesProcessor),
+}
+
+impl FileProcessor {
+    pub fn applies_to(&self, file: &Utf8Path) -> bool {
+        if self.extensions.is_empty() {
+            return true;
+        }
+        match file.extension() {
+            Some(extension) => self
+                .extensions
+                .iter()
+                .any(|e| e.trim_start_matches('.') == extension),
+            None => false,
+        }
+    }
+
+    pub async fn load_files(&mut self, directory: &Utf8Path) -> Result<()> {
+        self.processor.load_files(directory).await
+    }
+
+    pub fn process(&self, text: &mut String) -> Result<bool> {
+        self.processor.process(text)
+    }
+
+    pub fn line_local(&self) -> bool {
+        self.processor.line_local()
+    }
+}
+
+impl Processor {
+    /// Reads the files the processor needs, relative to the plan's `directory`
+    pub async fn load_files(&mut self, directory: &Utf8Path) -> Result<()> {
+        match self {
+            Processor::ApplyPatch(processor) => processor.load_files(directory).await,
+            Processor::IncludeBetween(processor) => processor.load_files(directory).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether processing each line on its own gives the same result as processing the whole text
+    pub fn line_local(&self) -> bool {
+        match self {
+            Processor::TrimWhitespace(processor) => processor.line_local(),
+            Processor::WrapLines(processor) => processor.line_local(),
+            Processor::Comment(_) | Processor::Uncomment(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Changes `text` in place and returns whether anything changed
+    pub fn process(&self, text: &mut String) -> Result<bool> {
+        match self {
+            Processor::Regex(processor) => Ok(processor.process(text)),
+            Processor::TrimWhitespace(processor) => Ok(processor.process(text)),
+            Processor::ApplyPatch(processor) => processor.process(text),
+            Processor::Comment(processor) => Ok(processor.comment(text)),
+            Processor::Uncomment(processor) => Ok(processor.uncomment(text)),
+            Processor::SectionKeyEdit(processor) => Ok(processor.process(text)),
+            Processor::HostReplace(processor) => Ok(processor.process(text)),
+            Processor::IncludeBetween(processor) => processor.process(text),
+            Processor::StripBom(processor) => Ok(processor.process(text)),
+            Processor::JsonArrayAppend(processor) => processor.process(text),
+            Processor::ChangeCase(processor) => Ok(processor.process(text)),
+            Processor::MapReplace(processor) => Ok(processor.process(text)),
+            Processor::EnsureFinalNewline(processor) => Ok(processor.process(text)),
+            Processor::Jq(processor) => processor.process(text),
+            Processor::WrapLines(processor) => Ok(processor.process(text)),
+        }
+    }
+}
+
+/// Splits `line` into its content and its line ending, if any
+fn split_line_ending(line: &str) -> (&str, &str) {
+    if let Some(content) = line.strip_suffix("\r\n") {
+        (content, "\r\n")
+    } else if let Some(content) = line.strip_suffix('\n') {
+        (content, "\n")
+    } else {
+        (line, "")
+    }
+}
+
+/// Where a line is relative to the fenced Markdown code blocks, between ``` or ~~~
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FencePosition {
+    Outside,
+    /// The line opening or closing a block
+    Fence,
+    Inside,
+}
+
+/// Follows the code blocks of a text given one line at a time
+#[derive(Debug, Default)]
+struct Fences {
+    open: Option<&'static str>,
+}
+
+impl Fences {
+    fn position(&mut self, line: &str) -> FencePosition {
+        let trimmed = line.trim_start();
+        let marker = ["```", "~~~"]
+            .iter()
+            .copied()
+            .find(|marker| trimmed.starts_with(*marker));
+        match (self.open, marker) {
+            (None, Some(marker)) => {
+                self.open = Some(marker);
+                FencePosition::Fence
+            }
+            (Some(open), Some(marker)) if open == marker => {
+                self.open = None;
+                FencePosition::Fence
+            }
+            (Some(_), _) => FencePosition::Inside,
+            (None, None) => FencePosition::Outside,
+        }
+    }
+}
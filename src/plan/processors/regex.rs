@@ -0,0 +1,213 @@
+use std::{borrow::Cow, ops::Range};
+
+use regex::{Captures, Regex};
+use serde::Deserialize;
+
+use super::{FencePosition, Fences};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegexProcessor {
+    operations: Vec<RegexOperation>,
+    /// Which parts of the text to replace in, with fenced Markdown code blocks
+    #[serde(default)]
+    code_blocks: CodeBlocks,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CodeBlocks {
+    /// The whole text, without looking for code blocks
+    #[default]
+    Include,
+    /// Only outside of code blocks, leaving their fences and contents as they are
+    Skip,
+    /// Only the contents of code blocks
+    Only,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegexOperation {
+    #[serde(with = "serde_regex")]
+    from: Regex,
+    to: String,
+    /// Only replace this many matches, all of them when missing
+    limit: Option<usize>,
+    /// Count `limit` from the last match instead of the first one
+    #[serde(default)]
+    from_end: bool,
+}
+
+impl RegexProcessor {
+    pub fn process(&self, text: &mut String) -> bool {
+        let mut changed = false;
+        for operation in &self.operations {
+            let regions = self.regions(text);
+            if let Some(new_text) = operation.replace(text, regions.as_deref()) {
+                *text = new_text;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// The byte ranges of whole lines to replace in, `None` for all of the text
+    fn regions(&self, text: &str) -> Option<Vec<Range<usize>>> {
+        let targeted = match self.code_blocks {
+            CodeBlocks::Include => return None,
+            CodeBlocks::Skip => FencePosition::Outside,
+            CodeBlocks::Only => FencePosition::Inside,
+        };
+        let mut fences = Fences::default();
+        let mut regions: Vec<Range<usize>> = vec![];
+        let mut start = 0;
+        for line in text.split_inclusive('\n') {
+            let end = start + line.len();
+            if fences.position(line.trim_end()) == targeted {
+                match regions.last_mut() {
+                    Some(region) if region.end == start => region.end = end,
+                    _ => regions.push(start..end),
+                }
+            }
+            start = end;
+        }
+        Some(regions)
+    }
+}
+
+impl RegexOperation {
+    /// The new text, if there was any match inside of `regions`
+    fn replace(&self, text: &str, regions: Option<&[Range<usize>]>) -> Option<String> {
+        let limit = match (self.limit, regions) {
+            (Some(limit), _) => limit,
+            (None, None) => match self.from.replace_all(text, &self.to) {
+                Cow::Owned(new_text) => return Some(new_text),
+                Cow::Borrowed(_) => return None,
+            },
+            (None, Some(_)) => usize::MAX,
+        };
+        // Matches across the edge of a region are left alone too
+        let captures = self
+            .from
+            .captures_iter(text)
+            .filter(|captures| {
+                let matched = captures.get(0).unwrap();
+                regions.is_none_or(|regions| {
+                    regions.iter().any(|region| {
+                        region.start <= matched.start() && matched.end() <= region.end
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        let skip = if self.from_end {
+            captures.len().saturating_sub(limit)
+        } else {
+            0
+        };
+        let selected = captures.iter().skip(skip).take(limit).collect::<Vec<_>>();
+        if selected.is_empty() {
+            return None;
+        }
+        Some(self.expand(text, &selected))
+    }
+
+    fn expand(&self, text: &str, captures: &[&Captures]) -> String {
+        let mut new_text = String::with_capacity(text.len());
+        let mut last_end = 0;
+        for capture in captures {
+            let matched = capture.get(0).unwrap();
+            new_text.push_str(&text[last_end..matched.start()]);
+            capture.expand(&self.to, &mut new_text);
+            last_end = matched.end();
+        }
+        new_text.push_str(&text[last_end..]);
+        new_text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::{CodeBlocks, RegexOperation, RegexProcessor};
+
+    fn replace(limit: Option<usize>, from_end: bool, text: &str) -> (String, bool) {
+        let processor = RegexProcessor {
+            operations: vec![RegexOperation {
+                from: Regex::new(r#"version = "(\d+)""#).unwrap(),
+                to: r#"version = "${1}0""#.to_string(),
+                limit,
+                from_end,
+            }],
+            code_blocks: CodeBlocks::Include,
+        };
+        let mut text = text.to_string();
+        let changed = processor.process(&mut text);
+        (text, changed)
+    }
+
+    #[test]
+    fn test_limit() {
+        let text = "version = \"1\"\nversion = \"2\"\nversion = \"3\"\n";
+        assert_eq!(
+            replace(None, false, text).0,
+            "version = \"10\"\nversion = \"20\"\nversion = \"30\"\n"
+        );
+        assert_eq!(
+            replace(Some(1), false, text).0,
+            "version = \"10\"\nversion = \"2\"\nversion = \"3\"\n"
+        );
+        assert_eq!(
+            replace(Some(2), true, text).0,
+            "version = \"1\"\nversion = \"20\"\nversion = \"30\"\n"
+        );
+        assert_eq!(
+            replace(Some(1), true, "name = \"a\""),
+            ("name = \"a\"".to_string(), false)
+        );
+    }
+
+    #[test]
+    fn test_code_blocks() {
+        let process = |code_blocks: &str, limit: &str, text: &str| {
+            let processor: RegexProcessor = toml::from_str(&format!(
+                r#"
+                code_blocks = "{}"
+                operations = [{{ from = 'http://(\S+)', to = 'https://${{1}}'{} }}]
+                "#,
+                code_blocks, limit
+            ))
+            .unwrap();
+            let mut text = text.to_string();
+            let changed = processor.process(&mut text);
+            (text, changed)
+        };
+        let text = "see http://a.com\n```sh\ncurl http://b.com\n```\nand http://c.com";
+        assert_eq!(
+            process("skip", "", text),
+            (
+                "see https://a.com\n```sh\ncurl http://b.com\n```\nand https://c.com".to_string(),
+                true
+            )
+        );
+        assert_eq!(
+            process("only", "", text),
+            (
+                "see http://a.com\n```sh\ncurl https://b.com\n```\nand http://c.com".to_string(),
+                true
+            )
+        );
+        assert_eq!(
+            process("skip", ", limit = 1, from_end = true", text),
+            (
+                "see http://a.com\n```sh\ncurl http://b.com\n```\nand https://c.com".to_string(),
+                true
+            )
+        );
+        let text = "prose\n~~~\nhttp://only.in/code\n~~~\n";
+        assert_eq!(process("skip", "", text), (text.to_string(), false));
+        let text = "http://only.in/prose\n```\n```\n";
+        assert_eq!(process("only", "", text), (text.to_string(), false));
+    }
+}
@@ -0,0 +1,89 @@
+use serde::Deserialize;
+
+use super::split_line_ending;
+
+/// Sets the value of `key` inside `[section]` of INI, TOML or properties-like files.
+///
+/// Keys with the same name in other sections are left untouched and nothing is added when the
+/// key is missing.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SectionKeyEditProcessor {
+    /// Name between the brackets, like `tool.black`
+    section: String,
+    key: String,
+    /// Written as is, so strings need their quotes, like `"value"`
+    value: String,
+}
+
+impl SectionKeyEditProcessor {
+    pub fn process(&self, text: &mut String) -> bool {
+        let mut in_section = false;
+        let mut new_text = String::with_capacity(text.len());
+        for line in text.split_inclusive('\n') {
+            let (content, ending) = split_line_ending(line);
+            if let Some(section) = section_name(content) {
+                in_section = section == self.section;
+            } else if in_section {
+                if let Some(new_content) = self.edit(content) {
+                    new_text.push_str(&new_content);
+                    new_text.push_str(ending);
+                    continue;
+                }
+            }
+            new_text.push_str(line);
+        }
+
+        if new_text == *text {
+            return false;
+        }
+        *text = new_text;
+        true
+    }
+
+    /// The line with the new value when it sets our key
+    fn edit(&self, line: &str) -> Option<String> {
+        let rest = line.trim_start().strip_prefix(&self.key)?;
+        let separator = rest.trim_start();
+        if !separator.starts_with(['=', ':']) {
+            return None;
+        }
+        let value = separator[1..].trim_start();
+        let prefix_length = line.len() - value.len();
+        Some(format!("{}{}", &line[..prefix_length], self.value))
+    }
+}
+
+/// `[section]` or `[[section]]`
+fn section_name(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let name = line.strip_prefix('[')?.strip_suffix(']')?;
+    let name = name
+        .strip_prefix('[')
+        .and_then(|name| name.strip_suffix(']'))
+        .unwrap_or(name);
+    Some(name.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SectionKeyEditProcessor;
+
+    #[test]
+    fn test_section_key_edit() {
+        let processor = SectionKeyEditProcessor {
+            section: "tool.black".to_string(),
+            key: "line-length".to_string(),
+            value: "100".to_string(),
+        };
+        let mut text = "[tool.isort]\nline-length = 88\n\n[tool.black]\nline-length-x = 1\nline-length  =  88\r\n\n[other]\nline-length = 88\n".to_string();
+
+        assert!(processor.process(&mut text));
+        assert_eq!(
+            text,
+            "[tool.isort]\nline-length = 88\n\n[tool.black]\nline-length-x = 1\nline-length  =  100\r\n\n[other]\nline-length = 88\n"
+        );
+
+        assert!(!processor.process(&mut text));
+    }
+}
@@ -0,0 +1,32 @@
+use serde::Deserialize;
+
+const BOM: char = '\u{feff}';
+
+/// Removes the byte order mark at the start of the text, which files written on Windows often
+/// have and some tools reject
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StripBomProcessor {}
+
+impl StripBomProcessor {
+    pub fn process(&self, text: &mut String) -> bool {
+        if !text.starts_with(BOM) {
+            return false;
+        }
+        text.drain(..BOM.len_utf8());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StripBomProcessor;
+
+    #[test]
+    fn test_strip_bom() {
+        let mut text = "\u{feff}key = 1\n\u{feff}\n".to_string();
+        assert!(StripBomProcessor {}.process(&mut text));
+        assert_eq!(text, "key = 1\n\u{feff}\n");
+        assert!(!StripBomProcessor {}.process(&mut text));
+    }
+}
@@ -0,0 +1,85 @@
+use serde::Deserialize;
+
+use super::split_line_ending;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrimWhitespaceProcessor {
+    /// Also replace multiple blank lines at the end of the file with a single newline
+    #[serde(default)]
+    collapse_final_newlines: bool,
+}
+
+impl TrimWhitespaceProcessor {
+    /// Collapsing the final newlines needs to see the end of the file
+    pub fn line_local(&self) -> bool {
+        !self.collapse_final_newlines
+    }
+
+    pub fn process(&self, text: &mut String) -> bool {
+        let mut new_text = String::with_capacity(text.len());
+        for line in text.split_inclusive('\n') {
+            let (content, ending) = split_line_ending(line);
+            new_text.push_str(content.trim_end());
+            new_text.push_str(ending);
+        }
+
+        if self.collapse_final_newlines {
+            collapse_final_newlines(&mut new_text);
+        }
+
+        if new_text == *text {
+            return false;
+        }
+        *text = new_text;
+        true
+    }
+}
+
+fn collapse_final_newlines(text: &mut String) {
+    let ending = if text.ends_with("\r\n") {
+        "\r\n"
+    } else if text.ends_with('\n') {
+        "\n"
+    } else {
+        return;
+    };
+    let content_length = text.trim_end_matches(['\r', '\n']).len();
+    text.truncate(content_length);
+    if !text.is_empty() {
+        text.push_str(ending);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrimWhitespaceProcessor;
+
+    fn trim(text: &str, collapse_final_newlines: bool) -> (String, bool) {
+        let processor = TrimWhitespaceProcessor {
+            collapse_final_newlines,
+        };
+        let mut text = text.to_string();
+        let changed = processor.process(&mut text);
+        (text, changed)
+    }
+
+    #[test]
+    fn test_trim_whitespace() {
+        assert_eq!(
+            trim("a  \nb\t\r\nc \n\n\n", false),
+            ("a\nb\r\nc\n\n\n".to_string(), true)
+        );
+        assert_eq!(trim("a\nb", false), ("a\nb".to_string(), false));
+        assert_eq!(trim("", false), ("".to_string(), false));
+    }
+
+    #[test]
+    fn test_collapse_final_newlines() {
+        assert_eq!(trim("a \n\n \n", true), ("a\n".to_string(), true));
+        assert_eq!(trim("a\r\n\r\n", true), ("a\r\n".to_string(), true));
+        assert_eq!(trim("a\n", true), ("a\n".to_string(), false));
+        assert_eq!(trim("a", true), ("a".to_string(), false));
+        assert_eq!(trim("\n\n", true), ("".to_string(), true));
+    }
+}
@@ -0,0 +1,210 @@
+use regex::Regex;
+use serde::Deserialize;
+
+use super::{split_line_ending, FencePosition, Fences};
+
+/// Splits lines longer than `width` characters at spaces. Lines are never joined, and words longer
+/// than `width` stay whole on their own line
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WrapLinesProcessor {
+    width: usize,
+    /// Leave the lines of fenced Markdown code blocks, between ``` or ~~~, as they are
+    #[serde(default)]
+    skip_code_blocks: bool,
+    /// Leave the lines matching this regex as they are, like URLs or tables
+    #[serde(default, with = "serde_regex")]
+    skip: Option<Regex>,
+    /// Repeated on the lines a line is split into when it matches at its start, like `\s*# ` for
+    /// comments. Defaults to the indentation of the line
+    #[serde(default, with = "serde_regex")]
+    prefix: Option<Regex>,
+}
+
+impl WrapLinesProcessor {
+    /// Code blocks need to see the lines before
+    pub fn line_local(&self) -> bool {
+        !self.skip_code_blocks
+    }
+
+    pub fn process(&self, text: &mut String) -> bool {
+        let mut new_text = String::with_capacity(text.len());
+        let mut changed = false;
+        let mut fences = Fences::default();
+        for line in text.split_inclusive('\n') {
+            let (content, ending) = split_line_ending(line);
+            if self.skip_code_blocks && fences.position(content) != FencePosition::Outside {
+                new_text.push_str(line);
+                continue;
+            }
+            if content.chars().count() <= self.width
+                || self
+                    .skip
+                    .as_ref()
+                    .is_some_and(|skip| skip.is_match(content))
+            {
+                new_text.push_str(line);
+                continue;
+            }
+            match self.wrap(content) {
+                Some(lines) => {
+                    // The last line of a file has no ending to split it with
+                    let separator = if ending.is_empty() { "\n" } else { ending };
+                    new_text.push_str(&lines.join(separator));
+                    new_text.push_str(ending);
+                    changed = true;
+                }
+                None => new_text.push_str(line),
+            }
+        }
+        if !changed {
+            return false;
+        }
+        *text = new_text;
+        true
+    }
+
+    /// The lines `content` is split into, `None` when it cannot be split
+    fn wrap(&self, content: &str) -> Option<Vec<String>> {
+        let prefix_length = match &self.prefix {
+            Some(prefix) => prefix
+                .find(content)
+                .filter(|found| found.start() == 0)
+                .map(|found| found.end()),
+            None => None,
+        }
+        .unwrap_or_else(|| content.len() - content.trim_start().len());
+        let (prefix, rest) = content.split_at(prefix_length);
+
+        // Only the spaces a line is split at are dropped, keeping runs of spaces elsewhere and the
+        // trailing ones, which are a hard line break in Markdown
+        let body = rest.trim_end_matches(' ');
+        let trailing = &rest[body.len()..];
+        let mut lines = vec![];
+        let mut current = prefix.to_owned();
+        let mut current_length = prefix.chars().count();
+        let mut remaining = body;
+        while !remaining.is_empty() {
+            let word_start = remaining.len() - remaining.trim_start_matches(' ').len();
+            let word_end = remaining[word_start..]
+                .find(' ')
+                .map_or(remaining.len(), |end| word_start + end);
+            let (separator, word) = remaining[..word_end].split_at(word_start);
+            remaining = &remaining[word_end..];
+            let word_length = word.chars().count();
+            let separator_length = separator.chars().count();
+            let empty = current.len() == prefix.len();
+            if !empty && current_length + separator_length + word_length > self.width {
+                lines.push(current);
+                current = prefix.to_owned();
+                current_length = prefix.chars().count();
+            } else {
+                current.push_str(separator);
+                current_length += separator_length;
+            }
+            current.push_str(word);
+            current_length += word_length;
+        }
+        current.push_str(trailing);
+        lines.push(current);
+        if lines.len() == 1 {
+            return None;
+        }
+        Some(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WrapLinesProcessor;
+
+    fn wrap(options: &str, text: &str) -> (String, bool) {
+        let processor: WrapLinesProcessor = toml::from_str(options).unwrap();
+        let mut text = text.to_string();
+        let changed = processor.process(&mut text);
+        (text, changed)
+    }
+
+    #[test]
+    fn test_wrap_lines() {
+        assert_eq!(
+            wrap(
+                "width = 20",
+                "short line\n  the quick brown fox jumps over the lazy dog\r\nlast"
+            ),
+            (
+                "short line\n  the quick brown\r\n  fox jumps over the\r\n  lazy dog\r\nlast"
+                    .to_string(),
+                true
+            )
+        );
+        assert_eq!(
+            wrap("width = 10", "averyveryverylongword ok\n"),
+            ("averyveryverylongword\nok\n".to_string(), true)
+        );
+        assert_eq!(
+            wrap("width = 10", "averyveryverylongword\nfits\n"),
+            ("averyveryverylongword\nfits\n".to_string(), false)
+        );
+        assert_eq!(
+            wrap("width = 10", "the last line of the file"),
+            ("the last\nline of\nthe file".to_string(), true)
+        );
+    }
+
+    #[test]
+    fn test_wrap_lines_spaces() {
+        assert_eq!(
+            wrap(
+                "width = 20",
+                "two spaces  after  the sentence ends  \nnext\n"
+            ),
+            (
+                "two spaces  after\nthe sentence ends  \nnext\n".to_string(),
+                true
+            )
+        );
+        assert_eq!(
+            wrap("width = 10", "a   b   c   d\n"),
+            ("a   b   c\nd\n".to_string(), true)
+        );
+    }
+
+    #[test]
+    fn test_wrap_lines_prefix() {
+        assert_eq!(
+            wrap(
+                r#"
+                width = 16
+                prefix = '\s*# '
+                "#,
+                "    # a comment that is too long\n"
+            ),
+            (
+                "    # a comment\n    # that is\n    # too long\n".to_string(),
+                true
+            )
+        );
+    }
+
+    #[test]
+    fn test_wrap_lines_skip() {
+        let options = r#"
+            width = 12
+            skip_code_blocks = true
+            skip = '^\||https?://'
+        "#;
+        let text = "```sh\necho one two three four\n```\n| a table | row |\nsee https://example.com/long\n";
+        assert_eq!(wrap(options, text), (text.to_string(), false));
+        assert_eq!(
+            wrap(
+                options,
+                "~~~\n```\nstill code here\n~~~\nnot code anymore\n"
+            ),
+            (
+                "~~~\n```\nstill code here\n~~~\nnot code\nanymore\n".to_string(),
+                true
+            )
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use camino::Utf8Path;
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::debug;
+
+/// What a successful run was based on, used to skip repositories on the next runs
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepositoryState {
+    pub plan_checksum: String,
+    pub base_commit: String,
+}
+
+impl RepositoryState {
+    pub async fn load(path: &Utf8Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read(path)
+            .await
+            .wrap_err_with(|| format!("failed to read state {}", path))?;
+        match serde_json::from_slice(&contents) {
+            Ok(state) => Ok(Some(state)),
+            Err(err) => {
+                debug!("ignoring invalid state {}: {}", path, err);
+                Ok(None)
+            }
+        }
+    }
+
+    pub async fn save(&self, path: &Utf8Path) -> Result<()> {
+        let contents = serde_json::to_vec_pretty(self)?;
+        let folder = path
+            .parent()
+            .ok_or_else(|| eyre!("failed to determine state folder"))?;
+        fs::create_dir_all(folder).await?;
+        fs::write(path, &contents)
+            .await
+            .wrap_err_with(|| format!("failed to save state {}", path))?;
+        Ok(())
+    }
+}
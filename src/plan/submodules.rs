@@ -0,0 +1,65 @@
+use camino::Utf8Path;
+use color_eyre::{eyre::Context, Result};
+use tokio::fs;
+
+/// Paths of the submodules declared on a repository's `.gitmodules`
+#[derive(Debug, Default)]
+pub struct Submodules {
+    paths: Vec<String>,
+}
+
+impl Submodules {
+    pub async fn from_repository(directory: &Utf8Path) -> Result<Self> {
+        let path = directory.join(".gitmodules");
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read_to_string(&path)
+            .await
+            .wrap_err_with(|| format!("failed to read {}", path))?;
+        Ok(Self::parse(&contents))
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let paths = contents
+            .lines()
+            .filter_map(|line| {
+                let (key, value) = line.split_once('=')?;
+                (key.trim() == "path").then(|| value.trim().trim_matches('/').to_owned())
+            })
+            .collect();
+        Self { paths }
+    }
+
+    /// Whether `relative_path`, with `/` as separator, is inside a submodule
+    pub fn contains(&self, relative_path: &str) -> bool {
+        self.paths.iter().any(|path| {
+            relative_path
+                .strip_prefix(path.as_str())
+                .is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Submodules;
+
+    #[test]
+    fn test_contains() {
+        let submodules = Submodules::parse(
+            r#"
+[submodule "vendor/lib"]
+	path = vendor/lib
+	url = git@github.com:org/lib.git
+[submodule "docs"]
+	path = docs/
+	url = ../docs.git
+"#,
+        );
+        assert!(submodules.contains("vendor/lib/src/main.rs"));
+        assert!(submodules.contains("docs/index.md"));
+        assert!(!submodules.contains("vendor/library/main.rs"));
+        assert!(!submodules.contains("src/docs/index.md"));
+    }
+}
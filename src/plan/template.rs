@@ -0,0 +1,94 @@
+use tracing::warn;
+
+/// Replaces the `{{ NAME }}` placeholders of `template` with the value of the variable `NAME`.
+/// Other placeholders, like the `${{ github.ref }}` of workflow snippets, are left as they are
+pub fn render(template: &str, variables: &[(String, String)]) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+        output.push_str(&rest[..start]);
+        let name = rest[start + 2..end].trim();
+        match variables.iter().find(|(key, _)| key == name) {
+            Some((_, value)) => output.push_str(value),
+            None => {
+                // Most likely a typo, as the other placeholders do not look like variables
+                if is_variable_name(name) {
+                    warn!(
+                        "leaving the unknown variable {} as it is, use one of {}",
+                        name,
+                        variables
+                            .iter()
+                            .map(|(key, _)| key.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                }
+                output.push_str(&rest[start..end + 2]);
+            }
+        }
+        rest = &rest[end + 2..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// The names of the `{{ NAME }}` placeholders of `template` that look like variables
+pub fn variable_names(template: &str) -> Vec<&str> {
+    let mut names = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+        let name = rest[start + 2..end].trim();
+        if is_variable_name(name) {
+            names.push(name);
+        }
+        rest = &rest[end + 2..];
+    }
+    names
+}
+
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, variable_names};
+
+    #[test]
+    fn test_render() {
+        let variables = vec![
+            ("REPO_NAME".to_owned(), "api".to_owned()),
+            ("VERSION".to_owned(), "1.2".to_owned()),
+        ];
+        assert_eq!(
+            render("v{{VERSION}}-{{ REPO_NAME }}", &variables),
+            "v1.2-api"
+        );
+        assert_eq!(render("no variables", &variables), "no variables");
+        assert_eq!(render("{{ MISSING }}", &variables), "{{ MISSING }}");
+        assert_eq!(render("{{ VERSION", &variables), "{{ VERSION");
+        assert_eq!(
+            render("ref: ${{ github.ref }} on {{ REPO_NAME }}", &variables),
+            "ref: ${{ github.ref }} on api"
+        );
+    }
+
+    #[test]
+    fn test_variable_names() {
+        assert_eq!(
+            variable_names("{{ FILE }} on ${{ github.ref }} {{REPO_NAME}} {{ OPEN"),
+            vec!["FILE", "REPO_NAME"]
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use color_eyre::{eyre::Context, Result};
+use serde::Deserialize;
+
+/// Format that files must still parse as after being processed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl FileFormat {
+    pub fn verify(&self, text: &str) -> Result<()> {
+        match self {
+            FileFormat::Json => serde_json::from_str::<serde_json::Value>(text)
+                .map(|_| ())
+                .wrap_err("processed file is not valid json"),
+            FileFormat::Yaml => {
+                // A file can have many documents separated by `---`
+                for document in serde_yaml::Deserializer::from_str(text) {
+                    serde_yaml::Value::deserialize(document)
+                        .wrap_err("processed file is not valid yaml")?;
+                }
+                Ok(())
+            }
+            FileFormat::Toml => toml::from_str::<toml::Value>(text)
+                .map(|_| ())
+                .wrap_err("processed file is not valid toml"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileFormat;
+
+    #[test]
+    fn test_verify() {
+        assert!(FileFormat::Json.verify(r#"{"a": [1, 2]}"#).is_ok());
+        assert!(FileFormat::Json.verify(r#"{"a": [1, 2}"#).is_err());
+        assert!(FileFormat::Yaml.verify("a: 1\n---\nb: [1, 2]\n").is_ok());
+        assert!(FileFormat::Yaml.verify("a: 1\n---\nb: [1, 2\n").is_err());
+        assert!(FileFormat::Toml.verify("[a]\nb = 1\n").is_ok());
+        assert!(FileFormat::Toml.verify("[a]\nb = \n").is_err());
+    }
+}
@@ -1,40 +1,250 @@
+use std::{
+    collections::hash_map::RandomState,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
 use async_trait::async_trait;
-use color_eyre::{eyre::Context, Result};
+use color_eyre::{
+    eyre::{eyre, Context},
+    Help, Result, SectionExt,
+};
 use lazy_static::lazy_static;
 use regex::Regex;
 use reqwest::{
     header::{HeaderMap, ACCEPT, CONTENT_TYPE, USER_AGENT},
-    Client, ClientBuilder, Method, RequestBuilder,
+    Client, ClientBuilder, Method, RequestBuilder, Response, StatusCode, Url,
 };
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use tracing::{debug, info, instrument, trace};
+use serde_json::{Map, Value};
+use tokio::time;
+use tracing::{debug, info, instrument, trace, warn};
 
 use crate::Repository;
 
 use super::constants::OUR_USER_AGENT;
-use super::{check_api_errors, fetch_from_cache, save_to_cache, Provider};
+use super::{
+    check_api_errors, fetch_from_cache,
+    headers::Headers,
+    save_to_cache,
+    token::{Secret, TokenSource},
+    ChecksState, DraftPr, Issue, Milestone, PrComment, PrState, Provider, PullRequest,
+};
+
+/// GitHub's search API never returns more results than this
+const MAX_SEARCH_RESULTS: u64 = 1000;
+const MAX_RATE_LIMIT_RETRIES: usize = 3;
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
 
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct GithubProvider {
     user: String,
-    token: String,
+    #[serde(flatten)]
+    token_source: TokenSource,
+    /// Loaded from `token_source` once, before using the provider
+    #[serde(skip)]
+    token: Option<Secret>,
     organization: String,
     #[serde(default = "default_url")]
     api_url: String,
+    /// Attempts of each request that fails with a server or network error, only for requests
+    /// that can be sent twice or that did not reach GitHub
+    #[serde(default = "default_max_attempts")]
+    max_attempts: u32,
+    #[serde(default)]
+    headers: Headers,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize)]
 struct PrCreateRequest<'a> {
     title: &'a str,
     body: Option<&'a str>,
     base: &'a str,
     head: &'a str,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+impl<'a> PrCreateRequest<'a> {
+    fn new(pull_request: &PullRequest<'a>) -> Self {
+        const FIELDS: [&str; 4] = ["title", "body", "base", "head"];
+        let mut extra: Map<String, Value> = pull_request
+            .extra
+            .iter()
+            .filter(|(key, _)| {
+                let reserved = FIELDS.contains(&key.as_str());
+                if reserved {
+                    warn!("ignoring extra pull request field {}", key);
+                }
+                !reserved
+            })
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        if pull_request.draft {
+            extra.insert("draft".to_owned(), Value::Bool(true));
+        }
+        Self {
+            title: pull_request.title,
+            body: pull_request.body,
+            base: pull_request.base,
+            head: pull_request.head,
+            extra,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct PrCreateResponse {
     url: String,
+    number: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct PrResponse {
+    state: String,
+    merged_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    total_count: u64,
+    items: Vec<SearchItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchItem {
+    owner: UserResponse,
+    #[serde(flatten)]
+    repository: Repository,
+}
+
+#[derive(Debug, Serialize)]
+struct ReleaseCreateRequest<'a> {
+    tag_name: &'a str,
+    name: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct IssueCreateRequest<'a> {
+    title: &'a str,
+    body: Option<&'a str>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct ReviewersRequest<'a> {
+    reviewers: Vec<&'a str>,
+    team_reviewers: Vec<&'a str>,
+}
+
+impl<'a> ReviewersRequest<'a> {
+    /// Teams are `org/team`, of which GitHub only wants the team. The author of the pull request
+    /// cannot review it
+    fn new(reviewers: &'a [String], organization: &str, author: &str) -> Self {
+        let mut request = Self::default();
+        for reviewer in reviewers {
+            match reviewer.split_once('/') {
+                Some((owner, team)) if owner.eq_ignore_ascii_case(organization) => {
+                    request.team_reviewers.push(team)
+                }
+                Some(_) => warn!("skipping reviewer {} from another organization", reviewer),
+                None if reviewer.eq_ignore_ascii_case(author) => {}
+                None => request.reviewers.push(reviewer),
+            }
+        }
+        request
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct UserResponse {
+    login: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PrNumberResponse {
+    number: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommentResponse {
+    id: u64,
+    #[serde(default)]
+    body: String,
+    user: Option<UserResponse>,
+}
+
+#[derive(Debug, Serialize)]
+struct CommentRequest<'a> {
+    body: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct RefResponse {
+    #[serde(rename = "ref")]
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct IssueResponse {
+    title: String,
+    /// The issues API also lists pull requests, which have this field
+    pull_request: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DraftPrResponse {
+    number: u64,
+    node_id: String,
+    draft: bool,
+    head: HeadResponse,
+}
+
+#[derive(Debug, Deserialize)]
+struct HeadResponse {
+    sha: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CombinedStatusResponse {
+    state: String,
+    total_count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckRunsResponse {
+    check_runs: Vec<CheckRunResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckRunResponse {
+    status: String,
+    conclusion: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct GraphqlRequest<'a> {
+    query: &'a str,
+    variables: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphqlResponse {
+    #[serde(default)]
+    errors: Vec<Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct IssueUpdateRequest {
+    milestone: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct MilestoneResponse {
+    number: u64,
+    title: String,
 }
 
 #[async_trait]
@@ -45,43 +255,57 @@ impl Provider for GithubProvider {
             "{}/repos/{}/{}/pulls",
             self.api_url, self.organization, repository_name
         );
-        let head = format!("{}:{}", self.organization, branch_name);
+        let head = self.head(branch_name);
         let response = self
-            .request(Method::GET, &url)?
-            .query(&[("head", head.as_str()), ("state", "open")])
-            .send()
+            .send(
+                self.request(Method::GET, &url)?
+                    .query(&[("head", head.as_str()), ("state", "open")]),
+            )
             .await?;
 
         let response = check_api_errors(response).await?;
         let body: Vec<Value> = response.json().await?;
         assert!(body.len() <= 1);
-        Ok(body.len() >= 1)
+        Ok(!body.is_empty())
+    }
+
+    #[instrument(skip(self))]
+    async fn pr_state(&self, repository_name: &str, branch_name: &str) -> Result<Option<PrState>> {
+        let url = format!(
+            "{}/repos/{}/{}/pulls",
+            self.api_url, self.organization, repository_name
+        );
+        let head = self.head(branch_name);
+        let response = self
+            .send(self.request(Method::GET, &url)?.query(&[
+                ("head", head.as_str()),
+                ("state", "all"),
+                ("sort", "created"),
+                ("direction", "desc"),
+            ]))
+            .await?;
+
+        let response = check_api_errors(response).await?;
+        let body: Vec<PrResponse> = response.json().await?;
+        Ok(body
+            .first()
+            .map(|pr| match (pr.merged_at.as_ref(), pr.state.as_str()) {
+                (Some(_), _) => PrState::Merged,
+                (None, "open") => PrState::Open,
+                (None, _) => PrState::Closed,
+            }))
     }
 
     #[instrument(skip(self),  fields(organization = self.organization.as_str()))]
-    async fn open_pr(
-        &self,
-        repository_name: &str,
-        base: &str,
-        head: &str,
-        title: &str,
-        body: Option<&str>,
-    ) -> Result<()> {
+    async fn open_pr(&self, repository_name: &str, pull_request: &PullRequest<'_>) -> Result<()> {
         debug!("openning pr");
         let url = format!(
             "{}/repos/{}/{}/pulls",
             self.api_url, self.organization, repository_name
         );
-        let payload = PrCreateRequest {
-            title,
-            body,
-            base,
-            head,
-        };
+        let payload = PrCreateRequest::new(pull_request);
         let response = self
-            .request(Method::POST, &url)?
-            .json(&payload)
-            .send()
+            .send(self.request(Method::POST, &url)?.json(&payload))
             .await?;
         let response = check_api_errors(response)
             .await
@@ -89,6 +313,15 @@ impl Provider for GithubProvider {
         let rv: PrCreateResponse = response.json().await?;
         info!("pr created with url {}", rv.url);
 
+        if let Some(milestone) = pull_request.milestone {
+            self.set_milestone(repository_name, rv.number, milestone)
+                .await?;
+        }
+        if !pull_request.reviewers.is_empty() {
+            self.request_reviewers(repository_name, rv.number, pull_request.reviewers)
+                .await?;
+        }
+
         Ok(())
     }
 
@@ -107,23 +340,429 @@ impl Provider for GithubProvider {
             self.api_url, self.organization
         ));
         while let Some(url) = next_page_url.as_ref() {
-            let (repositories, next_page) = self.list_repositories_per_page(&url).await?;
+            let (repositories, next_page) = self.list_repositories_per_page(url).await?;
             output.extend(repositories);
             next_page_url = next_page
         }
         save_to_cache("github", &self.organization, &output).await?;
         Ok(output)
     }
+
+    #[instrument(skip(self), fields(organization = self.organization.as_str()))]
+    async fn get_repository(&self, name: &str) -> Result<Option<Repository>> {
+        let name = match name.split_once('/') {
+            Some((owner, name)) if owner.eq_ignore_ascii_case(&self.organization) => name,
+            Some(_) => return Ok(None),
+            None => name,
+        };
+        let url = format!("{}/repos/{}/{}", self.api_url, self.organization, name);
+        let response = self.send(self.request(Method::GET, &url)?).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = check_api_errors(response)
+            .await
+            .wrap_err_with(|| format!("failed to get repository {}", name))?;
+        Ok(Some(response.json().await?))
+    }
+
+    #[instrument(skip(self))]
+    async fn search_repositories(&self, query: &str) -> Result<Vec<Repository>> {
+        trace!("searching repositories");
+        let mut output = vec![];
+        let mut next_page_url = Some(format!("{}/search/repositories", self.api_url));
+        let mut query = Some([("q", query), ("per_page", "100")]);
+        while let Some(url) = next_page_url.take() {
+            // The next page urls already have the query
+            let mut request = self.request(Method::GET, &url)?;
+            if let Some(query) = query.take() {
+                request = request.query(&query);
+            }
+            let response = self.send(request).await?;
+            let response = check_api_errors(response)
+                .await
+                .wrap_err("failed to search repositories")?;
+            next_page_url = response
+                .headers()
+                .get("link")
+                .and_then(|header| header.to_str().ok())
+                .and_then(get_next_url)
+                .map(|url| url.to_owned());
+            let body: SearchResponse = response.json().await?;
+            if output.is_empty() && body.total_count > MAX_SEARCH_RESULTS {
+                warn!(
+                    "search found {} repositories but only the first {} are returned",
+                    body.total_count, MAX_SEARCH_RESULTS
+                );
+            }
+            output.extend(self.own_repositories(body.items));
+        }
+        Ok(output)
+    }
+
+    #[instrument(skip(self))]
+    async fn draft_pr(&self, repository_name: &str, branch_name: &str) -> Result<Option<DraftPr>> {
+        let url = format!(
+            "{}/repos/{}/{}/pulls",
+            self.api_url, self.organization, repository_name
+        );
+        let head = self.head(branch_name);
+        let response = self
+            .send(
+                self.request(Method::GET, &url)?
+                    .query(&[("head", head.as_str()), ("state", "open")]),
+            )
+            .await?;
+        let response = check_api_errors(response)
+            .await
+            .wrap_err("failed to find the pull request")?;
+        let body: Vec<DraftPrResponse> = response.json().await?;
+        Ok(body.into_iter().find(|pr| pr.draft).map(|pr| DraftPr {
+            number: pr.number,
+            head_commit: pr.head.sha,
+            id: pr.node_id,
+        }))
+    }
+
+    /// Combines commit statuses and check runs, as CI can report on either
+    #[instrument(skip(self))]
+    async fn checks_state(&self, repository_name: &str, commit: &str) -> Result<ChecksState> {
+        let url = format!(
+            "{}/repos/{}/{}/commits/{}",
+            self.api_url, self.organization, repository_name, commit
+        );
+        let response = self
+            .send(self.request(Method::GET, &format!("{}/status", url))?)
+            .await?;
+        let response = check_api_errors(response)
+            .await
+            .wrap_err("failed to get the commit status")?;
+        let status: CombinedStatusResponse = response.json().await?;
+
+        let response = self
+            .send(
+                self.request(Method::GET, &format!("{}/check-runs", url))?
+                    .query(&[("per_page", "100")]),
+            )
+            .await?;
+        let response = check_api_errors(response)
+            .await
+            .wrap_err("failed to get the commit check runs")?;
+        let check_runs: CheckRunsResponse = response.json().await?;
+
+        Ok(combine_checks(&status, &check_runs.check_runs))
+    }
+
+    /// Only the GraphQL API can take pull requests out of draft
+    #[instrument(skip(self))]
+    async fn mark_ready(&self, repository_name: &str, pull_request: &DraftPr) -> Result<()> {
+        const QUERY: &str = "mutation($id: ID!) { markPullRequestReadyForReview(input: \
+            {pullRequestId: $id}) { pullRequest { isDraft } } }";
+        let response = self
+            .send(
+                self.request(Method::POST, &graphql_url(&self.api_url))?
+                    .json(&GraphqlRequest {
+                        query: QUERY,
+                        variables: serde_json::json!({ "id": pull_request.id }),
+                    }),
+            )
+            .await?;
+        let response = check_api_errors(response)
+            .await
+            .wrap_err("failed to mark the pull request as ready")?;
+        let body: GraphqlResponse = response.json().await?;
+        if !body.errors.is_empty() {
+            return Err(
+                eyre!("failed to mark the pull request as ready").with_section(|| {
+                    Value::Array(body.errors)
+                        .to_string()
+                        .header("Errors:")
+                        .to_string()
+                }),
+            );
+        }
+        info!("pull request {} is ready for review", pull_request.number);
+        Ok(())
+    }
+
+    #[instrument(skip(self), fields(organization = self.organization.as_str()))]
+    async fn create_release(&self, repository_name: &str, tag: &str) -> Result<()> {
+        let url = format!(
+            "{}/repos/{}/{}/releases",
+            self.api_url, self.organization, repository_name
+        );
+        let response = self
+            .send(self.request(Method::GET, &join_path(&format!("{}/tags", url), tag))?)
+            .await?;
+        if response.status() != StatusCode::NOT_FOUND {
+            check_api_errors(response)
+                .await
+                .wrap_err("failed to check release")?;
+            info!("release already exists");
+            return Ok(());
+        }
+
+        let response = self
+            .send(
+                self.request(Method::POST, &url)?
+                    .json(&ReleaseCreateRequest {
+                        tag_name: tag,
+                        name: tag,
+                    }),
+            )
+            .await?;
+        check_api_errors(response)
+            .await
+            .wrap_err("failed to create release")?;
+        info!("release created");
+        Ok(())
+    }
+
+    #[instrument(skip(self), fields(organization = self.organization.as_str()))]
+    async fn languages(&self, repository_name: &str) -> Result<Vec<String>> {
+        let url = format!(
+            "{}/repos/{}/{}/languages",
+            self.api_url, self.organization, repository_name
+        );
+        let response = self.send(self.request(Method::GET, &url)?).await?;
+        let response = check_api_errors(response)
+            .await
+            .wrap_err("failed to list languages")?;
+        // Bytes of code per language
+        let languages: Map<String, Value> = response.json().await?;
+        Ok(languages
+            .into_iter()
+            .map(|(language, _)| language)
+            .collect())
+    }
+
+    #[instrument(skip(self, issue), fields(organization = self.organization.as_str()))]
+    async fn open_issue(&self, repository_name: &str, issue: &Issue<'_>) -> Result<bool> {
+        let url = format!(
+            "{}/repos/{}/{}/issues",
+            self.api_url, self.organization, repository_name
+        );
+        // Only the issues opened by us can be duplicates of this one
+        let mut next_page_url = Some(url.clone());
+        let mut query = Some([
+            ("state", "open"),
+            ("creator", self.user.as_str()),
+            ("per_page", "100"),
+        ]);
+        while let Some(page_url) = next_page_url.take() {
+            // The next page urls already have the query
+            let mut request = self.request(Method::GET, &page_url)?;
+            if let Some(query) = query.take() {
+                request = request.query(&query);
+            }
+            let response = self.send(request).await?;
+            let response = check_api_errors(response)
+                .await
+                .wrap_err("failed to list issues")?;
+            next_page_url = response
+                .headers()
+                .get("link")
+                .and_then(|header| header.to_str().ok())
+                .and_then(get_next_url)
+                .map(|url| url.to_owned());
+            let issues: Vec<IssueResponse> = response.json().await?;
+            if has_open_issue(&issues, issue.title) {
+                info!("issue already opened");
+                return Ok(false);
+            }
+        }
+
+        let response = self
+            .send(self.request(Method::POST, &url)?.json(&IssueCreateRequest {
+                title: issue.title,
+                body: issue.body,
+            }))
+            .await?;
+        check_api_errors(response)
+            .await
+            .wrap_err("failed to open issue")?;
+        info!("issue opened");
+        Ok(true)
+    }
+
+    #[instrument(skip(self, comment))]
+    async fn comment_on_pr(
+        &self,
+        repository_name: &str,
+        branch_name: &str,
+        comment: &PrComment<'_>,
+    ) -> Result<()> {
+        let repository_url = format!(
+            "{}/repos/{}/{}",
+            self.api_url, self.organization, repository_name
+        );
+        let number = self.open_pr_number(repository_name, branch_name).await?;
+
+        let comments_url = format!("{}/issues/{}/comments", repository_url, number);
+        let mut comments: Vec<CommentResponse> = vec![];
+        let mut next_page_url = Some(format!("{}?per_page=100", comments_url));
+        while let Some(url) = next_page_url.take() {
+            let response = self.send(self.request(Method::GET, &url)?).await?;
+            let response = check_api_errors(response)
+                .await
+                .wrap_err("failed to list the pull request comments")?;
+            next_page_url = response
+                .headers()
+                .get("link")
+                .and_then(|header| header.to_str().ok())
+                .and_then(get_next_url)
+                .map(|url| url.to_owned());
+            comments.extend(response.json::<Vec<CommentResponse>>().await?);
+        }
+        // The token can belong to a bot other than the configured user
+        let login = self.authenticated_user().await?;
+        let request = CommentRequest { body: comment.body };
+        let response = match find_comment(&comments, &login, comment.marker) {
+            Some(id) => {
+                debug!("updating comment {}", id);
+                let url = format!("{}/issues/comments/{}", repository_url, id);
+                self.send(self.request(Method::PATCH, &url)?.json(&request))
+                    .await?
+            }
+            None => {
+                self.send(self.request(Method::POST, &comments_url)?.json(&request))
+                    .await?
+            }
+        };
+        check_api_errors(response)
+            .await
+            .wrap_err("failed to comment on the pull request")?;
+        info!("commented on pull request #{}", number);
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_pr_milestone(
+        &self,
+        repository_name: &str,
+        branch_name: &str,
+        milestone: &Milestone,
+    ) -> Result<()> {
+        let number = self.open_pr_number(repository_name, branch_name).await?;
+        self.set_milestone(repository_name, number, milestone).await
+    }
+
+    #[instrument(skip(self))]
+    async fn authenticated_user(&self) -> Result<String> {
+        let url = format!("{}/user", self.api_url);
+        let response = self.send(self.request(Method::GET, &url)?).await?;
+        let response = check_api_errors(response)
+            .await
+            .wrap_err("failed to get the authenticated user")?;
+        let user: UserResponse = response.json().await?;
+        Ok(user.login)
+    }
+
+    #[instrument(skip(self), fields(organization = self.organization.as_str()))]
+    async fn branches(&self, repository_name: &str, prefix: &str) -> Result<Vec<String>> {
+        let mut output = vec![];
+        let refs_url = format!(
+            "{}/repos/{}/{}/git/matching-refs/heads",
+            self.api_url, self.organization, repository_name
+        );
+        let mut next_page_url = Some(format!("{}?per_page=100", join_path(&refs_url, prefix)));
+        while let Some(url) = next_page_url.take() {
+            let response = self.send(self.request(Method::GET, &url)?).await?;
+            let response = check_api_errors(response)
+                .await
+                .wrap_err("failed to list branches")?;
+            next_page_url = response
+                .headers()
+                .get("link")
+                .and_then(|header| header.to_str().ok())
+                .and_then(get_next_url)
+                .map(|url| url.to_owned());
+            let refs: Vec<RefResponse> = response.json().await?;
+            output.extend(refs.into_iter().filter_map(|reference| {
+                reference
+                    .name
+                    .strip_prefix("refs/heads/")
+                    .map(str::to_owned)
+            }));
+        }
+        Ok(output)
+    }
+
+    #[instrument(skip(self), fields(organization = self.organization.as_str()))]
+    async fn delete_branch(&self, repository_name: &str, branch_name: &str) -> Result<()> {
+        let refs_url = format!(
+            "{}/repos/{}/{}/git/refs/heads",
+            self.api_url, self.organization, repository_name
+        );
+        let url = join_path(&refs_url, branch_name);
+        let response = self.send(self.request(Method::DELETE, &url)?).await?;
+        check_api_errors(response)
+            .await
+            .wrap_err_with(|| format!("failed to delete branch {}", branch_name))?;
+        Ok(())
+    }
+
+    fn branch_url(&self, repository_name: &str, branch_name: &str) -> String {
+        let tree_url = format!(
+            "{}/{}/{}/tree",
+            web_url(&self.api_url),
+            self.organization,
+            repository_name
+        );
+        join_path(&tree_url, branch_name)
+    }
 }
 
 impl GithubProvider {
+    /// Keys of the provider in plans, including the flattened token source
+    pub const FIELDS: [&'static str; 10] = [
+        "user",
+        "token",
+        "token_env",
+        "token_file",
+        "token_command",
+        "token_keyring",
+        "organization",
+        "api_url",
+        "max_attempts",
+        "headers",
+    ];
+
+    /// The searched repositories of the organization, as the later calls are made on it and a
+    /// query can also match other owners
+    fn own_repositories(&self, items: Vec<SearchItem>) -> impl Iterator<Item = Repository> + '_ {
+        items.into_iter().filter_map(move |item| {
+            if item.owner.login.eq_ignore_ascii_case(&self.organization) {
+                return Some(item.repository);
+            }
+            debug!(
+                "skipping {} of another owner {}",
+                item.repository.name, item.owner.login
+            );
+            None
+        })
+    }
+
+    /// Pull request heads are `owner:branch`, on the organization unless the owner is given
+    fn head(&self, branch_name: &str) -> String {
+        if branch_name.contains(':') {
+            return branch_name.to_owned();
+        }
+        format!("{}:{}", self.organization, branch_name)
+    }
+
+    pub async fn load_token(&mut self) -> Result<()> {
+        self.token = Some(self.token_source.load().await?);
+        Ok(())
+    }
+
     #[instrument(skip(self))]
     async fn list_repositories_per_page(
         &self,
         url: &str,
     ) -> Result<(Vec<Repository>, Option<String>)> {
         debug!("Fetching repositories on {}", &url);
-        let response = self.request(Method::GET, &url)?.send().await?;
+        let response = self.send(self.request(Method::GET, url)?).await?;
 
         let response = check_api_errors(response).await?;
         let link_header = response
@@ -139,10 +778,197 @@ impl GithubProvider {
         Ok((repositories, next_page))
     }
 
+    #[instrument(skip(self))]
+    async fn request_reviewers(
+        &self,
+        repository_name: &str,
+        pull_request_number: u64,
+        reviewers: &[String],
+    ) -> Result<()> {
+        let request = ReviewersRequest::new(reviewers, &self.organization, &self.user);
+        if request.reviewers.is_empty() && request.team_reviewers.is_empty() {
+            return Ok(());
+        }
+        let url = format!(
+            "{}/repos/{}/{}/pulls/{}/requested_reviewers",
+            self.api_url, self.organization, repository_name, pull_request_number
+        );
+        let response = self
+            .send(self.request(Method::POST, &url)?.json(&request))
+            .await?;
+        check_api_errors(response)
+            .await
+            .wrap_err("failed to request reviewers")?;
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_milestone(
+        &self,
+        repository_name: &str,
+        issue_number: u64,
+        milestone: &Milestone,
+    ) -> Result<()> {
+        let milestone_number = match self.find_milestone(repository_name, milestone).await? {
+            Some(number) => number,
+            None => {
+                warn!("milestone {:?} does not exist, skipping it", milestone);
+                return Ok(());
+            }
+        };
+        // Setting the same milestone again is not an error, so re-runs are safe
+        let url = format!(
+            "{}/repos/{}/{}/issues/{}",
+            self.api_url, self.organization, repository_name, issue_number
+        );
+        let response = self
+            .send(
+                self.request(Method::PATCH, &url)?
+                    .json(&IssueUpdateRequest {
+                        milestone: milestone_number,
+                    }),
+            )
+            .await?;
+        check_api_errors(response)
+            .await
+            .wrap_err("failed to set milestone")?;
+        Ok(())
+    }
+
+    async fn find_milestone(
+        &self,
+        repository_name: &str,
+        milestone: &Milestone,
+    ) -> Result<Option<u64>> {
+        let title = match milestone {
+            Milestone::Number(number) => return Ok(Some(*number)),
+            Milestone::Title(title) => title,
+        };
+        let mut next_page_url = Some(format!(
+            "{}/repos/{}/{}/milestones?state=open&per_page=100",
+            self.api_url, self.organization, repository_name
+        ));
+        while let Some(url) = next_page_url.take() {
+            let response = self.send(self.request(Method::GET, &url)?).await?;
+            let response = check_api_errors(response)
+                .await
+                .wrap_err("failed to list milestones")?;
+            next_page_url = response
+                .headers()
+                .get("link")
+                .and_then(|header| header.to_str().ok())
+                .and_then(get_next_url)
+                .map(|url| url.to_owned());
+            let milestones: Vec<MilestoneResponse> = response.json().await?;
+            if let Some(found) = milestones.into_iter().find(|m| &m.title == title) {
+                return Ok(Some(found.number));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The number of the branch's open pull request
+    async fn open_pr_number(&self, repository_name: &str, branch_name: &str) -> Result<u64> {
+        let url = format!(
+            "{}/repos/{}/{}/pulls",
+            self.api_url, self.organization, repository_name
+        );
+        let head = self.head(branch_name);
+        let response = self
+            .send(
+                self.request(Method::GET, &url)?
+                    .query(&[("head", head.as_str()), ("state", "open")]),
+            )
+            .await?;
+        let response = check_api_errors(response)
+            .await
+            .wrap_err("failed to find the pull request")?;
+        let pull_requests: Vec<PrNumberResponse> = response.json().await?;
+        Ok(pull_requests
+            .first()
+            .ok_or_else(|| eyre!("{} has no open pull request", head))?
+            .number)
+    }
+
+    /// Sends `request`, retrying with backoff on server errors and network failures up to
+    /// `max_attempts` times, and waiting when the rate limit is exceeded, which happens easily
+    /// with the search API's lower limits. Other errors, like authentication or validation ones,
+    /// are returned right away
+    async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        self.send_with_delay(request, time::sleep).await
+    }
+
+    /// `send`, waiting with `delay` between attempts. Requests that create something, like pull
+    /// requests or comments, are only retried when they did not reach GitHub, as a server error
+    /// can come after it was created
+    async fn send_with_delay<F: Future<Output = ()>>(
+        &self,
+        request: RequestBuilder,
+        delay: impl Fn(Duration) -> F,
+    ) -> Result<Response> {
+        let idempotent = request
+            .try_clone()
+            .and_then(|request| request.build().ok())
+            .is_some_and(|request| {
+                [Method::GET, Method::PATCH, Method::PUT, Method::DELETE].contains(request.method())
+            });
+        let mut attempt = 1;
+        let mut rate_limit_retries = 0;
+        loop {
+            let retry = request
+                .try_clone()
+                .ok_or_else(|| eyre!("failed to clone request"))?;
+            let last_attempt = attempt >= self.max_attempts;
+            let response = match retry.send().await {
+                Ok(response) => response,
+                Err(err)
+                    if !last_attempt && (err.is_connect() || idempotent && is_transient(&err)) =>
+                {
+                    let wait = backoff_delay(attempt, random());
+                    warn!("request failed, retrying in {:?}: {}", wait, err);
+                    delay(wait).await;
+                    attempt += 1;
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if rate_limit_retries < MAX_RATE_LIMIT_RETRIES {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                if let Some(wait) = rate_limit_delay(response.status(), response.headers(), now) {
+                    warn!("rate limit exceeded, retrying in {:?}", wait);
+                    delay(wait).await;
+                    rate_limit_retries += 1;
+                    continue;
+                }
+            }
+            if response.status().is_server_error() && idempotent && !last_attempt {
+                let wait = backoff_delay(attempt, random());
+                warn!(
+                    "github answered {}, retrying in {:?}",
+                    response.status(),
+                    wait
+                );
+                delay(wait).await;
+                attempt += 1;
+                continue;
+            }
+            return Ok(response);
+        }
+    }
+
+    /// The plan's headers go last, so they can replace the default ones
     fn request(&self, method: Method, url: &str) -> Result<RequestBuilder> {
         Ok(client()?
             .request(method, url)
-            .basic_auth(&self.user, Some(&self.token)))
+            .basic_auth(&self.user, Some(self.token()?))
+            .headers(self.headers.to_header_map()))
+    }
+
+    fn token(&self) -> Result<&str> {
+        self.token
+            .as_ref()
+            .map(|token| token.expose())
+            .ok_or_else(|| eyre!("github token was not loaded"))
     }
 }
 
@@ -157,6 +983,106 @@ fn client() -> Result<Client> {
     Ok(client)
 }
 
+fn combine_checks(status: &CombinedStatusResponse, check_runs: &[CheckRunResponse]) -> ChecksState {
+    const FAILURES: [&str; 4] = ["failure", "cancelled", "timed_out", "action_required"];
+    // Without any status or check run, CI has not even queued yet
+    if status.total_count == 0 && check_runs.is_empty() {
+        return ChecksState::Pending;
+    }
+    // GitHub reports a pending combined state without any status, which the check runs decide
+    let status_state = match status.state.as_str() {
+        _ if status.total_count == 0 => ChecksState::Success,
+        "success" => ChecksState::Success,
+        "pending" => ChecksState::Pending,
+        _ => ChecksState::Failure,
+    };
+    let failed = check_runs
+        .iter()
+        .any(|run| FAILURES.contains(&run.conclusion.as_deref().unwrap_or_default()));
+    let pending = check_runs.iter().any(|run| run.status != "completed");
+    match status_state {
+        ChecksState::Failure => ChecksState::Failure,
+        _ if failed => ChecksState::Failure,
+        ChecksState::Pending => ChecksState::Pending,
+        _ if pending => ChecksState::Pending,
+        _ => ChecksState::Success,
+    }
+}
+
+/// GitHub Enterprise serves REST on `/api/v3` and GraphQL on `/api/graphql`
+fn graphql_url(api_url: &str) -> String {
+    let api_url = api_url.trim_end_matches('/');
+    match api_url.strip_suffix("/v3") {
+        Some(base) => format!("{}/graphql", base),
+        None => format!("{}/graphql", api_url),
+    }
+}
+
+/// The website of the API, which GitHub Enterprise serves under `/api/v3`
+fn web_url(api_url: &str) -> String {
+    let api_url = api_url.trim_end_matches('/');
+    if let Some(base) = api_url.strip_suffix("/api/v3") {
+        return base.to_owned();
+    }
+    match api_url.strip_prefix("https://api.") {
+        Some(host) => format!("https://{}", host),
+        None => api_url.to_owned(),
+    }
+}
+
+/// Appends a branch or tag name to the path of `url`, encoding characters like `#` or `%` and
+/// keeping its slashes
+fn join_path(url: &str, name: &str) -> String {
+    let mut joined = match Url::parse(url) {
+        Ok(joined) => joined,
+        Err(_) => return format!("{}/{}", url, name),
+    };
+    if let Ok(mut segments) = joined.path_segments_mut() {
+        segments.extend(name.split('/'));
+    }
+    joined.to_string()
+}
+
+/// How long to wait before retrying, when the response says that the rate limit was exceeded
+fn rate_limit_delay(status: StatusCode, headers: &HeaderMap, now: Duration) -> Option<Duration> {
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let header = |name: &str| -> Option<u64> { headers.get(name)?.to_str().ok()?.parse().ok() };
+    if let Some(seconds) = header("retry-after") {
+        return Some(Duration::from_secs(seconds));
+    }
+    if header("x-ratelimit-remaining") != Some(0) {
+        return None;
+    }
+    let reset = Duration::from_secs(header("x-ratelimit-reset")?);
+    // A second more, as the reset time is rounded
+    Some(reset.saturating_sub(now) + Duration::from_secs(1))
+}
+
+/// Network errors that can go away by trying again
+fn is_transient(err: &reqwest::Error) -> bool {
+    err.is_timeout() || err.is_connect() || err.is_request()
+}
+
+/// Exponential backoff with jitter, so concurrent repositories do not retry all at once: half of
+/// the delay is fixed and the other half random
+fn backoff_delay(attempt: u32, random: u64) -> Duration {
+    let exponential = BASE_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_BACKOFF);
+    let half = exponential.as_millis() as u64 / 2;
+    Duration::from_millis(half + random % (half + 1))
+}
+
+fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+fn default_max_attempts() -> u32 {
+    4
+}
+
 fn default_url() -> String {
     "https://api.github.com".to_owned()
 }
@@ -166,30 +1092,82 @@ fn get_next_url(link_header: &str) -> Option<&str> {
         static ref RE: Regex = Regex::new(r#"<(.+?)>; rel="next""#).unwrap();
     }
     RE.captures(link_header)
-        .map(|c| c.get(1))
-        .flatten()
+        .and_then(|c| c.get(1))
         .map(|m| m.as_str())
 }
 
+fn has_open_issue(issues: &[IssueResponse], title: &str) -> bool {
+    issues
+        .iter()
+        .any(|issue| issue.pull_request.is_none() && issue.title == title)
+}
+
+/// Our own comment with `marker`, as other people could quote it
+fn find_comment(comments: &[CommentResponse], user: &str, marker: &str) -> Option<u64> {
+    comments
+        .iter()
+        .find(|comment| {
+            comment
+                .user
+                .as_ref()
+                .is_some_and(|author| author.login.eq_ignore_ascii_case(user))
+                && comment.body.contains(marker)
+        })
+        .map(|comment| comment.id)
+}
+
 #[cfg(test)]
 mod tests {
+    use serde_json::json;
+    #[cfg(docker)]
+    use serde_json::Map;
+    #[cfg(docker)]
     use stub_server::start_wiremock;
 
-    use crate::{providers::Provider, setup_error_handlers};
+    use crate::providers::{Issue, Milestone, PrComment, Provider, PullRequest};
+    #[cfg(docker)]
+    use crate::{providers::PrState, setup_error_handlers};
+
+    use std::{
+        future,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use reqwest::{header::HeaderMap, Method, StatusCode};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
 
-    use super::{get_next_url, GithubProvider};
+    use super::Secret;
+    use super::{
+        backoff_delay, combine_checks, find_comment, get_next_url, graphql_url, has_open_issue,
+        rate_limit_delay, web_url, CheckRunResponse, CombinedStatusResponse, CommentResponse,
+        IssueResponse, PrCreateRequest, ReviewersRequest, SearchItem,
+    };
+    use super::{GithubProvider, TokenSource};
+    use crate::providers::ChecksState;
+
+    /// A provider of the `fix-it` organization for `test-user`, with a token
+    fn test_provider(api_url: &str) -> GithubProvider {
+        GithubProvider {
+            user: "test-user".to_string(),
+            token_source: TokenSource::default(),
+            token: Some(Secret("bebacafe".to_string())),
+            organization: "fix-it".to_string(),
+            api_url: api_url.to_string(),
+            max_attempts: 1,
+            headers: Default::default(),
+        }
+    }
 
     #[cfg(docker)]
     #[tokio::test]
     async fn test_github() {
-        setup_error_handlers().ok();
+        setup_error_handlers("info").ok();
         let base_url = start_wiremock().await.unwrap();
-        let provider = GithubProvider {
-            user: "test-user".to_string(),
-            token: "bebacafe".to_string(),
-            organization: "fix-it".to_string(),
-            api_url: format!("{}/github", base_url),
-        };
+        let provider = test_provider(&format!("{}/github", base_url));
 
         let repositories = provider.list_repositories(false).await.unwrap();
         assert_eq!(repositories.len(), 2);
@@ -203,12 +1181,500 @@ mod tests {
             .is_pr_open("fix-it-1", "invalid-branch")
             .await
             .expect("failed to check if a pr for invalid branch is not open"));
+        assert_eq!(
+            provider
+                .pr_state("fix-it-1", "merged-branch")
+                .await
+                .expect("failed to get the state of a merged pr"),
+            Some(PrState::Merged)
+        );
+        assert_eq!(
+            provider
+                .pr_state("fix-it-1", "invalid-branch")
+                .await
+                .expect("failed to get the state of a missing pr"),
+            None
+        );
+        let pull_request = PullRequest {
+            base: "base",
+            head: "head",
+            title: "title",
+            body: Some("body"),
+            milestone: None,
+            draft: false,
+            reviewers: &[],
+            extra: &Map::new(),
+        };
         provider
-            .open_pr("fix-it-2", "base", "head", "title", Some("body"))
+            .open_pr("fix-it-2", &pull_request)
             .await
             .expect("failed to open pr");
     }
 
+    #[test]
+    fn test_reviewers_request() {
+        let reviewers = ["alice", "Fix-It/platform", "other/team", "bot"].map(String::from);
+        let request = ReviewersRequest::new(&reviewers, "fix-it", "Bot");
+        assert_eq!(
+            request,
+            ReviewersRequest {
+                reviewers: vec!["alice"],
+                team_reviewers: vec!["platform"],
+            }
+        );
+    }
+
+    #[test]
+    fn test_head() {
+        let provider = test_provider("");
+        assert_eq!(provider.head("branch"), "fix-it:branch");
+        assert_eq!(provider.head("staging:branch"), "staging:branch");
+    }
+
+    #[test]
+    fn test_own_repositories() {
+        let items: Vec<SearchItem> = serde_json::from_value(json!([
+            {
+                "name": "api",
+                "private": false,
+                "fork": false,
+                "ssh_url": "git@github.com:fix-it/api.git",
+                "owner": { "login": "Fix-It" }
+            },
+            {
+                "name": "api",
+                "private": false,
+                "fork": true,
+                "ssh_url": "git@github.com:someone/api.git",
+                "owner": { "login": "someone" }
+            }
+        ]))
+        .unwrap();
+        let repositories = test_provider("")
+            .own_repositories(items)
+            .collect::<Vec<_>>();
+        assert_eq!(repositories.len(), 1);
+        assert_eq!(repositories[0].ssh_url, "git@github.com:fix-it/api.git");
+    }
+
+    #[test]
+    fn test_branch_url() {
+        let provider = test_provider("https://api.github.com");
+        assert_eq!(
+            provider.branch_url("api", "fix/issue#12-100%"),
+            "https://github.com/fix-it/api/tree/fix/issue%2312-100%25"
+        );
+    }
+
+    #[test]
+    fn test_request_headers() {
+        let provider = GithubProvider {
+            headers: toml::from_str(
+                r#"
+                x-api-key = "gateway-key"
+                user-agent = "corporate-agent"
+                "#,
+            )
+            .unwrap(),
+            ..test_provider("")
+        };
+        let request = provider
+            .request(Method::GET, "https://api.github.com/user")
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()["x-api-key"], "gateway-key");
+        assert_eq!(request.headers()["user-agent"], "corporate-agent");
+        assert!(request.headers().contains_key("authorization"));
+    }
+
+    #[test]
+    fn test_create_request_extra() {
+        let extra = json!({"maintainer_can_modify": true, "title": "other"});
+        let pull_request = PullRequest {
+            base: "base",
+            head: "head",
+            title: "title",
+            body: None,
+            milestone: None,
+            draft: false,
+            reviewers: &[],
+            extra: extra.as_object().unwrap(),
+        };
+        let payload = serde_json::to_value(PrCreateRequest::new(&pull_request)).unwrap();
+        assert_eq!(
+            payload,
+            json!({
+                "title": "title",
+                "body": null,
+                "base": "base",
+                "head": "head",
+                "maintainer_can_modify": true,
+            })
+        );
+
+        let pull_request = PullRequest {
+            draft: true,
+            reviewers: &[],
+            ..pull_request
+        };
+        let payload = serde_json::to_value(PrCreateRequest::new(&pull_request)).unwrap();
+        assert_eq!(payload["draft"], json!(true));
+    }
+
+    #[test]
+    fn test_combine_checks() {
+        let status = |state: &str, total_count| CombinedStatusResponse {
+            state: state.to_string(),
+            total_count,
+        };
+        let run = |status: &str, conclusion: Option<&str>| CheckRunResponse {
+            status: status.to_string(),
+            conclusion: conclusion.map(str::to_string),
+        };
+        assert_eq!(
+            combine_checks(&status("pending", 0), &[]),
+            ChecksState::Pending
+        );
+        assert_eq!(
+            combine_checks(&status("pending", 0), &[run("completed", Some("success"))]),
+            ChecksState::Success
+        );
+        assert_eq!(
+            combine_checks(&status("pending", 1), &[]),
+            ChecksState::Pending
+        );
+        assert_eq!(
+            combine_checks(
+                &status("success", 1),
+                &[run("completed", Some("success")), run("in_progress", None)]
+            ),
+            ChecksState::Pending
+        );
+        assert_eq!(
+            combine_checks(
+                &status("pending", 0),
+                &[run("completed", Some("failure")), run("queued", None)]
+            ),
+            ChecksState::Failure
+        );
+        assert_eq!(
+            combine_checks(&status("error", 1), &[]),
+            ChecksState::Failure
+        );
+    }
+
+    #[test]
+    fn test_has_open_issue() {
+        let issues: Vec<IssueResponse> = serde_json::from_value(json!([
+            { "title": "Upgrade the runtime", "pull_request": { "url": "x" } },
+            { "title": "Rotate the keys" },
+        ]))
+        .unwrap();
+        assert!(has_open_issue(&issues, "Rotate the keys"));
+        assert!(!has_open_issue(&issues, "Upgrade the runtime"));
+        assert!(!has_open_issue(&issues, "Something else"));
+    }
+
+    #[test]
+    fn test_find_comment() {
+        let comments: Vec<CommentResponse> = serde_json::from_value(json!([
+            { "id": 1, "body": "Quoting <!-- marker -->", "user": { "login": "reviewer" } },
+            { "id": 2, "body": "Unrelated", "user": { "login": "Bot" } },
+            { "id": 3, "body": "<!-- marker -->\nDetails", "user": { "login": "Bot" } },
+            { "id": 4, "user": null },
+        ]))
+        .unwrap();
+        assert_eq!(find_comment(&comments, "bot", "<!-- marker -->"), Some(3));
+        assert_eq!(find_comment(&comments, "bot", "<!-- other -->"), None);
+    }
+
+    #[test]
+    fn test_graphql_url() {
+        assert_eq!(
+            graphql_url("https://api.github.com"),
+            "https://api.github.com/graphql"
+        );
+        assert_eq!(
+            graphql_url("https://github.example.com/api/v3/"),
+            "https://github.example.com/api/graphql"
+        );
+    }
+
+    #[test]
+    fn test_web_url() {
+        assert_eq!(web_url("https://api.github.com"), "https://github.com");
+        assert_eq!(
+            web_url("https://github.example.com/api/v3/"),
+            "https://github.example.com"
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_delay() {
+        let now = Duration::from_secs(1_000);
+        let mut headers = HeaderMap::new();
+        assert_eq!(rate_limit_delay(StatusCode::OK, &headers, now), None);
+        assert_eq!(rate_limit_delay(StatusCode::FORBIDDEN, &headers, now), None);
+
+        headers.insert("x-ratelimit-remaining", "0".parse().unwrap());
+        headers.insert("x-ratelimit-reset", "1010".parse().unwrap());
+        assert_eq!(
+            rate_limit_delay(StatusCode::FORBIDDEN, &headers, now),
+            Some(Duration::from_secs(11))
+        );
+
+        headers.insert("retry-after", "30".parse().unwrap());
+        assert_eq!(
+            rate_limit_delay(StatusCode::TOO_MANY_REQUESTS, &headers, now),
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        assert_eq!(backoff_delay(1, 0), Duration::from_millis(250));
+        assert_eq!(backoff_delay(1, 250), Duration::from_millis(500));
+        assert_eq!(backoff_delay(3, 0), Duration::from_millis(1000));
+        assert_eq!(backoff_delay(3, 1001), Duration::from_millis(1000));
+        assert_eq!(backoff_delay(100, 15_000), Duration::from_secs(30));
+        assert_eq!(backoff_delay(100, 0), Duration::from_secs(15));
+    }
+
+    /// Answers each connection with the next of `statuses`
+    async fn serve(statuses: &'static [u16]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                // GET requests have no body, so the headers are the whole request
+                let mut request = vec![];
+                let mut buffer = vec![0; 4096];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let response = format!(
+                    "HTTP/1.1 {} Status\r\ncontent-length: 2\r\nconnection: close\r\n\r\n[]",
+                    status
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        url
+    }
+
+    /// Answers each connection with the next of `responses`, as extra headers and body, where
+    /// `{url}` is the server's own, and keeps the first line of each request
+    async fn serve_responses(
+        responses: Vec<(&'static str, &'static str)>,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let (server_url, server_requests) = (url.clone(), requests.clone());
+        tokio::spawn(async move {
+            for (headers, body) in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![];
+                let mut buffer = vec![0; 4096];
+                let headers_end = loop {
+                    if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break end + 4;
+                    }
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                };
+                let head = String::from_utf8_lossy(&request[..headers_end]).to_lowercase();
+                let content_length = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length: "))
+                    .map_or(0, |length| length.trim().parse().unwrap());
+                while request.len() < headers_end + content_length {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let request_line = String::from_utf8_lossy(&request)
+                    .lines()
+                    .next()
+                    .unwrap()
+                    .to_owned();
+                server_requests.lock().unwrap().push(request_line);
+                let headers = headers.replace("{url}", &server_url);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\n{}content-length: {}\r\nconnection: close\r\n\r\n{}",
+                    headers,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn test_comment_on_pr_pages() {
+        let (api_url, requests) = serve_responses(vec![
+            ("", r#"[{ "number": 7 }]"#),
+            (
+                "link: <{url}/repos/fix-it/api/issues/7/comments?per_page=100&page=2>; rel=\"next\"\r\n",
+                r#"[{ "id": 1, "body": "<!-- marker -->", "user": { "login": "test-user" } }]"#,
+            ),
+            (
+                "",
+                r#"[{ "id": 2, "body": "<!-- marker -->\nOld", "user": { "login": "fix-it-bot" } }]"#,
+            ),
+            ("", r#"{ "login": "fix-it-bot" }"#),
+            ("", "{}"),
+        ])
+        .await;
+        let provider = test_provider(&api_url);
+        let comment = PrComment {
+            marker: "<!-- marker -->",
+            body: "<!-- marker -->\nNew",
+        };
+        provider
+            .comment_on_pr("api", "test", &comment)
+            .await
+            .unwrap();
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 5);
+        assert!(requests[2].contains("page=2"), "{}", requests[2]);
+        assert!(requests[3].starts_with("GET /user "), "{}", requests[3]);
+        // The comment of the token's user on the second page is updated instead of posting another
+        assert!(
+            requests[4].starts_with("PATCH /repos/fix-it/api/issues/comments/2 "),
+            "{}",
+            requests[4]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_open_issue_pages() {
+        let (api_url, requests) = serve_responses(vec![
+            (
+                "link: <{url}/repos/fix-it/api/issues?state=open&page=2>; rel=\"next\"\r\n",
+                r#"[{ "title": "Other issue" }]"#,
+            ),
+            ("", r#"[{ "title": "Rotate the keys" }]"#),
+        ])
+        .await;
+        let provider = test_provider(&api_url);
+        let issue = Issue {
+            title: "Rotate the keys",
+            body: None,
+        };
+        // Already opened on the second page
+        assert!(!provider.open_issue("api", &issue).await.unwrap());
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].contains("page=2"), "{}", requests[1]);
+    }
+
+    #[tokio::test]
+    async fn test_set_pr_milestone_pages() {
+        let (api_url, requests) = serve_responses(vec![
+            ("", r#"[{ "number": 7 }]"#),
+            (
+                "link: <{url}/repos/fix-it/api/milestones?state=open&per_page=100&page=2>; rel=\"next\"\r\n",
+                r#"[{ "number": 1, "title": "Other" }]"#,
+            ),
+            ("", r#"[{ "number": 2, "title": "Cleanup" }]"#),
+            ("", "{}"),
+        ])
+        .await;
+        let provider = test_provider(&api_url);
+        provider
+            .set_pr_milestone("api", "test", &Milestone::Title("Cleanup".to_owned()))
+            .await
+            .unwrap();
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 4);
+        assert!(requests[2].contains("page=2"), "{}", requests[2]);
+        assert!(
+            requests[3].starts_with("PATCH /repos/fix-it/api/issues/7 "),
+            "{}",
+            requests[3]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_branch_names_are_encoded() {
+        let (api_url, requests) = serve_responses(vec![("", "[]"), ("", "")]).await;
+        let provider = test_provider(&api_url);
+        provider.branches("api", "fix/issue#").await.unwrap();
+        provider
+            .delete_branch("api", "fix/issue#12-100%")
+            .await
+            .unwrap();
+        let requests = requests.lock().unwrap();
+        assert!(
+            requests[0].starts_with(
+                "GET /repos/fix-it/api/git/matching-refs/heads/fix/issue%23?per_page=100 "
+            ),
+            "{}",
+            requests[0]
+        );
+        assert!(
+            requests[1]
+                .starts_with("DELETE /repos/fix-it/api/git/refs/heads/fix/issue%2312-100%25 "),
+            "{}",
+            requests[1]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_release_encodes_tag() {
+        let (api_url, requests) = serve_responses(vec![("", "{}")]).await;
+        let provider = test_provider(&api_url);
+        provider.create_release("api", "v1.0#rc1").await.unwrap();
+        let requests = requests.lock().unwrap();
+        assert!(
+            requests[0].starts_with("GET /repos/fix-it/api/releases/tags/v1.0%23rc1 "),
+            "{}",
+            requests[0]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_retries_server_errors() {
+        let provider = |api_url: String| GithubProvider {
+            max_attempts: 3,
+            ..test_provider(&api_url)
+        };
+        let delays = Mutex::new(vec![]);
+        let send = |provider: GithubProvider, method: Method| {
+            let delays = &delays;
+            async move {
+                let request = provider.request(method, &provider.api_url).unwrap();
+                let delay = |wait| {
+                    delays.lock().unwrap().push(wait);
+                    future::ready(())
+                };
+                provider.send_with_delay(request, delay).await.unwrap()
+            }
+        };
+
+        let response = send(provider(serve(&[502, 503, 200]).await), Method::GET).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(delays.lock().unwrap().len(), 2);
+
+        let response = send(provider(serve(&[500, 500, 500, 200]).await), Method::GET).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(delays.lock().unwrap().len(), 4);
+
+        // Client errors are not retried
+        let response = send(provider(serve(&[401, 200]).await), Method::GET).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // The pull request or comment could have been created already
+        let response = send(provider(serve(&[502, 200]).await), Method::POST).await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(delays.lock().unwrap().len(), 4);
+    }
+
     #[test]
     fn test_next_url() {
         let with_next = r#"</repos?type=private&per_page=100&page=2>; rel="next", </repos?type=private&per_page=100&page=1>; rel="first""#;
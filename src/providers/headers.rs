@@ -0,0 +1,87 @@
+use std::{collections::BTreeMap, convert::TryFrom, fmt};
+
+use color_eyre::{
+    eyre::{eyre, Context},
+    Report,
+};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::Deserialize;
+
+/// Parts of header names whose values are treated as secrets
+const SECRET_NAMES: [&str; 7] = [
+    "auth", "key", "token", "secret", "password", "cookie", "session",
+];
+
+/// Headers sent on every request to the provider, from `[provider.headers]`, like the API key of
+/// a gateway in front of it. Values that look like secrets are redacted from logs
+#[derive(Clone, Default, Deserialize)]
+#[serde(try_from = "BTreeMap<String, String>")]
+pub struct Headers(HeaderMap);
+
+impl Headers {
+    pub fn to_header_map(&self) -> HeaderMap {
+        self.0.clone()
+    }
+}
+
+impl TryFrom<BTreeMap<String, String>> for Headers {
+    type Error = Report;
+
+    fn try_from(headers: BTreeMap<String, String>) -> Result<Self, Self::Error> {
+        let mut output = HeaderMap::new();
+        for (name, value) in headers {
+            let header_name = HeaderName::try_from(name.as_str())
+                .wrap_err_with(|| format!("invalid header name {:?}", name))?;
+            let mut header_value = HeaderValue::try_from(value)
+                .map_err(|_| eyre!("invalid value for header {}", name))?;
+            header_value.set_sensitive(is_secret(&header_name));
+            output.insert(header_name, header_value);
+        }
+        Ok(Self(output))
+    }
+}
+
+impl fmt::Debug for Headers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.0.iter().map(|(name, value)| {
+                let value = match value.to_str() {
+                    Ok(text) if !value.is_sensitive() => text,
+                    _ => "<redacted>",
+                };
+                (name.as_str(), value)
+            }))
+            .finish()
+    }
+}
+
+/// Header names are always lowercase
+fn is_secret(name: &HeaderName) -> bool {
+    SECRET_NAMES.iter().any(|part| name.as_str().contains(part))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Headers;
+
+    #[test]
+    fn test_headers() {
+        let headers: Headers = toml::from_str(
+            r#"
+            X-Api-Key = "bebacafe"
+            X-Request-Source = "there-i-fixed-it"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            format!("{:?}", headers),
+            r#"{"x-api-key": "<redacted>", "x-request-source": "there-i-fixed-it"}"#
+        );
+        let header_map = headers.to_header_map();
+        assert_eq!(header_map["x-api-key"], "bebacafe");
+        assert!(header_map["x-api-key"].is_sensitive());
+
+        assert!(toml::from_str::<Headers>(r#""bad name" = "value""#).is_err());
+        assert!(toml::from_str::<Headers>(r#"name = "bad\nvalue""#).is_err());
+    }
+}
@@ -1,32 +1,136 @@
 mod constants;
 mod github;
+mod headers;
 #[cfg(test)]
 pub(crate) mod tests;
+mod token;
 
 use async_trait::async_trait;
+use camino::Utf8PathBuf;
 use color_eyre::{
     eyre::{eyre, Context},
     Help, Result, SectionExt,
 };
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
 use tokio::fs;
 
 use crate::{constants::CACHE_DIR, Repository};
 
 pub use self::github::GithubProvider;
 
+/// A pull request to be opened by a provider
+#[derive(Debug)]
+pub struct PullRequest<'a> {
+    pub base: &'a str,
+    pub head: &'a str,
+    pub title: &'a str,
+    pub body: Option<&'a str>,
+    pub milestone: Option<&'a Milestone>,
+    /// Opened as a draft where supported
+    pub draft: bool,
+    /// Users or `org/team` teams asked to review the pull request
+    pub reviewers: &'a [String],
+    /// Provider specific fields, sent along with the ones above where supported
+    pub extra: &'a Map<String, Value>,
+}
+
+/// An issue to be opened by a provider, describing a change that is not automated
+#[derive(Debug)]
+pub struct Issue<'a> {
+    pub title: &'a str,
+    pub body: Option<&'a str>,
+}
+
+/// A comment on a pull request that later runs update instead of posting another one
+#[derive(Debug)]
+pub struct PrComment<'a> {
+    /// Hidden text that finds the comment of previous runs, which `body` must contain
+    pub marker: &'a str,
+    pub body: &'a str,
+}
+
+/// A milestone referenced by its number or by its title, which can differ between repositories
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Milestone {
+    Number(u64),
+    Title(String),
+}
+
+/// State of the latest pull request of a branch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrState {
+    Open,
+    Closed,
+    Merged,
+}
+
+/// An open draft pull request
+#[derive(Debug)]
+pub struct DraftPr {
+    pub number: u64,
+    /// Commit whose checks decide if the pull request is ready
+    pub head_commit: String,
+    /// Provider specific id used to update the pull request
+    pub id: String,
+}
+
+/// Combined result of the checks of a commit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksState {
+    Pending,
+    /// Also when the commit has no checks
+    Success,
+    Failure,
+}
+
 #[async_trait]
 pub trait Provider: Sync + Send {
+    /// `branch_name` can also be `owner:branch`, for a branch of another fork
     async fn is_pr_open(&self, repository_name: &str, branch_name: &str) -> Result<bool>;
-    async fn open_pr(
+    /// `None` when the branch never had a pull request
+    async fn pr_state(&self, repository_name: &str, branch_name: &str) -> Result<Option<PrState>>;
+    async fn open_pr(&self, repository_name: &str, pull_request: &PullRequest<'_>) -> Result<()>;
+    /// Comments on the open pull request of the branch, updating our comment with the same marker
+    /// when there is one
+    async fn comment_on_pr(
+        &self,
+        repository_name: &str,
+        branch_name: &str,
+        comment: &PrComment<'_>,
+    ) -> Result<()>;
+    /// Sets the milestone of the branch's open pull request, which an earlier run may have opened
+    /// without it
+    async fn set_pr_milestone(
         &self,
         repository_name: &str,
-        base: &str,
-        head: &str,
-        title: &str,
-        body: Option<&str>,
+        branch_name: &str,
+        milestone: &Milestone,
     ) -> Result<()>;
     async fn list_repositories(&self, use_cache: bool) -> Result<Vec<Repository>>;
+    /// A single repository by its name, optionally prefixed by its owner like `owner/name`, `None`
+    /// when it is not on this provider
+    async fn get_repository(&self, name: &str) -> Result<Option<Repository>>;
+    /// Repositories matching a query on the provider's search syntax
+    async fn search_repositories(&self, query: &str) -> Result<Vec<Repository>>;
+    /// `None` when the branch has no open draft pull request
+    async fn draft_pr(&self, repository_name: &str, branch_name: &str) -> Result<Option<DraftPr>>;
+    async fn checks_state(&self, repository_name: &str, commit: &str) -> Result<ChecksState>;
+    async fn mark_ready(&self, repository_name: &str, pull_request: &DraftPr) -> Result<()>;
+    /// Does nothing when the release already exists
+    async fn create_release(&self, repository_name: &str, tag: &str) -> Result<()>;
+    /// All languages detected on the repository, not only the primary one
+    async fn languages(&self, repository_name: &str) -> Result<Vec<String>>;
+    /// `false` when an open issue already has the same title
+    async fn open_issue(&self, repository_name: &str, issue: &Issue<'_>) -> Result<bool>;
+    /// Who the credentials belong to, which is a cheap way to check that they are valid
+    async fn authenticated_user(&self) -> Result<String>;
+    /// Names of the repository's branches that start with `prefix`
+    async fn branches(&self, repository_name: &str, prefix: &str) -> Result<Vec<String>>;
+    async fn delete_branch(&self, repository_name: &str, branch_name: &str) -> Result<()>;
+    /// Where people can see the branch on the provider's website
+    fn branch_url(&self, repository_name: &str, branch_name: &str) -> String;
 }
 
 pub(crate) async fn check_api_errors(response: reqwest::Response) -> Result<reqwest::Response> {
@@ -34,14 +138,23 @@ pub(crate) async fn check_api_errors(response: reqwest::Response) -> Result<reqw
         Err(source) => match response.text().await {
             Ok(body) => Err(eyre!(source)
                 .with_section(move || body.trim().to_string().header("Body: ").to_string())),
-            Err(err) => {
-                return Err(eyre!(err));
-            }
+            Err(err) => Err(eyre!(err)),
         },
         _ => Ok(response),
     }
 }
 
+/// Bumped when the cached repositories gain fields, like `language`, so caches written before are
+/// not read as if the repositories had none
+const CACHE_VERSION: u32 = 2;
+
+fn cache_file(provider_name: &str, organization: &str) -> Utf8PathBuf {
+    CACHE_DIR.join(format!(
+        "repositories-v{}-{}-{}.json",
+        CACHE_VERSION, provider_name, organization
+    ))
+}
+
 pub(crate) async fn fetch_from_cache<T>(
     provider_name: &str,
     organization: &str,
@@ -49,10 +162,7 @@ pub(crate) async fn fetch_from_cache<T>(
 where
     T: DeserializeOwned,
 {
-    let file = CACHE_DIR.join(format!(
-        "{}-{}-{}.json",
-        "repositories", provider_name, organization
-    ));
+    let file = cache_file(provider_name, organization);
     if !file.exists() {
         return Ok(None);
     }
@@ -65,10 +175,7 @@ where
     T: Serialize,
 {
     let contents = serde_json::to_vec_pretty(&data)?;
-    let path = CACHE_DIR.join(format!(
-        "{}-{}-{}.json",
-        "repositories", provider_name, organization
-    ));
+    let path = cache_file(provider_name, organization);
     let project_folder = path
         .parent()
         .ok_or_else(|| eyre!("failed to determine project folder"))?;
@@ -5,7 +5,7 @@ use tracing::instrument;
 
 use crate::Repository;
 
-use super::Provider;
+use super::{ChecksState, DraftPr, Issue, Milestone, PrComment, PrState, Provider, PullRequest};
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct TestProvider;
@@ -18,14 +18,15 @@ impl Provider for TestProvider {
     }
 
     #[instrument(skip(self))]
-    async fn open_pr(
-        &self,
-        _repository_name: &str,
-        _base: &str,
-        _head: &str,
-        _title: &str,
-        _body: Option<&str>,
-    ) -> Result<()> {
+    async fn pr_state(&self, _repository_name: &str, branch_name: &str) -> Result<Option<PrState>> {
+        Ok(match branch_name {
+            "merged-branch" => Some(PrState::Merged),
+            _ => None,
+        })
+    }
+
+    #[instrument(skip(self))]
+    async fn open_pr(&self, _repository_name: &str, _pull_request: &PullRequest<'_>) -> Result<()> {
         Ok(())
     }
 
@@ -37,6 +38,103 @@ impl Provider for TestProvider {
             fork: false,
             ssh_url: "any-url".to_string(),
             default_branch: "main".to_string(),
+            language: Some("Rust".to_string()),
+            provider: 0,
+            detected_default_branch: Default::default(),
         }])
     }
+
+    #[instrument(skip(self))]
+    async fn get_repository(&self, name: &str) -> Result<Option<Repository>> {
+        let mut repository = self.list_repositories(false).await?.remove(0);
+        if name == "missing-repo" {
+            return Ok(None);
+        }
+        repository.name = name.rsplit('/').next().unwrap_or(name).to_string();
+        Ok(Some(repository))
+    }
+
+    #[instrument(skip(self))]
+    async fn search_repositories(&self, _query: &str) -> Result<Vec<Repository>> {
+        self.list_repositories(false).await
+    }
+
+    #[instrument(skip(self))]
+    async fn draft_pr(
+        &self,
+        _repository_name: &str,
+        _branch_name: &str,
+    ) -> Result<Option<DraftPr>> {
+        Ok(None)
+    }
+
+    #[instrument(skip(self))]
+    async fn checks_state(&self, _repository_name: &str, _commit: &str) -> Result<ChecksState> {
+        Ok(ChecksState::Success)
+    }
+
+    #[instrument(skip(self))]
+    async fn mark_ready(&self, _repository_name: &str, _pull_request: &DraftPr) -> Result<()> {
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn create_release(&self, _repository_name: &str, _tag: &str) -> Result<()> {
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn languages(&self, _repository_name: &str) -> Result<Vec<String>> {
+        Ok(vec!["Rust".to_string(), "Shell".to_string()])
+    }
+
+    #[instrument(skip(self))]
+    async fn comment_on_pr(
+        &self,
+        _repository_name: &str,
+        _branch_name: &str,
+        _comment: &PrComment<'_>,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_pr_milestone(
+        &self,
+        _repository_name: &str,
+        _branch_name: &str,
+        _milestone: &Milestone,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn open_issue(&self, _repository_name: &str, _issue: &Issue<'_>) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn authenticated_user(&self) -> Result<String> {
+        Ok("test-user".to_owned())
+    }
+
+    #[instrument(skip(self))]
+    async fn branches(&self, _repository_name: &str, prefix: &str) -> Result<Vec<String>> {
+        Ok(["main", "merged-branch", "merged-branch-2", "open-branch"]
+            .iter()
+            .filter(|branch| branch.starts_with(prefix))
+            .map(|branch| branch.to_string())
+            .collect())
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_branch(&self, _repository_name: &str, _branch_name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn branch_url(&self, repository_name: &str, branch_name: &str) -> String {
+        format!(
+            "https://example.com/{}/tree/{}",
+            repository_name, branch_name
+        )
+    }
 }
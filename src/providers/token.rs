@@ -0,0 +1,194 @@
+use std::fmt;
+
+use camino::Utf8PathBuf;
+use color_eyre::{
+    eyre::{eyre, Context},
+    Help, Result,
+};
+use serde::Deserialize;
+use tokio::{fs, process::Command};
+use tracing::debug;
+
+/// A value that never shows up in logs or error messages
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(pub(super) String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// Where a provider reads its token from, only one of them should be set
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct TokenSource {
+    token: Option<Secret>,
+    /// Environment variable with the token
+    token_env: Option<String>,
+    /// File with the token
+    token_file: Option<Utf8PathBuf>,
+    /// Shell command printing the token, like a credential helper
+    token_command: Option<String>,
+    /// Entry of the OS keyring with the token, needs the `keyring` feature
+    token_keyring: Option<KeyringEntry>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct KeyringEntry {
+    service: String,
+    account: String,
+}
+
+impl TokenSource {
+    pub async fn load(&self) -> Result<Secret> {
+        if let Some(token) = &self.token {
+            return Ok(token.clone());
+        }
+        if let Some(name) = &self.token_env {
+            let token = std::env::var(name)
+                .wrap_err_with(|| format!("failed to read token from ${}", name))?;
+            return Ok(Secret(token));
+        }
+        if let Some(path) = &self.token_file {
+            debug!("reading token from {}", path);
+            let contents = fs::read_to_string(path)
+                .await
+                .wrap_err_with(|| format!("failed to read token from {:?}", path))?;
+            return non_empty(contents);
+        }
+        if let Some(command) = &self.token_command {
+            debug!("reading token from command {:?}", command);
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .output()
+                .await
+                .wrap_err_with(|| format!("failed to run token command {:?}", command))?;
+            // Neither stdout nor stderr are shown, they could contain the token
+            if !output.status.success() {
+                return Err(eyre!(
+                    "token command {:?} failed with {}",
+                    command,
+                    output.status
+                ));
+            }
+            let stdout = String::from_utf8(output.stdout)
+                .wrap_err("token command printed an invalid token")?;
+            return non_empty(stdout);
+        }
+        if let Some(entry) = &self.token_keyring {
+            return entry.load();
+        }
+        Err(eyre!(
+            "provider requires token, token_env, token_file, token_command or token_keyring"
+        ))
+    }
+}
+
+impl KeyringEntry {
+    #[cfg(feature = "keyring")]
+    fn load(&self) -> Result<Secret> {
+        debug!("reading token from keyring");
+        let entry = keyring::Entry::new(&self.service, &self.account)
+            .wrap_err("failed to open keyring entry")?;
+        match entry.get_password() {
+            Ok(token) => non_empty(token),
+            Err(keyring::Error::NoEntry) => Err(eyre!(
+                "keyring has no token for service {:?} and account {:?}",
+                self.service,
+                self.account
+            )
+            .suggestion(format!(
+                "store it with your OS password manager or with `keyring set {} {}` from the \
+                Python keyring package",
+                self.service, self.account
+            ))),
+            Err(err) => Err(eyre!(err).wrap_err("failed to read token from keyring")),
+        }
+    }
+
+    #[cfg(not(feature = "keyring"))]
+    fn load(&self) -> Result<Secret> {
+        Err(eyre!(
+            "keyring support is disabled, cannot read the token of {:?} for {:?}",
+            self.service,
+            self.account
+        )
+        .suggestion("build there-i-fixed-it with `--features keyring` or use another token source"))
+    }
+}
+
+fn non_empty(contents: String) -> Result<Secret> {
+    let token = contents.trim();
+    if token.is_empty() {
+        return Err(eyre!("token is empty"));
+    }
+    Ok(Secret(token.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use camino::Utf8PathBuf;
+    use tempdir::TempDir;
+
+    #[cfg(not(feature = "keyring"))]
+    use super::KeyringEntry;
+    use super::{Secret, TokenSource};
+
+    #[tokio::test]
+    async fn test_load() {
+        let directory = TempDir::new("token").unwrap();
+        let path = Utf8PathBuf::from_path_buf(directory.path().join("token")).unwrap();
+        fs::write(&path, "from-file\n").unwrap();
+
+        let source = TokenSource {
+            token_file: Some(path),
+            ..TokenSource::default()
+        };
+        assert_eq!(source.load().await.unwrap().expose(), "from-file");
+
+        let source = TokenSource {
+            token_command: Some("echo from-command".to_string()),
+            ..TokenSource::default()
+        };
+        assert_eq!(source.load().await.unwrap().expose(), "from-command");
+
+        let source = TokenSource {
+            token_command: Some("false".to_string()),
+            ..TokenSource::default()
+        };
+        assert!(source.load().await.is_err());
+
+        assert!(TokenSource::default().load().await.is_err());
+    }
+
+    #[cfg(not(feature = "keyring"))]
+    #[tokio::test]
+    async fn test_keyring_disabled() {
+        let source = TokenSource {
+            token_keyring: Some(KeyringEntry {
+                service: "there-i-fixed-it".to_string(),
+                account: "user".to_string(),
+            }),
+            ..TokenSource::default()
+        };
+        let error = source.load().await.unwrap_err();
+        assert!(error.to_string().starts_with("keyring support is disabled"));
+    }
+
+    #[test]
+    fn test_secret_is_redacted() {
+        let secret = Secret("bebacafe".to_string());
+        assert_eq!(format!("{:?}", secret), "<redacted>");
+    }
+}
@@ -0,0 +1,662 @@
+use std::{collections::BTreeMap, fmt::Display, time::Duration};
+
+use camino::Utf8Path;
+use color_eyre::{
+    eyre::{eyre, Context},
+    Help, Result,
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::warn;
+
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    /// The plan of the run, so `--retry-from` can check that it is the same
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plan: Option<PlanIdentity>,
+    repositories: Vec<RepositoryReport>,
+    /// Repositories that the plan's filters left out, which were never processed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    skipped: Vec<SkippedRepository>,
+}
+
+/// What tells the plans of two runs apart
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanIdentity {
+    pub branch_name: String,
+    /// Changes whenever the plan, its variables or the global config defaults change
+    pub checksum: String,
+}
+
+/// The parts of a saved report that `--retry-from` needs
+#[derive(Debug, Deserialize)]
+pub struct SavedReport {
+    plan: Option<PlanIdentity>,
+    repositories: Vec<SavedRepository>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SavedRepository {
+    name: String,
+    status: Status,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SkippedRepository {
+    name: String,
+    reason: SkipReason,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RepositoryReport {
+    name: String,
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<SkipReason>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff_stat: Option<DiffStat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    skipped_files: Vec<SkippedFile>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    timings: Vec<PhaseTiming>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diagnostics: Option<Diagnostics>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pushed_branch: Option<PushedBranch>,
+}
+
+/// The git state of a repository that failed, from `--diagnose-failures`
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostics {
+    pub branch: String,
+    pub status: String,
+    /// The last commits of the checked out branch
+    pub log: String,
+}
+
+/// A file left untouched because processing it failed and the operation has `on_error = "skip"`
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedFile {
+    pub path: String,
+    pub error: String,
+}
+
+/// Changes of one of the plan's operations, or of its commands, that a dry run did not commit
+#[derive(Debug, Clone)]
+pub struct OperationDiff {
+    pub operation: String,
+    pub diff: String,
+}
+
+/// A step of processing a repository
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Clone,
+    Branch,
+    Process,
+    Commit,
+    Push,
+    PullRequest,
+    Issue,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseTiming {
+    phase: Phase,
+    seconds: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Unchanged,
+    Changed,
+    Skipped,
+    /// The repository has no commits
+    Empty,
+    Failed,
+    IssueOpened,
+}
+
+/// Why a repository was skipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// On the plan's `repository_exclude`
+    Excluded,
+    /// Not matching `repositories` or matching `deny_repositories`
+    NotAllowed,
+    /// None of its languages are on the plan's `languages`
+    Language,
+    /// The pull request of `require_merged_branch` is not merged
+    BranchNotMerged,
+    /// Another run is processing it
+    Locked,
+    /// Neither the plan nor the default branch changed since the last successful run
+    UpToDate,
+    /// It has no CI configuration and the plan has `require_ci`
+    NoCi,
+    /// Its changes were declined on the interactive prompt
+    Declined,
+    /// A dry run skips opening issues
+    DryRun,
+    /// The plan's `base_ref` does not exist on the repository
+    MissingBaseRef,
+    /// The run's `--max-runtime` was exceeded before it started
+    MaxRuntime,
+}
+
+/// What happened to a repository that was processed without errors
+#[derive(Debug)]
+pub struct Outcome {
+    pub status: Status,
+    pub skip_reason: Option<SkipReason>,
+    pub diff_stat: Option<DiffStat>,
+    /// Changes that a dry run did not commit
+    pub diff: Option<String>,
+    /// The same changes split by the operation that made them
+    pub operation_diffs: Vec<OperationDiff>,
+    /// Author and message of the commit that a dry run did not make
+    pub commit: Option<String>,
+    pub skipped_files: Vec<SkippedFile>,
+    /// The branch that was pushed without opening a pull request
+    pub pushed_branch: Option<PushedBranch>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PushedBranch {
+    pub name: String,
+    pub url: String,
+}
+
+/// Parsed output of `git diff --shortstat`
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffStat {
+    pub files_changed: u64,
+    pub insertions: u64,
+    pub deletions: u64,
+}
+
+impl Outcome {
+    pub fn unchanged() -> Self {
+        Self {
+            status: Status::Unchanged,
+            skip_reason: None,
+            diff_stat: None,
+            diff: None,
+            operation_diffs: vec![],
+            commit: None,
+            skipped_files: vec![],
+            pushed_branch: None,
+        }
+    }
+
+    pub fn skipped(reason: SkipReason) -> Self {
+        Self {
+            status: Status::Skipped,
+            skip_reason: Some(reason),
+            diff_stat: None,
+            diff: None,
+            operation_diffs: vec![],
+            commit: None,
+            skipped_files: vec![],
+            pushed_branch: None,
+        }
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            status: Status::Empty,
+            skip_reason: None,
+            diff_stat: None,
+            diff: None,
+            operation_diffs: vec![],
+            commit: None,
+            skipped_files: vec![],
+            pushed_branch: None,
+        }
+    }
+
+    pub fn issue_opened() -> Self {
+        Self {
+            status: Status::IssueOpened,
+            skip_reason: None,
+            diff_stat: None,
+            diff: None,
+            operation_diffs: vec![],
+            commit: None,
+            skipped_files: vec![],
+            pushed_branch: None,
+        }
+    }
+
+    pub fn changed(diff_stat: DiffStat) -> Self {
+        Self {
+            status: Status::Changed,
+            skip_reason: None,
+            diff_stat: Some(diff_stat),
+            diff: None,
+            operation_diffs: vec![],
+            commit: None,
+            skipped_files: vec![],
+            pushed_branch: None,
+        }
+    }
+
+    pub fn dry_run(diff_stat: DiffStat, diff: String, commit: String) -> Self {
+        Self {
+            status: Status::Changed,
+            skip_reason: None,
+            diff_stat: Some(diff_stat),
+            diff: Some(diff),
+            operation_diffs: vec![],
+            commit: Some(commit),
+            skipped_files: vec![],
+            pushed_branch: None,
+        }
+    }
+}
+
+impl PhaseTiming {
+    pub fn new(phase: Phase, elapsed: Duration) -> Self {
+        Self {
+            phase,
+            seconds: elapsed.as_secs_f64(),
+        }
+    }
+}
+
+impl Report {
+    pub fn for_plan(plan: PlanIdentity) -> Self {
+        Self {
+            plan: Some(plan),
+            ..Default::default()
+        }
+    }
+
+    pub fn add(&mut self, name: &str, result: &Result<Outcome>, timings: Vec<PhaseTiming>) {
+        let repository = match result {
+            Ok(outcome) => RepositoryReport {
+                name: name.to_owned(),
+                status: outcome.status,
+                reason: outcome.skip_reason,
+                diff_stat: outcome.diff_stat.clone(),
+                error: None,
+                skipped_files: outcome.skipped_files.clone(),
+                timings,
+                diagnostics: None,
+                pushed_branch: outcome.pushed_branch.clone(),
+            },
+            Err(err) => RepositoryReport {
+                name: name.to_owned(),
+                status: Status::Failed,
+                reason: None,
+                diff_stat: None,
+                error: Some(format!("{:#}", err)),
+                skipped_files: vec![],
+                timings,
+                diagnostics: None,
+                pushed_branch: None,
+            },
+        };
+        self.repositories.push(repository);
+    }
+
+    /// Attaches the git state to the repository added as `name`
+    pub fn add_diagnostics(&mut self, name: &str, diagnostics: Diagnostics) {
+        if let Some(repository) = self
+            .repositories
+            .iter_mut()
+            .find(|repository| repository.name == name)
+        {
+            repository.diagnostics = Some(diagnostics);
+        }
+    }
+
+    /// Adds a repository that was not processed
+    pub fn skip(&mut self, name: &str, reason: SkipReason) {
+        self.skipped.push(SkippedRepository {
+            name: name.to_owned(),
+            reason,
+        });
+    }
+
+    /// How many repositories were added
+    pub fn len(&self) -> usize {
+        self.repositories.len()
+    }
+
+    pub fn failures(&self) -> usize {
+        self.repositories
+            .iter()
+            .filter(|r| r.status == Status::Failed)
+            .count()
+    }
+
+    pub async fn save(&self, path: &Utf8Path) -> Result<()> {
+        let contents = serde_json::to_vec_pretty(self)?;
+        fs::write(path, &contents)
+            .await
+            .wrap_err_with(|| format!("failed to save report {}", path))?;
+        Ok(())
+    }
+}
+
+impl SavedReport {
+    pub async fn load(path: &Utf8Path) -> Result<Self> {
+        let contents = fs::read(path)
+            .await
+            .wrap_err_with(|| format!("failed to read report {}", path))?;
+        serde_json::from_slice(&contents).wrap_err_with(|| format!("invalid report {}", path))
+    }
+
+    /// Names of the repositories that failed, when the report is of the same plan. A plan that
+    /// changed since is fine, as long as it still makes the same branch
+    pub fn failed_repositories(&self, plan: &PlanIdentity) -> Result<Vec<String>> {
+        let saved_plan = self.plan.as_ref().ok_or_else(|| {
+            eyre!("the report does not say which plan it is of")
+                .suggestion("save a new report with --report")
+        })?;
+        if saved_plan.branch_name != plan.branch_name {
+            return Err(eyre!(
+                "the report is of the plan with branch {}, not {}",
+                saved_plan.branch_name,
+                plan.branch_name
+            ));
+        }
+        if saved_plan.checksum != plan.checksum {
+            warn!("the plan changed since the report was saved");
+        }
+        Ok(self
+            .repositories
+            .iter()
+            .filter(|repository| repository.status == Status::Failed)
+            .map(|repository| repository.name.clone())
+            .collect())
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for repository in &self.repositories {
+            write!(f, "{}: {}", repository.name, repository.status)?;
+            if let Some(reason) = &repository.reason {
+                write!(f, " ({})", reason)?;
+            }
+            if let Some(diff_stat) = &repository.diff_stat {
+                write!(f, " ({})", diff_stat)?;
+            }
+            if let Some(branch) = &repository.pushed_branch {
+                write!(f, " - pushed {}: {}", branch.name, branch.url)?;
+            }
+            if let Some(error) = &repository.error {
+                write!(f, " - {}", error)?;
+            }
+            writeln!(f)?;
+            for file in &repository.skipped_files {
+                writeln!(f, "  skipped {}: {}", file.path, file.error)?;
+            }
+            if let Some(diagnostics) = &repository.diagnostics {
+                writeln!(f, "  branch: {}", diagnostics.branch)?;
+                for (name, output) in [("status", &diagnostics.status), ("log", &diagnostics.log)] {
+                    writeln!(f, "  {}:", name)?;
+                    for line in output.lines() {
+                        writeln!(f, "    {}", line)?;
+                    }
+                }
+            }
+        }
+        if !self.skipped.is_empty() {
+            writeln!(f, "skipped:")?;
+            for repository in &self.skipped {
+                writeln!(f, "  {}: {}", repository.name, repository.reason)?;
+            }
+        }
+
+        let mut phases: BTreeMap<Phase, Vec<(&str, f64)>> = BTreeMap::new();
+        for repository in &self.repositories {
+            for timing in &repository.timings {
+                phases
+                    .entry(timing.phase)
+                    .or_default()
+                    .push((&repository.name, timing.seconds));
+            }
+        }
+        for (phase, timings) in phases {
+            let average = timings.iter().map(|(_, s)| s).sum::<f64>() / timings.len() as f64;
+            let (slowest, max) = timings
+                .iter()
+                .fold(("", 0.0), |acc, t| if t.1 > acc.1 { *t } else { acc });
+            writeln!(
+                f,
+                "{}: avg {:.1}s, max {:.1}s on {}",
+                phase, average, max, slowest
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let phase = match self {
+            Phase::Clone => "clone",
+            Phase::Branch => "branch",
+            Phase::Process => "process",
+            Phase::Commit => "commit",
+            Phase::Push => "push",
+            Phase::PullRequest => "pull request",
+            Phase::Issue => "issue",
+        };
+        f.write_str(phase)
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            Status::Unchanged => "unchanged",
+            Status::Changed => "changed",
+            Status::Skipped => "skipped",
+            Status::Empty => "repository is empty",
+            Status::Failed => "failed",
+            Status::IssueOpened => "issue opened",
+        };
+        f.write_str(status)
+    }
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            SkipReason::Excluded => "excluded",
+            SkipReason::NotAllowed => "not_allowed",
+            SkipReason::Language => "language",
+            SkipReason::BranchNotMerged => "branch_not_merged",
+            SkipReason::Locked => "locked",
+            SkipReason::UpToDate => "up_to_date",
+            SkipReason::NoCi => "no_ci",
+            SkipReason::Declined => "declined",
+            SkipReason::DryRun => "dry_run",
+            SkipReason::MissingBaseRef => "missing_base_ref",
+            SkipReason::MaxRuntime => "max_runtime",
+        };
+        f.write_str(reason)
+    }
+}
+
+impl DiffStat {
+    pub fn from_shortstat(output: &str) -> Self {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"(\d+) (file|insertion|deletion)").unwrap();
+        }
+        let mut diff_stat = DiffStat::default();
+        for capture in RE.captures_iter(output) {
+            let count = capture[1].parse().unwrap_or_default();
+            match &capture[2] {
+                "file" => diff_stat.files_changed = count,
+                "insertion" => diff_stat.insertions = count,
+                _ => diff_stat.deletions = count,
+            }
+        }
+        diff_stat
+    }
+}
+
+impl Display for DiffStat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} files changed, +{} -{}",
+            self.files_changed, self.insertions, self.deletions
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use color_eyre::eyre::eyre;
+
+    use super::{
+        Diagnostics, DiffStat, Outcome, Phase, PhaseTiming, PlanIdentity, PushedBranch, Report,
+        SavedReport, SkipReason, SkippedFile,
+    };
+
+    #[test]
+    fn test_summary() {
+        let mut report = Report::default();
+        report.add(
+            "fast",
+            &Ok(Outcome::unchanged()),
+            vec![PhaseTiming::new(Phase::Clone, Duration::from_secs(1))],
+        );
+        let mut outcome = Outcome::changed(DiffStat::default());
+        outcome.skipped_files.push(SkippedFile {
+            path: "broken.toml".to_owned(),
+            error: "invalid toml".to_owned(),
+        });
+        report.add("partial", &Ok(outcome), vec![]);
+        report.add("locked", &Ok(Outcome::skipped(SkipReason::Locked)), vec![]);
+        let mut outcome = Outcome::changed(DiffStat::default());
+        outcome.pushed_branch = Some(PushedBranch {
+            name: "bot/upgrade".to_owned(),
+            url: "https://github.com/org/pushed/tree/bot/upgrade".to_owned(),
+        });
+        report.add("pushed", &Ok(outcome), vec![]);
+        report.skip("legacy", SkipReason::Excluded);
+        report.add(
+            "slow",
+            &Err(eyre!("failed to push")),
+            vec![
+                PhaseTiming::new(Phase::Clone, Duration::from_secs(3)),
+                PhaseTiming::new(Phase::Push, Duration::from_millis(500)),
+            ],
+        );
+        assert_eq!(
+            report.to_string(),
+            "fast: unchanged\n\
+            partial: changed (0 files changed, +0 -0)\n  \
+            skipped broken.toml: invalid toml\n\
+            locked: skipped (locked)\n\
+            pushed: changed (0 files changed, +0 -0) - pushed bot/upgrade: \
+            https://github.com/org/pushed/tree/bot/upgrade\n\
+            slow: failed - failed to push\n\
+            skipped:\n  \
+            legacy: excluded\n\
+            clone: avg 2.0s, max 3.0s on slow\n\
+            push: avg 0.5s, max 0.5s on slow\n"
+        );
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut report = Report::default();
+        report.add("working", &Ok(Outcome::unchanged()), vec![]);
+        report.add("flaky", &Err(eyre!("failed to push")), vec![]);
+        report.add_diagnostics(
+            "flaky",
+            Diagnostics {
+                branch: "bot/upgrade".to_owned(),
+                status: "On branch bot/upgrade\nnothing to commit".to_owned(),
+                log: "abc123 chore: Upgrade".to_owned(),
+            },
+        );
+        assert_eq!(
+            report.to_string(),
+            "working: unchanged\n\
+            flaky: failed - failed to push\n  \
+            branch: bot/upgrade\n  \
+            status:\n    \
+            On branch bot/upgrade\n    \
+            nothing to commit\n  \
+            log:\n    \
+            abc123 chore: Upgrade\n"
+        );
+        let json = serde_json::to_value(&report).unwrap();
+        assert!(json["repositories"][0].get("diagnostics").is_none());
+        assert_eq!(
+            json["repositories"][1]["diagnostics"]["branch"],
+            "bot/upgrade"
+        );
+    }
+
+    #[test]
+    fn test_failed_repositories() {
+        let plan = PlanIdentity {
+            branch_name: "bot/upgrade".to_owned(),
+            checksum: "abc".to_owned(),
+        };
+        let mut report = Report::for_plan(plan.clone());
+        report.add("working", &Ok(Outcome::unchanged()), vec![]);
+        report.add("flaky", &Err(eyre!("failed to push")), vec![]);
+        let saved: SavedReport =
+            serde_json::from_slice(&serde_json::to_vec(&report).unwrap()).unwrap();
+
+        assert_eq!(saved.failed_repositories(&plan).unwrap(), vec!["flaky"]);
+        let changed_plan = PlanIdentity {
+            checksum: "def".to_owned(),
+            ..plan.clone()
+        };
+        assert_eq!(
+            saved.failed_repositories(&changed_plan).unwrap(),
+            vec!["flaky"]
+        );
+        let other_plan = PlanIdentity {
+            branch_name: "bot/other".to_owned(),
+            ..plan.clone()
+        };
+        assert!(saved.failed_repositories(&other_plan).is_err());
+
+        let saved: SavedReport =
+            serde_json::from_slice(&serde_json::to_vec(&Report::default()).unwrap()).unwrap();
+        assert!(saved.failed_repositories(&plan).is_err());
+    }
+
+    #[test]
+    fn test_diff_stat_from_shortstat() {
+        assert_eq!(
+            DiffStat::from_shortstat(" 3 files changed, 10 insertions(+), 2 deletions(-)\n"),
+            DiffStat {
+                files_changed: 3,
+                insertions: 10,
+                deletions: 2
+            }
+        );
+        assert_eq!(
+            DiffStat::from_shortstat(" 1 file changed, 1 deletion(-)\n"),
+            DiffStat {
+                files_changed: 1,
+                insertions: 0,
+                deletions: 1
+            }
+        );
+        assert_eq!(DiffStat::from_shortstat(""), DiffStat::default());
+    }
+}